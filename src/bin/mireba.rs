@@ -2,18 +2,28 @@ extern crate clap;
 use clap::{App, Arg, SubCommand};
 use gfx::{
   accelerator::naive::Naive,
+  camera::polarizer::{Polarizer, MOSAIC_ANGLES},
   integrator::direct::Direct,
-  scene::{RawScene, Scene},
+  scene::{file, gltf, mitsuba, pbrt, RawScene, Scene},
 };
 use std::{
+  collections::HashMap,
+  fmt::Display,
   fs::File,
-  io::{BufReader, BufWriter},
+  io::{BufWriter, Write},
+  process::exit,
 };
 
 // TODO need to setup this so it can be switched out at compile time
 /// This is the accelerator used by the render
 pub type Accelerator = Naive;
 
+/// Reports an error which stops the render
+fn fail(e: impl Display) -> ! {
+  eprintln!("error: {}", e);
+  exit(1)
+}
+
 pub fn main() {
   let matches = App::new("Mireba(見れば)")
     .version("0.1")
@@ -24,8 +34,13 @@ pub fn main() {
         .short("i")
         .long("input")
         .value_name("FILE")
-        .help("Input scene file (if example is specified this is where it will be stored")
-        .required(true)
+        .help(
+          "Input scene file, either JSON, TOML, glTF, Mitsuba XML or PBRT (if example is \
+           specified this is where it will be stored)",
+        )
+        // global so that it may also follow a subcommand, which is why it is checked below
+        // rather than being required
+        .global(true)
         .takes_value(true),
     )
     .arg(
@@ -37,30 +52,159 @@ pub fn main() {
         .required(false)
         .takes_value(true),
     )
+    .arg(
+      Arg::with_name("set")
+        .long("set")
+        .value_name("PATH=VALUE")
+        .help(
+          "Overrides a field of the scene by its dot separated path, such as \
+           camera.film_builder.size=[256,256] or variables.spp=64, where VALUE is JSON or a string",
+        )
+        .multiple(true)
+        .number_of_values(1)
+        .takes_value(true),
+    )
     .subcommand(SubCommand::with_name("example").about("Creates an empty scene file"))
+    .subcommand(
+      SubCommand::with_name("mitsuba")
+        .about("Renders a Mitsuba 2 XML scene")
+        .arg(
+          Arg::with_name("define")
+            .short("D")
+            .long("define")
+            .value_name("NAME=VALUE")
+            .help("Overrides a $-parameter of the scene")
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+        ),
+    )
+    .subcommand(SubCommand::with_name("pbrt").about("Renders a PBRT-v3 scene"))
+    .subcommand(SubCommand::with_name("check").about(
+      "Validates a scene and prints its statistics without rendering, detecting the format by \
+       its extension",
+    ))
     .get_matches();
 
-  let input_file = matches.value_of("input").unwrap();
+  let input_file = matches
+    .value_of("input")
+    .unwrap_or_else(|| fail("an input scene file must be given with --input"));
 
   if let Some(_sub) = matches.subcommand_matches("example") {
     // Creates an example scene
     let empty_scene = RawScene::example();
-    let f = File::create(input_file).expect("Could not create input file");
-    let f = BufWriter::new(f);
-    serde_json::to_writer_pretty(f, &empty_scene).expect("Failed to write example file");
+    let f = File::create(input_file).unwrap_or_else(|e| fail(format!("{}: {}", input_file, e)));
+    let mut f = BufWriter::new(f);
+    if input_file.ends_with(".toml") {
+      toml::Value::try_from(&empty_scene)
+        .and_then(|v| toml::to_string_pretty(&v))
+        .map_err(|e| e.to_string())
+        .and_then(|s| f.write_all(s.as_bytes()).map_err(|e| e.to_string()))
+    } else {
+      serde_json::to_writer_pretty(f, &empty_scene).map_err(|e| e.to_string())
+    }
+    .unwrap_or_else(|e| fail(format!("{}: {}", input_file, e)));
     return;
   }
-  let f = File::open(input_file).expect("Could not find input file");
-  let f = BufReader::new(f);
-
   let output_file = matches.value_of("output").unwrap_or("out.jpg");
-  let raw_scene: RawScene = serde_json::from_reader(f).expect("Error while reading json");
-  let scene: Scene<(), Accelerator> = raw_scene.build();
+  let check = matches.subcommand_matches("check").is_some();
+  let is_gltf = input_file.ends_with(".gltf") || input_file.ends_with(".glb");
+  let overrides = matches
+    .values_of("set")
+    .into_iter()
+    .flatten()
+    .map(|s| {
+      file::parse_override(s)
+        .unwrap_or_else(|| fail(format!("--set {:?} must be of the form PATH=VALUE", s)))
+    })
+    .collect::<Vec<_>>();
+  // scenes from other formats are imported before their fields are overridden
+  let imported = if let Some(sub) = matches.subcommand_matches("mitsuba") {
+    let mut params = HashMap::new();
+    for d in sub.values_of("define").into_iter().flatten() {
+      let eq = d
+        .find('=')
+        .unwrap_or_else(|| fail(format!("define {:?} must be of the form NAME=VALUE", d)));
+      params.insert(d[..eq].to_string(), d[eq + 1..].to_string());
+    }
+    Some(mitsuba::from_mitsuba(input_file, &params))
+  } else if matches.subcommand_matches("pbrt").is_some() || (check && input_file.ends_with(".pbrt"))
+  {
+    Some(pbrt::from_pbrt(input_file))
+  } else if check && input_file.ends_with(".xml") {
+    Some(mitsuba::from_mitsuba(input_file, &HashMap::new()))
+  } else if is_gltf {
+    Some(gltf::from_gltf(input_file))
+  } else {
+    None
+  };
+  let raw_scene = match imported {
+    Some(scene) => scene.and_then(|s| s.set(&overrides)),
+    None => RawScene::from_file_with(input_file, &overrides),
+  }
+  .unwrap_or_else(|e| fail(e));
+  if check {
+    let report = raw_scene.check();
+    for lint in &report.lints {
+      eprintln!("{}", lint);
+    }
+    println!("{}", report.stats);
+    if report.has_errors() {
+      exit(1);
+    }
+    return;
+  }
+  let scene: Scene<(), Accelerator> = raw_scene.build().unwrap_or_else(|e| fail(e));
   scene.render(Direct {});
   scene
     .camera
     .film()
     .to_image()
     .save(output_file)
-    .expect("Failed to save file");
+    .unwrap_or_else(|e| fail(format!("{}: {}", output_file, e)));
+  if let Some(Polarizer::DivisionOfFocalPlane) = scene.camera.polarizer() {
+    save_mosaic(&scene, output_file);
+  }
+  #[cfg(feature = "polarized")]
+  save_polarized(&scene, output_file);
+}
+
+/// Appends a suffix to the file name of a path, keeping the extension
+fn with_suffix(output_file: &str, suffix: &str) -> std::path::PathBuf {
+  let out = std::path::Path::new(output_file);
+  let stem = out.file_stem().unwrap().to_string_lossy();
+  let ext = out
+    .extension()
+    .map_or(String::from("png"), |e| e.to_string_lossy().into_owned());
+  out.with_file_name(format!("{}_{}.{}", stem, suffix, ext))
+}
+
+/// Saves one image per polarizer angle of a division of focal plane camera
+fn save_mosaic(scene: &Scene<(), Accelerator>, output_file: &str) {
+  let channels = scene.camera.film().split_mosaic();
+  for (angle, img) in MOSAIC_ANGLES.iter().zip(channels.iter()) {
+    img
+      .save(with_suffix(output_file, &format!("pol{}", angle)))
+      .unwrap_or_else(|e| fail(format!("Failed to save polarizer channel: {}", e)));
+  }
+}
+
+/// Saves each stokes component and polarization visualisations next to the output file
+#[cfg(feature = "polarized")]
+fn save_polarized(scene: &Scene<(), Accelerator>, output_file: &str) {
+  let with_suffix = |suffix: &str| with_suffix(output_file, suffix);
+  let film = scene.camera.film();
+  for (i, img) in film.stokes_images().iter().enumerate() {
+    img
+      .save(with_suffix(&format!("s{}", i)))
+      .unwrap_or_else(|e| fail(format!("Failed to save stokes component: {}", e)));
+  }
+  film
+    .dop_image()
+    .save(with_suffix("dop"))
+    .unwrap_or_else(|e| fail(format!("Failed to save degree of polarization: {}", e)));
+  film
+    .aop_image()
+    .save(with_suffix("aop"))
+    .unwrap_or_else(|e| fail(format!("Failed to save angle of polarization: {}", e)));
 }
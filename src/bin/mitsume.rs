@@ -2,10 +2,18 @@ extern crate clap;
 use clap::{App, Arg, SubCommand};
 use gfx::two_d::scene::{RawScene, Scene};
 use std::{
+  fmt::Display,
   fs::File,
   io::{BufReader, BufWriter},
+  process::exit,
 };
 
+/// Reports an error which stops the render
+fn fail(e: impl Display) -> ! {
+  eprintln!("error: {}", e);
+  exit(1)
+}
+
 pub fn main() {
   let matches = App::new("Mitsume(見つめ)")
     .version("0.1")
@@ -37,22 +45,24 @@ pub fn main() {
   if let Some(_sub) = matches.subcommand_matches("example") {
     // Creates an example scene
     let empty_scene = RawScene::example();
-    let f = File::create(input_file).expect("Could not create input file");
+    let f = File::create(input_file).unwrap_or_else(|e| fail(format!("{}: {}", input_file, e)));
     let f = BufWriter::new(f);
-    serde_json::to_writer_pretty(f, &empty_scene).expect("Failed to write example file");
+    serde_json::to_writer_pretty(f, &empty_scene)
+      .unwrap_or_else(|e| fail(format!("{}: {}", input_file, e)));
     return;
   }
-  let f = File::open(input_file).expect("Could not find input file");
+  let f = File::open(input_file).unwrap_or_else(|e| fail(format!("{}: {}", input_file, e)));
   let f = BufReader::new(f);
 
   let output_file = matches.value_of("output").unwrap_or("out2d.jpg");
-  let raw_scene: RawScene = serde_json::from_reader(f).expect("Error while reading json");
+  let raw_scene: RawScene =
+    serde_json::from_reader(f).unwrap_or_else(|e| fail(format!("{}: {}", input_file, e)));
   let mut scene: Scene = raw_scene.into();
   scene.render();
   scene
     .film
     .to_image()
     .save(output_file)
-    .expect("Failed to save file");
+    .unwrap_or_else(|e| fail(format!("{}: {}", output_file, e)));
   // TODO extract film here
 }
@@ -1,4 +1,4 @@
-use quick_maths::{Float, Ray3, Vec3, Vector};
+use quick_maths::{Float, Ray3, Transform4, Vec3, Vector};
 use std::fmt::Debug;
 
 /// Returns whether two intervals overlap
@@ -84,6 +84,20 @@ impl Bounds3 {
     let Vector([hx, hy, hz]) = self.max;
     (hx - lx) * (hy - ly) * (hz - lz)
   }
+  /// Returns the bounding box of this box after it has been transformed
+  pub fn transform(&self, t: &Transform4) -> Self {
+    let Vector([lx, ly, lz]) = self.min;
+    let Vector([hx, hy, hz]) = self.max;
+    let corner = |i: u8| {
+      let pick = |bit: u8, l: f32, h: f32| if i & bit == 0 { l } else { h };
+      t.apply_point(&Vec3::new(
+        pick(4, lx, hx),
+        pick(2, ly, hy),
+        pick(1, lz, hz),
+      ))
+    };
+    (1..8).fold(Self::empty(corner(0)), |acc, i| acc.union_vec(&corner(i)))
+  }
   /// Returns whether this bounding box intersects this ray.
   /// Can possibly intersect backwards.
   pub fn intersect_ray(&self, r: &Ray3) -> bool {
@@ -100,6 +114,25 @@ impl Bounds3 {
 
     t_max >= t_min.max(0.0)
   }
+  /// Returns the range of parameters along the ray which are inside this box, clipped to
+  /// begin at 0.
+  pub fn ray_range(&self, r: &Ray3) -> Option<(f32, f32)> {
+    let mut t_min = 0f32;
+    let mut t_max = f32::INFINITY;
+    for i in 0..3 {
+      let (t0, t1) = (
+        (self.min[i] - r.pos[i]) / r.dir[i],
+        (self.max[i] - r.pos[i]) / r.dir[i],
+      );
+      if t0.is_nan() || t1.is_nan() {
+        // Parallel to this axis and starting on one of its planes
+        continue;
+      }
+      t_min = t_min.max(t0.min(t1));
+      t_max = t_max.min(t0.max(t1));
+    }
+    Some((t_min, t_max)).filter(|(t_min, t_max)| t_min <= t_max)
+  }
   /// Computes the distance from a ray to the box and also the normal to the box
   pub fn intersects_ray_params(&self, r: &Ray3) -> Option<(f32, Vec3)> {
     let Vector([lx, ly, lz]) = self.min;
@@ -1,28 +1,108 @@
-use super::BSDFImpl;
-use crate::spectrum::Spectrum;
+use super::{AlphaMode, BSDFImpl};
+use crate::{scene::SceneError, spectrum::Spectrum, texture::builder::Builder as TextureBuilder};
+use std::path::Path;
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub enum Builder {
-  Diffuse(Spectrum),
+  Diffuse(#[serde(deserialize_with = "crate::polarized::deserialize_spectrum")] Spectrum),
+  /// First material of an MTL file. OBJs can use all of their materials with `use_mtls`.
   MTL(String),
   Debug,
+  /// Material given inline instead of in an MTL file, whose illumination model chooses which
+  /// of its diffuse, highlight and mirror lobes are used.
+  InlineMTL {
+    illum: u8,
+    #[serde(deserialize_with = "crate::polarized::deserialize_spectrum")]
+    diffuse: Spectrum,
+    #[serde(deserialize_with = "crate::polarized::deserialize_spectrum")]
+    specular: Spectrum,
+    shininess: f32,
+  },
+  /// Glossy dielectric with an index of refraction and a lobe exponent
+  Dielectric {
+    eta: f32,
+    exponent: f32,
+  },
+  /// Glossy conductor with a complex index of refraction and a lobe exponent
+  Conductor {
+    eta: f32,
+    k: f32,
+    exponent: f32,
+  },
+  /// Metallic-roughness material, with an optional texture multiplying the base color. Its
+  /// alpha, multiplied by the texture's, is ignored unless the alpha mode is set.
+  Principled {
+    #[serde(deserialize_with = "crate::polarized::deserialize_spectrum")]
+    base_color: Spectrum,
+    base_color_texture: Option<TextureBuilder>,
+    metallic: f32,
+    roughness: f32,
+    alpha: Option<f32>,
+    #[serde(default)]
+    alpha_mode: AlphaMode,
+  },
 }
 
-impl From<Builder> for BSDFImpl {
-  fn from(b: Builder) -> Self {
+impl Builder {
+  /// Files this bsdf is loaded from, including textures
+  pub fn files(&self) -> Vec<&str> {
+    match self {
+      Builder::MTL(src) => vec![src.as_str()],
+      Builder::Principled {
+        base_color_texture: Some(t),
+        ..
+      } => vec![t.file()],
+      _ => vec![],
+    }
+  }
+  /// Builds this bsdf, loading any files it refers to
+  pub fn build(self) -> Result<BSDFImpl, SceneError> {
     use Builder::*;
-    match b {
+    Ok(match self {
       Debug => BSDFImpl::Debug(super::debug::Debug),
       Diffuse(s) => BSDFImpl::Diffuse(super::diffuse::Diffuse::new(s)),
       MTL(src) => {
+        let p = Path::new(&src);
         let mut mtls = vec![];
-        let f = std::fs::File::open(src).expect("Failed to open MTL file");
-        super::mtl::read_mtl(f, &mut mtls).expect("Failed to read MTL file");
-        if mtls.len() > 1 {
-          println!("Currently can only handle 1 material per MTL file but multiple specified");
+        let f = std::fs::File::open(p).map_err(|e| SceneError::io(p, e))?;
+        super::mtl::read_mtl(f, p, &mut mtls)?;
+        if mtls.is_empty() {
+          return Err(SceneError::parse(p, None, "MTL file has no materials"));
         }
-        BSDFImpl::MTL(mtls.remove(0))
+        let mut mtl = mtls.remove(0);
+        mtl.load_textures(p.parent().unwrap_or_else(|| Path::new("")))?;
+        BSDFImpl::MTL(mtl)
       },
-    }
+      InlineMTL {
+        illum,
+        diffuse,
+        specular,
+        shininess,
+      } => BSDFImpl::MTL(
+        super::mtl::MTL::empty()
+          .illum(illum)
+          .diffuse(diffuse)
+          .specular(specular)
+          .shininess(shininess),
+      ),
+      Dielectric { eta, exponent } =>
+        BSDFImpl::Fresnel(super::fresnel::Fresnel::dielectric(eta, exponent)),
+      Conductor { eta, k, exponent } =>
+        BSDFImpl::Fresnel(super::fresnel::Fresnel::conductor(eta, k, exponent)),
+      Principled {
+        base_color,
+        base_color_texture,
+        metallic,
+        roughness,
+        alpha,
+        alpha_mode,
+      } => {
+        let texture = base_color_texture.map(TextureBuilder::build).transpose()?;
+        BSDFImpl::Principled(
+          super::principled::Principled::new(base_color, texture, metallic, roughness)
+            .with_alpha(alpha.unwrap_or(1.), alpha_mode),
+        )
+      },
+    })
   }
 }
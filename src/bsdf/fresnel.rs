@@ -0,0 +1,184 @@
+use super::BSDF;
+use crate::{
+  interaction::SurfaceInteraction,
+  polarized::{
+    mueller::{self, Mueller},
+    stokes::stokes_basis,
+  },
+  spectrum::{self, Spectrum},
+};
+use num::Complex;
+use quick_maths::Vec3;
+
+/// Glossy surface whose reflection and transmission are weighted by the polarized fresnel
+/// equations. Dielectrics have k = 0 and transmit, conductors have k > 0 and only reflect.
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct Fresnel {
+  /// Real component of the index of refraction of the inside of this surface
+  eta: f32,
+  /// Absorption coefficient of the surface, 0 for dielectrics
+  k: f32,
+  /// Blinn-Phong exponent of the microfacet lobe, larger is closer to a perfect mirror
+  exponent: f32,
+}
+
+impl Fresnel {
+  pub fn dielectric(eta: f32, exponent: f32) -> Self {
+    Self {
+      eta,
+      k: 0.0,
+      exponent,
+    }
+  }
+  pub fn conductor(eta: f32, k: f32, exponent: f32) -> Self { Self { eta, k, exponent } }
+  pub fn is_conductor(&self) -> bool { self.k > 0.0 }
+  /// Normalized Blinn-Phong distribution of microfacet normals
+  fn distribution(&self, cos_h: f32) -> f32 {
+    (self.exponent + 2.0) * std::f32::consts::FRAC_1_PI * 0.5 * cos_h.abs().powf(self.exponent)
+  }
+  /// Mueller matrix for light arriving from the direction `wo` and leaving towards the viewer,
+  /// with stokes vectors in the canonical basis of their direction of travel.
+  pub fn mueller(&self, si: &SurfaceInteraction, wo: Vec3) -> Mueller {
+    let n = si.normal;
+    let view = -si.wi;
+    let (cos_o, cos_v) = (n.dot(&wo), n.dot(&view));
+    let reflect = cos_o * cos_v > 0.0;
+    let (h, m, jacobian) = if reflect {
+      let h = (wo + view).norm();
+      let eta = if cos_o > 0.0 {
+        Complex::new(self.eta, self.k)
+      } else {
+        Complex::new(self.eta.recip(), 0.0)
+      };
+      (h, mueller::specular_reflection(wo.dot(&h), eta), 0.25)
+    } else if self.is_conductor() {
+      return mueller::depolarizer(0.0);
+    } else {
+      // Generalized half vector for refraction
+      let (eta_i, eta_o) = if cos_o > 0.0 {
+        (1.0, self.eta)
+      } else {
+        (self.eta, 1.0)
+      };
+      let h = -(wo * eta_i + view * eta_o).norm();
+      // Change of measure from the half vector to wo, weighted by the cosine of the view
+      let denom = eta_i * wo.dot(&h) + eta_o * view.dot(&h);
+      let jacobian = eta_i * eta_i * wo.dot(&h).abs() * view.dot(&h).abs() / (denom * denom);
+      let m = mueller::specular_transmission(wo.dot(&h), eta_o / eta_i);
+      (h, m, jacobian)
+    };
+    let scale = self.distribution(n.dot(&h)) * jacobian * cos_o.abs();
+    // Fresnel matrices are defined w.r.t. the plane of incidence on the microfacet
+    let (d_in, d_out) = (-wo, view);
+    let m = mueller::change_basis(
+      &m,
+      (&d_in, &mueller::s_axis(&h, &d_in), &stokes_basis(&d_in)),
+      (&d_out, &mueller::s_axis(&h, &d_out), &stokes_basis(&d_out)),
+    );
+    m * scale
+  }
+}
+
+impl BSDF for Fresnel {
+  fn eval(&self, si: &SurfaceInteraction, wo: Vec3) -> Spectrum {
+    spectrum::from_mono(self.mueller(si, wo).0[0][0])
+  }
+  #[cfg(feature = "polarized")]
+  fn eval_mueller(&self, si: &SurfaceInteraction, wo: Vec3) -> Mueller { self.mueller(si, wo) }
+}
+
+#[cfg(test)]
+mod test_fresnel {
+  use super::*;
+  use crate::{
+    interaction::Interaction,
+    polarized::stokes::{degree_of_polarization, unpolarized},
+  };
+  use quick_maths::Vec2;
+  use std::f32::consts::PI;
+
+  fn viewed_from(view: Vec3) -> SurfaceInteraction {
+    SurfaceInteraction {
+      it: Interaction::new(),
+      normal: Vec3::new(0., 0., 1.),
+      uv: Vec2::of(0.),
+      wi: -view,
+      material: 0,
+    }
+  }
+
+  /// Integrates the reflected and transmitted intensity of light arriving from every direction
+  fn albedo(bsdf: &Fresnel, si: &SurfaceInteraction) -> f32 {
+    const N: usize = 200;
+    let (d_theta, d_phi) = (PI / N as f32, 2. * PI / N as f32);
+    let mut total = 0.;
+    for i in 0..N {
+      let theta = (i as f32 + 0.5) * d_theta;
+      for j in 0..N {
+        let phi = (j as f32 + 0.5) * d_phi;
+        let wo = Vec3::new(
+          theta.sin() * phi.cos(),
+          theta.sin() * phi.sin(),
+          theta.cos(),
+        );
+        total += bsdf.mueller(si, wo).0[0][0] * theta.sin() * d_theta * d_phi;
+      }
+    }
+    total
+  }
+
+  #[test]
+  fn energy_is_conserved() {
+    let conductor = Fresnel::conductor(0.2, 3.0, 200.);
+    for &bsdf in &[
+      &Fresnel::dielectric(1.5, 20.),
+      &Fresnel::conductor(0.2, 3.0, 20.),
+      &conductor,
+    ] {
+      for &theta in &[0.0f32, 0.5, 1.0, 1.4] {
+        let si = viewed_from(Vec3::new(theta.sin(), 0., theta.cos()));
+        let albedo = albedo(bsdf, &si);
+        assert!(
+          (0. ..=1.).contains(&albedo),
+          "{:?} at {} reflects {}",
+          bsdf,
+          theta,
+          albedo
+        );
+      }
+    }
+    // a narrow lobe viewed head on reflects about as much as a mirror
+    let (r_s, _) = mueller::fresnel_amplitudes(1., Complex::new(0.2, 3.0));
+    let albedo = albedo(&conductor, &viewed_from(Vec3::new(0., 0., 1.)));
+    assert!(
+      (albedo - r_s.norm_sqr()).abs() < 0.05,
+      "{} {}",
+      albedo,
+      r_s.norm_sqr()
+    );
+  }
+
+  #[test]
+  fn white_furnace() {
+    // fresnel reflection and transmission sum to 1, so a narrow lobe viewed head on neither
+    // gains nor loses energy
+    let bsdf = Fresnel::dielectric(1.5, 100.);
+    let albedo = albedo(&bsdf, &viewed_from(Vec3::new(0., 0., 1.)));
+    assert!((albedo - 1.).abs() < 0.02, "{}", albedo);
+  }
+
+  #[test]
+  fn brewster_angle_reflects_only_s() {
+    let eta = 1.5f32;
+    let brewster = eta.atan();
+    let (_, r_p) = mueller::fresnel_amplitudes(brewster.cos(), Complex::new(eta, 0.));
+    assert!(r_p.norm() < 1e-4, "{}", r_p);
+
+    let bsdf = Fresnel::dielectric(eta, 1000.);
+    let si = viewed_from(Vec3::new(brewster.sin(), 0., brewster.cos()));
+    let mirrored = Vec3::new(-brewster.sin(), 0., brewster.cos());
+    let reflected = mueller::apply(&bsdf.mueller(&si, mirrored), &unpolarized());
+    assert!(reflected[0] > 0.);
+    assert!((degree_of_polarization(&reflected) - 1.0).abs() < 1e-3);
+  }
+}
@@ -2,22 +2,46 @@ pub mod builder;
 pub use builder::Builder;
 pub mod debug;
 pub mod diffuse;
+pub mod fresnel;
 pub mod mtl;
 pub mod phong;
+pub mod principled;
 
-use crate::{interaction::SurfaceInteraction, sampler::Samplers, spectrum::Spectrum};
-use quick_maths::{Vec3, Zero};
-use std::fmt::Debug;
+#[cfg(feature = "polarized")]
+use crate::polarized::mueller::{self, Mueller};
+use crate::{interaction::SurfaceInteraction, spectrum::Spectrum};
+use quick_maths::{Vec2, Vec3, Zero};
+use std::{f32::consts::PI, fmt::Debug};
 
 /// Trait representing a BSDF
 pub trait BSDF: Debug {
-  // fn sample(&self, si: &SurfaceInteraction, wo: Vec3, dir_sample: Vec2) -> (Sample, Spectrum);
   /// Evaluate this bsdf at the surface interaction in the outgoing direction
   fn eval(&self, si: &SurfaceInteraction, wo: Vec3) -> Spectrum;
-  /// Select some direction and a probability of going in that direction.
-  /// Default implementation uniformly samples along hemisphere
-  /// add pdf in some outgoing direction.
-  fn sample(&self, _s: Samplers) -> (Sample, Spectrum) { todo!() }
+  /// Selects an outgoing direction from two uniform samples, or None if the light is absorbed.
+  /// Default implementation samples the hemisphere the light arrived from by cosine.
+  fn sample(&self, si: &SurfaceInteraction, u: Vec2) -> Option<Sample> {
+    sample_cosine(self, si, u)
+  }
+  /// Perfectly specular directions light leaves in, which evaluating the bsdf never returns.
+  /// Each has a pdf of 1. Default implementation has none.
+  fn specular(&self, _si: &SurfaceInteraction) -> Vec<Sample> { vec![] }
+  /// How much of the surface is covered at the surface interaction, where rays pass through
+  /// surfaces with an alpha of 0. Default implementation is opaque.
+  fn alpha(&self, _si: &SurfaceInteraction) -> f32 { 1. }
+  /// Evaluate this bsdf as a mueller matrix, where the incoming stokes vector is expressed in
+  /// the basis of light travelling along -wo and the outgoing one along -si.wi.
+  /// Default implementation depolarizes.
+  #[cfg(feature = "polarized")]
+  fn eval_mueller(&self, si: &SurfaceInteraction, wo: Vec3) -> Mueller {
+    mueller::depolarizer(self.eval(si, wo)[0])
+  }
+  /// Mueller matrix of one of the specular directions, where the incoming stokes vector is
+  /// expressed in the basis of light travelling along -s.wo and the outgoing one along -si.wi.
+  /// Default implementation depolarizes.
+  #[cfg(feature = "polarized")]
+  fn specular_mueller(&self, _si: &SurfaceInteraction, s: &Sample) -> Mueller {
+    mueller::depolarizer(s.weight.luminance())
+  }
 }
 
 /// Different implementations of BSDFs
@@ -26,6 +50,8 @@ pub enum BSDFImpl {
   Diffuse(diffuse::Diffuse),
   Debug(debug::Debug),
   MTL(mtl::MTL),
+  Fresnel(fresnel::Fresnel),
+  Principled(principled::Principled),
 }
 
 impl BSDFImpl {
@@ -36,6 +62,60 @@ impl BSDFImpl {
       Diffuse(d) => d.eval(si, wo),
       Debug(d) => d.eval(si, wo),
       MTL(mtl) => mtl.eval(si, wo),
+      Fresnel(f) => f.eval(si, wo),
+      Principled(p) => p.eval(si, wo),
+    }
+  }
+  pub fn sample(&self, si: &SurfaceInteraction, u: Vec2) -> Option<Sample> {
+    use BSDFImpl::*;
+    match self {
+      Diffuse(d) => d.sample(si, u),
+      Debug(d) => d.sample(si, u),
+      MTL(mtl) => mtl.sample(si, u),
+      Fresnel(f) => f.sample(si, u),
+      Principled(p) => p.sample(si, u),
+    }
+  }
+  pub fn specular(&self, si: &SurfaceInteraction) -> Vec<Sample> {
+    use BSDFImpl::*;
+    match self {
+      Diffuse(d) => d.specular(si),
+      Debug(d) => d.specular(si),
+      MTL(mtl) => mtl.specular(si),
+      Fresnel(f) => f.specular(si),
+      Principled(p) => p.specular(si),
+    }
+  }
+  pub fn alpha(&self, si: &SurfaceInteraction) -> f32 {
+    use BSDFImpl::*;
+    match self {
+      Diffuse(d) => d.alpha(si),
+      Debug(d) => d.alpha(si),
+      MTL(mtl) => mtl.alpha(si),
+      Fresnel(f) => f.alpha(si),
+      Principled(p) => p.alpha(si),
+    }
+  }
+  #[cfg(feature = "polarized")]
+  pub fn eval_mueller(&self, si: &SurfaceInteraction, wo: Vec3) -> Mueller {
+    use BSDFImpl::*;
+    match self {
+      Diffuse(d) => d.eval_mueller(si, wo),
+      Debug(d) => d.eval_mueller(si, wo),
+      MTL(mtl) => mtl.eval_mueller(si, wo),
+      Fresnel(f) => f.eval_mueller(si, wo),
+      Principled(p) => p.eval_mueller(si, wo),
+    }
+  }
+  #[cfg(feature = "polarized")]
+  pub fn specular_mueller(&self, si: &SurfaceInteraction, s: &Sample) -> Mueller {
+    use BSDFImpl::*;
+    match self {
+      Diffuse(d) => d.specular_mueller(si, s),
+      Debug(d) => d.specular_mueller(si, s),
+      MTL(mtl) => mtl.specular_mueller(si, s),
+      Fresnel(f) => f.specular_mueller(si, s),
+      Principled(p) => p.specular_mueller(si, s),
     }
   }
 
@@ -43,11 +123,83 @@ impl BSDFImpl {
   pub fn ambient(&self) -> Spectrum { Spectrum::zero() }
 }
 
+/// How the alpha of a material decides whether rays hit it
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum AlphaMode {
+  /// Alpha is ignored
+  Opaque,
+  /// Surfaces are hit where alpha is at least the cutoff
+  Mask(f32),
+  /// Surfaces are hit with a probability of their alpha
+  Blend,
+}
+
+impl Default for AlphaMode {
+  fn default() -> Self { AlphaMode::Opaque }
+}
+
+impl AlphaMode {
+  /// Applies this mode to an alpha value
+  pub fn apply(self, alpha: f32) -> f32 {
+    match self {
+      AlphaMode::Opaque => 1.,
+      AlphaMode::Mask(cutoff) if alpha >= cutoff => 1.,
+      AlphaMode::Mask(_) => 0.,
+      AlphaMode::Blend => alpha,
+    }
+  }
+}
+
 #[derive(Debug)]
 pub struct Sample {
-  wo: Vec3,
-  // Probability of sampling
-  pdf: f32,
-  // relative index of refraction
-  eta: f32,
+  /// Direction light leaves the surface in
+  pub wo: Vec3,
+  /// Probability of sampling this direction, which is the chance of choosing it if specular
+  pub pdf: f32,
+  /// Relative index of refraction across the surface, which is 1 for reflection
+  pub eta: f32,
+  /// Bsdf in this direction divided by the pdf
+  pub weight: Spectrum,
+  /// Whether this direction came from a delta distribution
+  pub specular: bool,
+}
+
+/// Returns a direction around the normal sampled by cosine from two uniform samples, and its pdf
+pub fn cosine_hemisphere(n: &Vec3, u: Vec2) -> (Vec3, f32) {
+  let a = if n.x().abs() > 0.9 {
+    Vec3::new(0., 1., 0.)
+  } else {
+    Vec3::new(1., 0., 0.)
+  };
+  let t = a.cross(n).norm();
+  let b = n.cross(&t);
+  let r = u.x().sqrt();
+  let phi = 2. * PI * u.y();
+  let z = (1. - u.x()).max(0.).sqrt();
+  let wo = t * (r * phi.cos()) + b * (r * phi.sin()) + *n * z;
+  (wo, z / PI)
+}
+
+/// Samples the hemisphere on the side of the surface the light arrived from by cosine
+pub fn sample_cosine<B: BSDF + ?Sized>(
+  bsdf: &B,
+  si: &SurfaceInteraction,
+  u: Vec2,
+) -> Option<Sample> {
+  let n = if si.normal.dot(&si.wi) > 0. {
+    -si.normal
+  } else {
+    si.normal
+  };
+  let (wo, pdf) = cosine_hemisphere(&n, u);
+  if pdf <= 0. {
+    return None;
+  }
+  Some(Sample {
+    wo,
+    pdf,
+    eta: 1.,
+    weight: bsdf.eval(si, wo) / pdf,
+    specular: false,
+  })
 }
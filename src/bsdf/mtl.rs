@@ -1,14 +1,25 @@
 /// Handling parsing mtl files
-use super::BSDF;
+use super::{sample_cosine, Sample, BSDF};
+#[cfg(feature = "polarized")]
+use crate::polarized::{
+  mueller::{self, Mueller},
+  stokes::stokes_basis,
+};
 use crate::{
   interaction::SurfaceInteraction,
-  spectrum::{from_rgb, Spectrum},
+  scene::SceneError,
+  spectrum::{from_rgb, Spectral, Spectrum},
+  texture::{bitmap::Bitmap, Texture},
 };
+#[cfg(feature = "polarized")]
+use num::Complex;
 use num::Num;
-use quick_maths::{One, Vec3, Zero};
+use quick_maths::{One, Vec2, Vec3, Zero};
 use std::{
+  f32::consts::FRAC_1_PI,
   io::{self, BufRead, Read},
   mem::replace,
+  path::Path,
 };
 
 /// Directly loaded mtl file
@@ -26,6 +37,9 @@ pub struct MTL {
   k_diffuse: Spectrum,
   k_specular: Spectrum,
   k_emission: Spectrum,
+  /// Opacity texture as written in the file, relative to it
+  pub map_d: Option<String>,
+  alpha_map: Option<Bitmap>,
 }
 
 impl MTL {
@@ -33,62 +47,227 @@ impl MTL {
     Self {
       name: Default::default(),
       n_s: 0.0,
-      n_i: 0.0,
-      d: 0.0,
+      n_i: 1.0,
+      d: 1.0,
       t_r: 0.0,
-      t_f: Vec3::zero(),
+      t_f: Vec3::one(),
       illum: 0,
-      k_ambient: Vec3::zero(),
-      k_diffuse: Vec3::zero(),
-      k_specular: Vec3::zero(),
-      k_emission: Vec3::zero(),
+      k_ambient: Spectrum::zero(),
+      k_diffuse: Spectrum::zero(),
+      k_specular: Spectrum::zero(),
+      k_emission: Spectrum::zero(),
+      map_d: None,
+      alpha_map: None,
     }
   }
   // Builder for MTL
-  pub fn ambient(self, k_ambient: Vec3) -> Self { Self { k_ambient, ..self } }
-  pub fn diffuse(self, k_diffuse: Vec3) -> Self { Self { k_diffuse, ..self } }
-  pub fn specular(self, k_specular: Vec3) -> Self { Self { k_specular, ..self } }
+  pub fn ambient(self, k_ambient: Spectrum) -> Self { Self { k_ambient, ..self } }
+  pub fn diffuse(self, k_diffuse: Spectrum) -> Self { Self { k_diffuse, ..self } }
+  pub fn specular(self, k_specular: Spectrum) -> Self { Self { k_specular, ..self } }
+  pub fn shininess(self, n_s: f32) -> Self { Self { n_s, ..self } }
+  pub fn illum(self, illum: u8) -> Self { Self { illum, ..self } }
+  pub fn ior(self, n_i: f32) -> Self { Self { n_i, ..self } }
+  pub fn dissolve(self, d: f32) -> Self { Self { d, ..self } }
+  /// How opaque this material is, from dissolve and its complement transparency
+  pub fn opacity(&self) -> f32 { self.d.min(1. - self.t_r).max(0.).min(1.) }
+  /// Loads the textures of this material, whose paths are relative to some directory
+  pub fn load_textures(&mut self, dir: &Path) -> Result<(), SceneError> {
+    if let Some(map_d) = &self.map_d {
+      let p = dir.join(map_d);
+      self.alpha_map = Some(Bitmap::from_file(&p).map_err(|e| SceneError::image(p, e))?);
+    }
+    Ok(())
+  }
+  fn lobes(&self) -> Lobes {
+    // http://paulbourke.net/dataformats/mtl/
+    let (glossy, mirror, refract, fresnel) = match self.illum {
+      0 | 1 => (false, false, false, false),
+      3 | 8 => (true, true, false, false),
+      4 | 6 | 9 => (true, true, true, false),
+      5 => (true, true, false, true),
+      7 => (true, true, true, true),
+      // 2 and models with shadow effects are a diffuse and highlight
+      _ => (true, false, false, false),
+    };
+    Lobes {
+      glossy,
+      mirror,
+      refract,
+      fresnel,
+    }
+  }
+}
+
+/// Lobes enabled by an illumination model, besides the diffuse lobe which is always present
+#[derive(Debug, Clone, Copy)]
+struct Lobes {
+  /// Blinn-Phong highlight of Ks with exponent Ns
+  glossy: bool,
+  /// Mirror reflection of Ks
+  mirror: bool,
+  /// Refraction through the surface filtered by Tf with index Ni
+  refract: bool,
+  /// Whether the mirror and refraction are weighted by fresnel instead of Ks and Tf
+  fresnel: bool,
 }
 
-macro_rules! quint {
-  ($v: expr) => {{
-    let a = $v * $v;
-    a * a * $v
-  }};
+/// Schlick's approximation of dielectric fresnel reflectance
+fn schlick(cos_theta: f32, eta: f32) -> f32 {
+  let r0 = ((eta - 1.) / (eta + 1.)).powi(2);
+  r0 + (1. - r0) * (1. - cos_theta.max(0.)).powi(5)
 }
 
-fn schlick(cos_theta: f32, n_s: f32) -> Spectrum {
-  (Spectrum::one() - n_s) * quint!(1.0 - cos_theta) + n_s
+/// Refracts the direction towards the viewer through a surface with relative index eta, or
+/// returns None on total internal reflection.
+fn refract(view: Vec3, n: Vec3, eta: f32) -> Option<Vec3> {
+  let cos_i = n.dot(&view);
+  let sin2_t = (1. - cos_i * cos_i).max(0.) / (eta * eta);
+  if sin2_t >= 1. {
+    return None;
+  }
+  let cos_t = (1. - sin2_t).sqrt();
+  Some(-view / eta + n * (cos_i / eta - cos_t))
 }
 
 impl BSDF for MTL {
   fn eval(&self, si: &SurfaceInteraction, wo: Vec3) -> Spectrum {
-    // http://paulbourke.net/dataformats/mtl/
-    // These are best guess approximations because these are not light vectors but outgoing
-    // direction vectors.
-    match self.illum {
-      0 => self.k_diffuse,
-      1 => self.k_diffuse * si.normal.dot(&wo),
-      // This always includes a term for recursive ray tracing.
-      2 | 3 | 4 =>
-        self.k_diffuse * si.normal.dot(&wo) + self.k_ambient * (wo.dot(&si.wi.reflect(&si.normal))),
-      5 => {
-        let bisector = si.wi.reflect(&si.normal);
-        self.k_diffuse * si.normal.dot(&wo)
-          + self.k_ambient * wo.dot(&bisector) * schlick(wo.dot(&bisector), self.n_s)
-          + schlick(si.normal.dot(&si.wi), self.n_s)
-      },
-      _ => todo!(),
+    let n = si.normal;
+    let view = -si.wi;
+    let (cos_o, cos_v) = (n.dot(&wo), n.dot(&view));
+    if cos_o <= 0. || cos_v <= 0. {
+      return Spectrum::zero();
+    }
+    let mut f = self.k_diffuse * (FRAC_1_PI * cos_o);
+    if self.lobes().glossy {
+      let h = (wo + view).norm();
+      // normalized Blinn-Phong
+      let d = (self.n_s + 8.) * FRAC_1_PI * 0.125 * n.dot(&h).max(0.).powf(self.n_s);
+      f += self.k_specular * (d * cos_o);
     }
+    f
+  }
+  fn specular(&self, si: &SurfaceInteraction) -> Vec<Sample> {
+    let mut out = vec![];
+    let mut push = |wo, eta, weight: Spectrum| {
+      if weight.luminance() > 0. {
+        out.push(Sample {
+          wo,
+          pdf: 1.,
+          eta,
+          weight,
+          specular: true,
+        });
+      }
+    };
+    let lobes = self.lobes();
+    if !lobes.mirror {
+      return out;
+    }
+    let view = -si.wi;
+    // the side of the surface light arrives from and the relative index across it
+    let (n, eta) = if si.normal.dot(&view) >= 0. {
+      (si.normal, self.n_i)
+    } else {
+      (-si.normal, self.n_i.recip())
+    };
+    let cos_v = n.dot(&view);
+    let reflected = n * (2. * cos_v) - view;
+    let f = schlick(cos_v, eta);
+    let refracted = if lobes.refract {
+      refract(view, n, eta)
+    } else {
+      None
+    };
+    let (r, t) = match (refracted.is_some(), lobes.fresnel) {
+      (true, true) => (Spectrum::from_mono(f), from_rgb(self.t_f) * (1. - f)),
+      (true, false) => (self.k_specular, from_rgb(self.t_f)),
+      // All light is reflected on total internal reflection
+      (false, _) if lobes.refract => (Spectrum::from_mono(1.), Spectrum::zero()),
+      (false, true) => (Spectrum::from_mono(f), Spectrum::zero()),
+      (false, false) => (self.k_specular, Spectrum::zero()),
+    };
+    push(reflected, 1., r);
+    if let Some(wt) = refracted {
+      push(wt, eta, t);
+    }
+    out
+  }
+  fn alpha(&self, si: &SurfaceInteraction) -> f32 {
+    // Opacity maps are usually grayscale, so their brightness is used without an alpha channel
+    let map = self.alpha_map.as_ref().map_or(1., |m| {
+      m.alpha(si.uv)
+        .unwrap_or_else(|| m.sample(si.uv).luminance())
+    });
+    self.opacity() * map
+  }
+  /// Polarizes mirror reflection and refraction by the fresnel equations for Ni, keeping the
+  /// intensity of the lobe.
+  #[cfg(feature = "polarized")]
+  fn specular_mueller(&self, si: &SurfaceInteraction, s: &Sample) -> Mueller {
+    let view = -si.wi;
+    let (n, eta) = if si.normal.dot(&view) >= 0. {
+      (si.normal, self.n_i)
+    } else {
+      (-si.normal, self.n_i.recip())
+    };
+    let intensity = s.weight.luminance();
+    let m = if n.dot(&s.wo) > 0. {
+      mueller::specular_reflection(n.dot(&view), Complex::new(eta, 0.))
+    } else {
+      // light crosses from the far side towards the viewer
+      mueller::specular_transmission(n.dot(&s.wo), eta.recip())
+    };
+    if m.0[0][0] <= 0. {
+      return mueller::depolarizer(intensity);
+    }
+    let scale = intensity / m.0[0][0];
+    // Fresnel matrices are defined w.r.t. the plane of incidence
+    let (d_in, d_out) = (-s.wo, view);
+    let m = mueller::change_basis(
+      &m,
+      (&d_in, &mueller::s_axis(&n, &d_in), &stokes_basis(&d_in)),
+      (&d_out, &mueller::s_axis(&n, &d_out), &stokes_basis(&d_out)),
+    );
+    m * scale
+  }
+  fn sample(&self, si: &SurfaceInteraction, u: Vec2) -> Option<Sample> {
+    let specular = self.specular(si);
+    let weights = specular
+      .iter()
+      .map(|s| s.weight.luminance())
+      .collect::<Vec<_>>();
+    let glossy = (self.k_diffuse + self.k_specular).luminance();
+    let total = glossy + weights.iter().sum::<f32>();
+    if total <= 0. {
+      return None;
+    }
+    // choose a lobe by how much light it carries, then reuse the sample within it
+    let mut x = u.x() * total;
+    for (mut s, w) in specular.into_iter().zip(weights) {
+      if x < w {
+        s.pdf = w / total;
+        s.weight = s.weight / s.pdf;
+        return Some(s);
+      }
+      x -= w;
+    }
+    let p = glossy / total;
+    let mut s = sample_cosine(self, si, Vec2::new((x / glossy).min(1.), u.y()))?;
+    s.pdf *= p;
+    s.weight = s.weight / p;
+    Some(s)
   }
 }
 
-/// Reads an mtl file from src, and panicks if the read failes
-pub fn read_mtl(src: impl Read, out: &mut Vec<MTL>) -> io::Result<()> {
+/// Reads an mtl file from src, where path is where it was read from for reporting errors
+pub fn read_mtl(src: impl Read, path: &Path, out: &mut Vec<MTL>) -> Result<(), SceneError> {
   let buf = io::BufReader::new(src);
   let mut curr = MTL::empty();
-  for line in buf.lines() {
-    let line = line?;
+  for (i, line) in buf.lines().enumerate() {
+    let line = line.map_err(|e| SceneError::io(path, e))?;
+    let invalid = || SceneError::parse(path, Some(i + 1), format!("Invalid values {:?}", line));
+    let float = |v: &str| f32::from_str_radix(v, 10).map_err(|_| invalid());
+    let rgb = |x, y, z| Vec3::from_str_radix([x, y, z], 10).map_err(|_| invalid());
     let parts = line
       .splitn(2, '#')
       .next()
@@ -104,35 +283,94 @@ pub fn read_mtl(src: impl Read, out: &mut Vec<MTL>) -> io::Result<()> {
           out.push(replace(&mut curr, MTL::empty()));
           curr.name = (*name).to_string();
         },
-      // TODO convert below into errors
-      ["Ns", ns] => curr.n_s = f32::from_str_radix(ns, 10).unwrap(),
-      ["Ni", ni] => curr.n_i = f32::from_str_radix(ni, 10).unwrap(),
-      ["d", d] => curr.d = f32::from_str_radix(d, 10).unwrap(),
-      ["Tr", tr] => curr.t_r = f32::from_str_radix(tr, 10).unwrap(),
-      ["Tf", x, y, z] => curr.t_f = Vec3::from_str_radix([x, y, z], 10).unwrap(),
-      ["illum", il] => curr.illum = il.parse().unwrap(),
-      ["Ka", x, y, z] => curr.k_ambient = from_rgb(Vec3::from_str_radix([x, y, z], 10).unwrap()),
-      ["Kd", x, y, z] => curr.k_diffuse = from_rgb(Vec3::from_str_radix([x, y, z], 10).unwrap()),
-      ["Ks", x, y, z] => curr.k_specular = from_rgb(Vec3::from_str_radix([x, y, z], 10).unwrap()),
-      ["Ke", x, y, z] => curr.k_emission = from_rgb(Vec3::from_str_radix([x, y, z], 10).unwrap()),
-      // TODO implement the below
-      ["map_Ka", _src] => todo!(),
-      ["map_Kd", _src] => todo!(),
-      ["map_bump", _src] | ["bump", _src] => todo!(),
-      unknown => panic!("Unknown mtl file command {:?}", unknown),
+      ["Ns", ns] => curr.n_s = float(ns)?,
+      ["Ni", ni] => curr.n_i = float(ni)?,
+      ["d", d] => curr.d = float(d)?,
+      ["Tr", tr] => curr.t_r = float(tr)?,
+      ["Tf", x, y, z] => curr.t_f = rgb(x, y, z)?,
+      ["illum", il] => curr.illum = il.parse().map_err(|_| invalid())?,
+      ["Ka", x, y, z] => curr.k_ambient = from_rgb(rgb(x, y, z)?),
+      ["Kd", x, y, z] => curr.k_diffuse = from_rgb(rgb(x, y, z)?),
+      ["Ks", x, y, z] => curr.k_specular = from_rgb(rgb(x, y, z)?),
+      ["Ke", x, y, z] => curr.k_emission = from_rgb(rgb(x, y, z)?),
+      // TODO implement texture maps, which are skipped for now
+      // options come before the file name
+      ["map_d", .., file] => curr.map_d = Some((*file).to_string()),
+      [map, ..] if map.starts_with("map_") || *map == "bump" || *map == "disp" => (),
+      [cmd, ..] => {
+        let context = format!("mtl command {}", cmd);
+        return Err(SceneError::unsupported(path, Some(i + 1), context));
+      },
     }
   }
+  if !curr.name.is_empty() {
+    out.push(curr);
+  }
   Ok(())
 }
 
 #[test]
 fn test_mtl_load() {
-  use std::{fs::File, path::Path};
+  use std::fs::File;
   let p = Path::new(file!())
     .parent()
     .unwrap()
-    .join("sample_files")
+    .parent()
+    .unwrap()
+    .parent()
+    .unwrap()
+    .join("data")
     .join("sponza.mtl");
-  let r = File::open(p).unwrap();
-  assert!(read_mtl::<_, f32>(r).is_ok());
+  let r = File::open(&p).unwrap();
+  let mut mtls = vec![];
+  assert!(read_mtl(r, &p, &mut mtls).is_ok());
+  assert_eq!(mtls.len(), 20);
+  let err = read_mtl(
+    "newmtl a\nKd 1 x 0\n".as_bytes(),
+    Path::new("a.mtl"),
+    &mut vec![],
+  );
+  assert_eq!(
+    err.unwrap_err().to_string(),
+    "a.mtl:2: Invalid values \"Kd 1 x 0\""
+  );
+}
+
+#[test]
+fn test_mtl_lobes() {
+  use crate::interaction::Interaction;
+  let si = SurfaceInteraction {
+    it: Interaction::new(),
+    normal: Vec3::new(0., 0., 1.),
+    uv: Vec2::of(0.),
+    wi: Vec3::new(0., 0., -1.),
+    material: 0,
+  };
+  // Fresnel glass at normal incidence reflects 4% and transmits the rest straight through
+  let glass = MTL::empty().illum(7).ior(1.5).specular(Spectrum::one());
+  let lobes = glass.specular(&si);
+  assert_eq!(lobes.len(), 2);
+  assert!((lobes[0].weight.luminance() - 0.04).abs() < 1e-4);
+  assert!((lobes[1].wo - si.wi).magn() < 1e-4);
+  assert!((lobes[1].eta - 1.5).abs() < 1e-6);
+  // Dissolved diffuse surfaces are partially covered and have no specular lobes
+  let dissolved = MTL::empty()
+    .illum(1)
+    .diffuse(Spectrum::one())
+    .dissolve(0.25);
+  assert!(dissolved.specular(&si).is_empty());
+  assert!((dissolved.alpha(&si) - 0.25).abs() < 1e-6);
+  let s = dissolved.sample(&si, Vec2::new(0.9, 0.3)).unwrap();
+  assert!(!s.specular && s.wo.z() > 0.);
+  // The diffuse lobe does not depend on the direction it is viewed from
+  let wo = Vec3::new(0., 0., 1.);
+  let grazing = SurfaceInteraction {
+    it: Interaction::new(),
+    normal: si.normal,
+    uv: si.uv,
+    wi: Vec3::new(0.8, 0., -0.6),
+    material: 0,
+  };
+  let (head_on, grazing) = (dissolved.eval(&si, wo), dissolved.eval(&grazing, wo));
+  assert!((head_on.luminance() - grazing.luminance()).abs() < 1e-6);
 }
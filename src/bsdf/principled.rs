@@ -0,0 +1,113 @@
+use super::{AlphaMode, BSDF};
+use crate::{
+  interaction::SurfaceInteraction,
+  spectrum::{Spectral, Spectrum},
+  texture::{bitmap::Bitmap, Texture},
+};
+use quick_maths::{Vec3, Zero};
+use std::f32::consts::FRAC_1_PI;
+
+/// Metallic-roughness material as used by glTF, a diffuse lobe for dielectrics and a glossy lobe
+/// tinted by the base color for metals.
+#[derive(Debug)]
+pub struct Principled {
+  base_color: Spectrum,
+  /// Multiplied by the base color if present
+  base_color_texture: Option<Bitmap>,
+  metallic: f32,
+  roughness: f32,
+  /// Coverage of the surface, multiplied by the texture's alpha if it has one
+  alpha: f32,
+  alpha_mode: AlphaMode,
+}
+
+impl Principled {
+  pub fn new(
+    base_color: Spectrum,
+    base_color_texture: Option<Bitmap>,
+    metallic: f32,
+    roughness: f32,
+  ) -> Self {
+    Self {
+      base_color,
+      base_color_texture,
+      metallic: metallic.max(0.).min(1.),
+      roughness: roughness.max(0.).min(1.),
+      alpha: 1.,
+      alpha_mode: AlphaMode::Opaque,
+    }
+  }
+  pub fn with_alpha(self, alpha: f32, alpha_mode: AlphaMode) -> Self {
+    Self {
+      alpha,
+      alpha_mode,
+      ..self
+    }
+  }
+  fn base_color(&self, si: &SurfaceInteraction) -> Spectrum {
+    match &self.base_color_texture {
+      None => self.base_color,
+      Some(t) => t.sample(si.uv) * self.base_color,
+    }
+  }
+}
+
+impl BSDF for Principled {
+  fn eval(&self, si: &SurfaceInteraction, wo: Vec3) -> Spectrum {
+    let n = si.normal;
+    let view = -si.wi;
+    let (cos_o, cos_v) = (n.dot(&wo), n.dot(&view));
+    if cos_o <= 0. || cos_v <= 0. {
+      return Spectrum::zero();
+    }
+    let base = self.base_color(si);
+    let diffuse = base * ((1. - self.metallic) * FRAC_1_PI * cos_o);
+    // Blinn-Phong lobe with an exponent matching the width of the GGX lobe for this roughness
+    let h = (wo + view).norm();
+    let alpha = (self.roughness * self.roughness).max(1e-3);
+    let exponent = 2. / (alpha * alpha) - 2.;
+    let d = (exponent + 2.) * FRAC_1_PI * 0.5 * n.dot(&h).max(0.).powf(exponent);
+    // Dielectrics reflect 4% at normal incidence, and metals reflect their base color
+    let f0 = Spectrum::from_mono(0.04 * (1. - self.metallic)) + base * self.metallic;
+    let f = f0 + (f0 * -1. + 1.) * (1. - wo.dot(&h).max(0.)).powi(5);
+    diffuse + f * (d * 0.25 * cos_o)
+  }
+  fn alpha(&self, si: &SurfaceInteraction) -> f32 {
+    let texture = self
+      .base_color_texture
+      .as_ref()
+      .and_then(|t| t.alpha(si.uv));
+    self.alpha_mode.apply(self.alpha * texture.unwrap_or(1.))
+  }
+}
+
+#[test]
+fn test_principled_rough_dielectric() {
+  use super::diffuse::Diffuse;
+  use crate::interaction::Interaction;
+  use quick_maths::Vec2;
+  let viewed_from = |view: Vec3| SurfaceInteraction {
+    it: Interaction::new(),
+    normal: Vec3::new(0., 0., 1.),
+    uv: Vec2::of(0.),
+    wi: -view,
+    material: 0,
+  };
+  let color = Spectrum::from_mono(0.5);
+  let principled = Principled::new(color, None, 0., 1.);
+  let diffuse = Diffuse::new(color);
+  let (head_on, grazing) = (
+    viewed_from(Vec3::new(0., 0., 1.)),
+    viewed_from(Vec3::new(0.8, 0., 0.6)),
+  );
+  for &theta in &[0.0f32, 0.5, 1.0] {
+    let wo = Vec3::new(-theta.sin(), 0., theta.cos());
+    // Diffuse is weighted by the cosine of the view, which is 1 head on
+    let expected = diffuse.eval(&head_on, wo).luminance();
+    // only the 4% specular reflection of dielectrics is added, from any view
+    for si in &[&head_on, &grazing] {
+      let v = principled.eval(si, wo).luminance();
+      assert!((v - expected).abs() < 0.05 * expected, "{} {}", v, expected);
+    }
+  }
+}
@@ -1,5 +1,7 @@
 use super::{orthographic::Orthographic, perspective::Perspective, Cameras};
+use crate::scene::SceneError;
 use quick_maths::Transform4;
+use std::sync::Mutex;
 
 /// Camera Builder
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -8,6 +10,9 @@ pub struct Builder {
   pub to_world: crate::transform::Builder,
   pub sampler: Option<crate::sampler::builder::Builder>,
   pub variant: Variant,
+  /// Optional polarizing filter in front of the film
+  #[serde(default)]
+  pub polarizer: Option<super::polarizer::Polarizer>,
 }
 
 /// Builder Variants
@@ -27,6 +32,19 @@ pub enum Variant {
   },
 }
 
+impl Builder {
+  /// Checks that the camera can be rendered with the features this was compiled with
+  pub fn validate(&self) -> Result<(), SceneError> {
+    match self.polarizer {
+      Some(p) if !cfg!(feature = "polarized") => Err(SceneError::invalid(format!(
+        "camera polarizer {:?} requires the polarized feature",
+        p
+      ))),
+      _ => Ok(()),
+    }
+  }
+}
+
 impl From<Variant> for super::Variant {
   fn from(v: Variant) -> Self {
     match v {
@@ -52,6 +70,7 @@ impl From<Builder> for Cameras {
       to_world,
       variant,
       sampler,
+      polarizer,
     } = b;
     let sampler = sampler.unwrap_or_else(Default::default);
     let to_world: Transform4 = to_world.into();
@@ -60,7 +79,8 @@ impl From<Builder> for Cameras {
       to_world,
       film: film_builder.into(),
       variant: variant.into(),
-      sampler: sampler.into(),
+      sampler: Mutex::new(sampler.into()),
+      polarizer,
     }
   }
 }
@@ -1,11 +1,15 @@
 pub mod builder;
 pub mod orthographic;
 pub mod perspective;
+pub mod polarizer;
 pub mod projective;
 
-use crate::{film::Film, sampler::Samplers};
-use quick_maths::{Ray3, Transform4, Vec2};
-use std::fmt::Debug;
+use crate::{film::Film, sampler::Samplers, spectrum::Spectrum};
+use quick_maths::{Ray3, Transform4, Vec2, Vec3};
+use std::{
+  fmt::Debug,
+  sync::{Mutex, MutexGuard},
+};
 
 pub trait Camera: Debug {
   /// Sample a ray from the camera using the given uv in [0,1]^2.
@@ -24,8 +28,11 @@ pub struct Cameras {
   /// The film for this camera
   film: Film,
 
-  /// Sampler for this camera
-  sampler: Samplers,
+  /// Sampler for this camera, locked since drawing from it advances its state
+  sampler: Mutex<Samplers>,
+
+  /// Optional polarizing filter in front of the film
+  polarizer: Option<polarizer::Polarizer>,
 
   /// Which specific version of this camera is it
   variant: Variant,
@@ -42,8 +49,40 @@ pub enum Variant {
 impl Cameras {
   /// Returns the film for this camera
   pub fn film(&self) -> &Film { &self.film }
-  /// Returns the sampler used
-  pub fn sampler(&self) -> &Samplers { &self.sampler }
+  /// Returns the sampler used, which is locked until the guard is dropped
+  pub fn sampler(&self) -> MutexGuard<'_, Samplers> { self.sampler.lock().unwrap() }
+  /// Returns the polarizing filter in front of the film if there is one
+  pub fn polarizer(&self) -> Option<&polarizer::Polarizer> { self.polarizer.as_ref() }
+  /// Applies any filters in front of the film to light arriving at a pixel
+  pub fn filter(&self, px: (u32, u32), s: Spectrum) -> Spectrum {
+    match &self.polarizer {
+      None => s,
+      Some(p) => p.filter(px, s),
+    }
+  }
+  /// Returns how many pixels a segment in world space covers on the film, or zero if either end
+  /// is behind the camera.
+  pub fn projected_length(&self, a: Vec3, b: Vec3) -> f32 {
+    let to_raster = match &self.variant {
+      Variant::Perspective(p) => &p.projective().camera_to_raster,
+      Variant::Orthographic(o) => &o.projective().camera_to_raster,
+    };
+    let (a, b) = (
+      self.from_world.apply_point(&a),
+      self.from_world.apply_point(&b),
+    );
+    if a.z() <= 0. || b.z() <= 0. {
+      return 0.;
+    }
+    let size = self.film.size.apply_fn(|v| v as f32);
+    let to_px = |p: Vec3| {
+      let p = to_raster.apply_point(&p);
+      Vec2::new(p.x(), p.y()) * size
+    };
+    (to_px(a) - to_px(b)).magn()
+  }
+  /// Returns the horizontal axis of this camera in world space
+  pub fn right(&self) -> Vec3 { self.to_world.apply_vec(&Vec3::new(1., 0., 0.)).norm() }
 }
 
 impl Camera for Cameras {
@@ -11,6 +11,7 @@ impl Orthographic {
       * Transform4::orthographic(near, far);
     Self(Projective::new(camera_to_raster))
   }
+  pub fn projective(&self) -> &Projective { &self.0 }
 }
 
 impl Camera for Orthographic {
@@ -11,6 +11,7 @@ impl Perspective {
       * Transform4::perspective(x_fov, near, far);
     Self(Projective::new(camera_to_raster))
   }
+  pub fn projective(&self) -> &Projective { &self.0 }
 }
 
 impl Camera for Perspective {
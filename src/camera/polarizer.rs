@@ -0,0 +1,72 @@
+use crate::spectrum::Spectrum;
+
+/// A polarizing filter placed in front of the film of a camera
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum Polarizer {
+  /// Linear polarizer with its transmission axis at some angle in degrees counter-clockwise
+  /// from the horizontal of the camera.
+  Linear(f32),
+  /// Division of focal plane sensor, with a repeating 2x2 mosaic of linear polarizers.
+  /// Follows the layout of common polarization cameras:
+  /// ```text
+  ///  90 | 45
+  /// ----+----
+  /// 135 | 0
+  /// ```
+  DivisionOfFocalPlane,
+}
+
+/// Angles in degrees of the division of focal plane mosaic, indexed by (x % 2) + 2 * (y % 2)
+pub const MOSAIC_ANGLES: [f32; 4] = [90., 45., 135., 0.];
+
+impl Polarizer {
+  /// Returns the angle in degrees of the polarizer over some pixel
+  pub fn angle_at(&self, (x, y): (u32, u32)) -> f32 {
+    match self {
+      Polarizer::Linear(angle) => *angle,
+      Polarizer::DivisionOfFocalPlane => MOSAIC_ANGLES[((x % 2) + 2 * (y % 2)) as usize],
+    }
+  }
+  /// Filters light arriving at a pixel, where the light is expressed relative to the horizontal
+  /// of the camera.
+  #[cfg(feature = "polarized")]
+  pub fn filter(&self, px: (u32, u32), s: Spectrum) -> Spectrum {
+    use crate::polarized::mueller;
+    let theta = self.angle_at(px).to_radians();
+    mueller::apply(
+      &mueller::rotated_element(theta, &mueller::linear_polarizer(1.0)),
+      &s,
+    )
+  }
+  /// Without polarization all light is treated as unpolarized, so half is transmitted. Scenes
+  /// with polarizers are rejected when built without the feature, see `Builder::validate`.
+  #[cfg(not(feature = "polarized"))]
+  pub fn filter(&self, _px: (u32, u32), s: Spectrum) -> Spectrum { s * 0.5 }
+}
+
+#[cfg(all(test, feature = "polarized"))]
+mod test_polarizer {
+  use super::Polarizer;
+  use crate::polarized::stokes::linear;
+
+  #[test]
+  fn mosaic_transmits_aligned_light() {
+    let horizontal = linear(1.0, 0.0);
+    let dofp = Polarizer::DivisionOfFocalPlane;
+    assert!((dofp.filter((1, 1), horizontal)[0] - 1.0).abs() < 1e-5);
+    assert!(dofp.filter((0, 0), horizontal)[0].abs() < 1e-5);
+    assert!((dofp.filter((1, 0), horizontal)[0] - 0.5).abs() < 1e-5);
+  }
+
+  #[test]
+  fn mosaic_separates_diagonals() {
+    use std::f32::consts::FRAC_PI_4;
+    let dofp = Polarizer::DivisionOfFocalPlane;
+    let (diagonal, anti_diagonal) = (linear(1.0, FRAC_PI_4), linear(1.0, 3. * FRAC_PI_4));
+    // (1, 0) is behind the 45 degree polarizer and (0, 1) behind the 135 degree one
+    assert!((dofp.filter((1, 0), diagonal)[0] - 1.0).abs() < 1e-5);
+    assert!(dofp.filter((0, 1), diagonal)[0].abs() < 1e-5);
+    assert!(dofp.filter((1, 0), anti_diagonal)[0].abs() < 1e-5);
+    assert!((dofp.filter((0, 1), anti_diagonal)[0] - 1.0).abs() < 1e-5);
+  }
+}
@@ -2,12 +2,14 @@ pub mod blocks;
 pub mod builder;
 pub use builder::Builder;
 pub mod draw;
+#[cfg(feature = "polarized")]
+pub mod stokes;
 
 use crate::{
   spectrum::{self, Spectrum},
   utils::{morton_decode, morton_encode},
 };
-use image::{DynamicImage, GenericImage, Rgba};
+use image::{DynamicImage, GenericImage, GenericImageView, Rgba};
 use quick_maths::{Vec2, Vec3, Vector, Zero};
 use std::{fmt::Debug, sync::RwLock};
 
@@ -51,6 +53,22 @@ impl Film {
     }
     img
   }
+  /// Splits a film rendered through a 2x2 mosaic filter into one image per position in the
+  /// mosaic, each at half the resolution. They are ordered by (x % 2) + 2 * (y % 2).
+  pub fn split_mosaic(&self) -> [DynamicImage; 4] {
+    let full = self.to_image();
+    let (w, h) = (self.size.x() / 2, self.size.y() / 2);
+    let sub = |dx: u32, dy: u32| {
+      let mut img = DynamicImage::new_rgb8(w, h);
+      for x in 0..w {
+        for y in 0..h {
+          img.put_pixel(x, y, full.get_pixel(2 * x + dx, 2 * y + dy));
+        }
+      }
+      img
+    };
+    [sub(0, 0), sub(1, 0), sub(0, 1), sub(1, 1)]
+  }
 }
 
 /// Represents one portion of the film
@@ -0,0 +1,73 @@
+use super::Film;
+use crate::{
+  polarized::stokes::{
+    angle_of_polarization, degree_of_linear_polarization, degree_of_polarization, Stokes,
+  },
+  utils::morton_decode,
+};
+use image::{DynamicImage, GenericImage, Rgba};
+
+/// Maps a signed value in [-1, 1] to red if positive and blue if negative
+fn diverging(v: f32) -> [u8; 3] {
+  let v = v.max(-1.).min(1.);
+  [(v.max(0.) * 255.) as u8, 0, ((-v).max(0.) * 255.) as u8]
+}
+
+/// Converts hue, saturation, value all in [0,1] to rgb
+fn hsv_to_rgb(h: f32, s: f32, v: f32) -> [u8; 3] {
+  let h = (h.fract() + 1.).fract() * 6.;
+  let c = v * s;
+  let x = c * (1. - ((h % 2.) - 1.).abs());
+  let (r, g, b) = match h as u32 {
+    0 => (c, x, 0.),
+    1 => (x, c, 0.),
+    2 => (0., c, x),
+    3 => (0., x, c),
+    4 => (x, 0., c),
+    _ => (c, 0., x),
+  };
+  let m = v - c;
+  let to_u8 = |u: f32| ((u + m) * 255.) as u8;
+  [to_u8(r), to_u8(g), to_u8(b)]
+}
+
+impl Film {
+  fn map_image(&self, f: impl Fn(&Stokes) -> [u8; 3]) -> DynamicImage {
+    let mut img = DynamicImage::new_rgb8(self.size.x(), self.size.y());
+    for (i, s) in self.storage.read().unwrap().iter().enumerate() {
+      let (x, y) = morton_decode(i as u32);
+      if x >= self.size.x() || y >= self.size.y() {
+        continue;
+      }
+      let [r, g, b] = f(s);
+      img.put_pixel(x, y, Rgba([r, g, b, 255]));
+    }
+    img
+  }
+  /// Returns one image per stokes component. The first is intensity, and the other three are
+  /// normalized by intensity and shown with red as positive and blue as negative.
+  pub fn stokes_images(&self) -> [DynamicImage; 4] {
+    let normalized = |s: &Stokes, i: usize| if s[0] > 0. { s[i] / s[0] } else { 0. };
+    [
+      self.to_image(),
+      self.map_image(|s| diverging(normalized(s, 1))),
+      self.map_image(|s| diverging(normalized(s, 2))),
+      self.map_image(|s| diverging(normalized(s, 3))),
+    ]
+  }
+  /// Returns a grayscale image of the degree of polarization
+  pub fn dop_image(&self) -> DynamicImage {
+    self.map_image(|s| {
+      let v = (degree_of_polarization(s) * 255.) as u8;
+      [v, v, v]
+    })
+  }
+  /// Returns an image with hue as the angle of polarization and brightness as the degree of
+  /// polarization.
+  pub fn aop_image(&self) -> DynamicImage {
+    self.map_image(|s| {
+      let hue = angle_of_polarization(s) * std::f32::consts::FRAC_1_PI + 0.5;
+      hsv_to_rgb(hue, 1., degree_of_linear_polarization(s))
+    })
+  }
+}
@@ -1,28 +1,41 @@
 use super::SamplingIntegrator;
-use crate::{accelerator::Accelerator, camera::Cameras, scene::Scene, spectrum::Spectrum};
+use crate::{
+  accelerator::Accelerator,
+  camera::Cameras,
+  sampler::Samplers,
+  scene::Scene,
+  spectrum::{Spectral, Spectrum},
+};
 use quick_maths::{Ray3, Vec2, Zero};
 
+/// How many mirror reflections and refractions are followed from each camera ray
+const MAX_SPECULAR_DEPTH: u32 = 8;
+
+/// Offset along specular rays so they do not hit the surface they leave
+const SPECULAR_EPS: f32 = 1e-3;
+
 #[derive(Debug)]
 pub struct Direct {
   // TODO add other items here?
 }
 
-impl SamplingIntegrator for Direct {
-  fn sample<El, Acc: Accelerator>(
+impl Direct {
+  /// Light arriving along a ray directly from light sources, including through any number of
+  /// specular bounces up to the maximum depth. Specular lobes are chosen with the sampler.
+  fn radiance<El, Acc: Accelerator>(
     &self,
-    _position: Vec2,
     ray: &Ray3,
-    _camera: &Cameras,
     scene: &Scene<El, Acc>,
+    sampler: &mut Samplers,
+    depth: u32,
   ) -> Spectrum {
-    let si = scene.intersect_ray(ray);
     let mut result = Spectrum::zero();
-    let (si, s) = if let Some((si, s)) = si {
-      (si, s)
+    let si = if let Some((si, _)) = scene.intersect_ray(ray) {
+      si
     } else {
       return result;
     };
-    let bsdf = s.bsdf();
+    let bsdf = scene.bsdf(&si);
 
     // Attempt to compute direct lighting in scene
     for l in &scene.lights {
@@ -36,10 +49,67 @@ impl SamplingIntegrator for Direct {
           continue;
         }
       }
-      // add light from direct sources and ensure it's not negative
-      let reflected = bsdf.eval(&si, -ray.dir);
-      result += (reflected * emitted_light).max(0.);
+      #[cfg(feature = "polarized")]
+      {
+        // emitted light is expressed in the stokes basis of the light ray
+        let reflected = bsdf.eval_mueller(&si, -ray.dir);
+        result += crate::polarized::mueller::apply(&reflected, &emitted_light);
+      }
+      #[cfg(not(feature = "polarized"))]
+      {
+        // add light from direct sources and ensure it's not negative
+        let reflected = bsdf.eval(&si, -ray.dir);
+        result += (reflected * emitted_light).clamp_negative();
+      }
+    }
+    if depth >= MAX_SPECULAR_DEPTH {
+      return result;
+    }
+    // Mirrors and glass never reflect light sources directly, so follow one of their lobes
+    // instead, chosen by how much light it carries
+    let lobes = bsdf
+      .specular(&si)
+      .into_iter()
+      .filter(|s| s.weight.luminance() > 0.)
+      .collect::<Vec<_>>();
+    let total = lobes.iter().map(|s| s.weight.luminance()).sum::<f32>();
+    if total <= 0. {
+      return result;
+    }
+    let mut x = sampler.sample() * total;
+    let chosen = lobes
+      .iter()
+      .position(|s| {
+        x -= s.weight.luminance();
+        x < 0.
+      })
+      .unwrap_or(lobes.len() - 1);
+    let s = &lobes[chosen];
+    let pdf = s.weight.luminance() / total;
+    let ray = Ray3::new(si.it.p + s.wo * SPECULAR_EPS, s.wo);
+    let incoming = self.radiance(&ray, scene, sampler, depth + 1);
+    #[cfg(feature = "polarized")]
+    {
+      use crate::polarized::mueller;
+      let m = bsdf.specular_mueller(&si, s) * pdf.recip();
+      result += mueller::apply(&m, &incoming);
+    }
+    #[cfg(not(feature = "polarized"))]
+    {
+      result += (s.weight * incoming / pdf).clamp_negative();
     }
     result
   }
 }
+
+impl SamplingIntegrator for Direct {
+  fn sample<El, Acc: Accelerator>(
+    &self,
+    _position: Vec2,
+    ray: &Ray3,
+    camera: &Cameras,
+    scene: &Scene<El, Acc>,
+  ) -> Spectrum {
+    self.radiance(ray, scene, &mut camera.sampler(), 0)
+  }
+}
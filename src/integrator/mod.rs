@@ -17,6 +17,8 @@ pub trait Integrator: Debug {
 }
 
 pub trait SamplingIntegrator: Debug {
+  /// Returns the radiance arriving along the ray. When rendering polarization, it is expressed
+  /// in the stokes basis of light travelling along -ray.dir.
   fn sample<El, Acc: Accelerator>(
     &self,
     position: Vec2,
@@ -46,6 +48,7 @@ impl<S: SamplingIntegrator> Integrator for S {
         .map(|_| render_sample(self, s, uv))
         .fold(Spectrum::zero(), |acc, n| acc + n)
         / (sample_count as f32);
+      let spec = s.camera.filter((x, y), spec);
       s.camera.film().write(uv, spec);
     }
   }
@@ -60,7 +63,18 @@ fn render_sample<S: SamplingIntegrator, El, Acc: Accelerator>(
   // TODO maybe this should include a weight?
   let ray = camera.sample_ray(pos);
   // Write the sample to the position
-  s.sample(pos, &ray, camera, scene)
+  let spec = s.sample(pos, &ray, camera, scene);
+  #[cfg(feature = "polarized")]
+  let spec = {
+    // Express the stokes vector w.r.t. the horizontal axis of the camera
+    use crate::polarized::{mueller, stokes::stokes_basis};
+    let w = -ray.dir;
+    mueller::apply(
+      &mueller::rotate_stokes_basis(&w, &stokes_basis(&w), &camera.right()),
+      &spec,
+    )
+  };
+  spec
 }
 
 pub trait MonteCarloIntegrator: SamplingIntegrator {
@@ -30,7 +30,7 @@ impl SamplingIntegrator for Path {
         break
       }
 
-      let bsdf = shape.bsdf();
+      let bsdf = scene.bsdf(&si);
       for l in &scene.lights {
         let (light_ray, emitted_light) = l.sample_towards(&si.it);
         if let Some((l_si, _)) = scene.intersect_ray(&light_ray) {
@@ -60,7 +60,7 @@ impl SamplingIntegrator for Path {
       if emitted_light.is_zero() {
         continue;
       }
-      let bsdf = s.bsdf();
+      let bsdf = scene.bsdf(&si);
       // add light from direct sources and ensure it's not negative
       let reflected = bsdf.eval(&si, -ray.dir);
       result += (reflected * emitted_light).max(0.);
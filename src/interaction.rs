@@ -36,6 +36,8 @@ pub struct SurfaceInteraction {
   pub uv: Vec2,
   /// Incoming direction of incident light
   pub wi: Vec3,
+  /// Which of its shape's materials the primitive hit uses, which is 0 for shapes with one
+  pub material: usize,
 }
 
 #[derive(Debug)]
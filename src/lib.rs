@@ -4,6 +4,11 @@
 
 pub const EPS: f32 = 0.000001;
 
+#[cfg(all(feature = "mono", feature = "polarized"))]
+compile_error!("The mono and polarized features are mutually exclusive");
+#[cfg(all(feature = "spectral", any(feature = "mono", feature = "polarized")))]
+compile_error!("The spectral feature cannot be combined with mono or polarized");
+
 pub mod accelerator;
 pub mod bounds;
 pub mod bsdf;
@@ -13,6 +18,7 @@ pub mod integrator;
 pub mod interaction;
 pub mod light;
 pub mod medium;
+pub mod mesh;
 pub mod polarized;
 pub mod sampler;
 pub mod scene;
@@ -1,5 +1,8 @@
 use super::Light;
-use crate::{interaction::Interaction, spectrum::Spectrum};
+use crate::{
+  interaction::Interaction,
+  spectrum::{Spectral, Spectrum},
+};
 use quick_maths::{Ray3, Vec3};
 
 /// Represents a direction light source
@@ -11,7 +14,9 @@ pub struct Dir {
   /// Scale of intensity
   intensity: f32,
 
-  /// Colour emitted by this light
+  /// Colour emitted by this light, which can also be a blackbody temperature, spd file or
+  /// standard illuminant
+  #[serde(deserialize_with = "crate::polarized::deserialize_spectrum")]
   spectrum: Spectrum,
 }
 
@@ -33,4 +38,5 @@ impl Light for Dir {
       self.spectrum * self.intensity,
     )
   }
+  fn strength(&self) -> f32 { self.intensity * self.spectrum.luminance() }
 }
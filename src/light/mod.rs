@@ -13,6 +13,8 @@ pub trait Light: Debug {
   /// Casts a ray towards an interaction of the scene, returning a ray representing the
   /// direction and the light emitted towards it
   fn sample_towards(&self, it: &Interaction) -> (Ray3, Spectrum);
+  /// How strongly this light emits, as the luminance of its colour scaled by its intensity
+  fn strength(&self) -> f32;
 }
 
 // pub mod point;
@@ -31,4 +33,11 @@ impl Lights {
       Dir(d) => d.sample_towards(it),
     }
   }
+  pub fn strength(&self) -> f32 {
+    use Lights::*;
+    match self {
+      Point(p) => p.strength(),
+      Dir(d) => d.strength(),
+    }
+  }
 }
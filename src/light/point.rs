@@ -1,5 +1,8 @@
 use super::Light;
-use crate::{interaction::Interaction, spectrum::Spectrum};
+use crate::{
+  interaction::Interaction,
+  spectrum::{Spectral, Spectrum},
+};
 use quick_maths::{Ray3, Vec3};
 
 /// Represents a point light source
@@ -11,7 +14,9 @@ pub struct Point {
   /// Scale of intensity
   intensity: f32,
 
-  /// Colour emitted by this light
+  /// Colour emitted by this light, which can also be a blackbody temperature, spd file or
+  /// standard illuminant
+  #[serde(deserialize_with = "crate::polarized::deserialize_spectrum")]
   spectrum: Spectrum,
 }
 
@@ -34,6 +39,7 @@ impl Light for Point {
       self.spectrum * self.intensity / (dist * dist),
     )
   }
+  fn strength(&self) -> f32 { self.intensity * self.spectrum.luminance() }
 }
 
 /*
@@ -0,0 +1,604 @@
+use quick_maths::Vec3;
+use std::{
+  collections::{hash_map::Entry, HashMap, HashSet},
+  fs::File,
+  io::{self, BufRead},
+  path::Path,
+};
+
+/// Face index of half edges which lie on the boundary of a mesh
+pub const NO_FACE: usize = usize::MAX;
+/// Half edge index of vertices which are not part of any face
+const NO_HALF_EDGE: usize = usize::MAX;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Vertex {
+  pub pos: Vec3,
+  // index into a half_edge list, of a half edge starting at this vertex
+  half_edge: usize,
+}
+
+impl Vertex {
+  fn connect_he(&mut self, he: &HalfEdge) { self.half_edge = he.id; }
+}
+
+/// Iterator over edges adjacent to vertex (vertex-edge iterator).
+pub struct VEIter<'a> {
+  first: Option<&'a HalfEdge>,
+  curr: Option<&'a HalfEdge>,
+  mesh: &'a DCEL,
+}
+
+impl<'a> VEIter<'a> {
+  fn new(mesh: &'a DCEL, v: &Vertex) -> Self {
+    let first = v.he(mesh);
+    Self {
+      first,
+      curr: first,
+      mesh,
+    }
+  }
+}
+
+impl<'a> Iterator for VEIter<'a> {
+  type Item = &'a HalfEdge;
+  fn next(&mut self) -> Option<Self::Item> {
+    let out = self.curr?;
+    let next = out.opp(self.mesh).next(self.mesh);
+    assert_eq!(next.vertex, out.vertex);
+    self.curr = Some(next).filter(|&next| Some(next) != self.first);
+    Some(out)
+  }
+}
+
+impl Vertex {
+  fn new(pos: Vec3) -> Self {
+    Self {
+      pos,
+      half_edge: NO_HALF_EDGE,
+    }
+  }
+  /// Returns a half edge starting at this vertex, if it is part of any face
+  pub fn he<'a>(&self, mesh: &'a DCEL) -> Option<&'a HalfEdge> {
+    mesh.half_edges.get(self.half_edge)
+  }
+  /// Returns half edges starting at this vertex
+  pub fn adj_edges<'a>(&self, mesh: &'a DCEL) -> VEIter<'a> { VEIter::new(mesh, self) }
+  pub fn adj_verts<'a>(&self, mesh: &'a DCEL) -> impl Iterator<Item = &'a Vertex> {
+    VEIter::new(mesh, self).map(move |he| he.opp(mesh).vert(mesh))
+  }
+  pub fn adj_faces<'a>(&self, mesh: &'a DCEL) -> impl Iterator<Item = &'a Face> {
+    self.adj_edges(mesh).filter_map(move |he| he.face(mesh))
+  }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct HalfEdge {
+  id: usize,
+  /// Vertex this half edge starts at
+  vertex: usize,
+  // index to where the next counterclockwise half edge is, hopefully should be close in the
+  // original vector to where this one is counter clockwise(cc)
+  cc_next: usize,
+  opposite: usize,
+
+  face: usize,
+}
+
+impl HalfEdge {
+  pub fn id(&self) -> usize { self.id }
+  pub fn vert_id(&self) -> usize { self.vertex }
+  pub fn face_id(&self) -> Option<usize> { Some(self.face).filter(|&f| f != NO_FACE) }
+  /// Returns whether this half edge lies on the boundary of the mesh
+  pub fn is_boundary(&self) -> bool { self.face == NO_FACE }
+  pub fn opp<'a>(&self, mesh: &'a DCEL) -> &'a HalfEdge { &mesh.half_edges[self.opposite] }
+  pub fn next<'a>(&self, mesh: &'a DCEL) -> &'a HalfEdge { &mesh.half_edges[self.cc_next] }
+  pub fn vert<'a>(&self, mesh: &'a DCEL) -> &'a Vertex { &mesh.vertices[self.vertex] }
+  pub fn face<'a>(&self, mesh: &'a DCEL) -> Option<&'a Face> {
+    self.face_id().map(|f| &mesh.faces[f])
+  }
+  /// returns the vertices which end this half-edge
+  /// In the order of [from, to]
+  pub fn adj_verts<'a>(&self, mesh: &'a DCEL) -> [&'a Vertex; 2] {
+    [self.vert(&mesh), self.opp(&mesh).vert(&mesh)]
+  }
+  /// Returns just the id of adjacent faces, in the order of this edge and then its opposite
+  pub fn adj_face_ids(&self, mesh: &DCEL) -> [usize; 2] { [self.face, self.opp(&mesh).face] }
+  /// returns an initial half edge which is partially constructed
+  fn build_initial(vertex: usize, face: usize, id: usize) -> Self {
+    HalfEdge {
+      id,
+      vertex,
+      face,
+      ..Default::default()
+    }
+  }
+  /// Connects two half-edges marking them as opposites
+  fn connect_opposite(&mut self, o: &mut Self) {
+    self.opposite = o.id;
+    o.opposite = self.id;
+  }
+  /// Builds the counterclock-wise subsequent half-edge
+  fn build_next(&mut self, vertex: usize, face: usize, id: usize) -> Self {
+    self.cc_next = id;
+    HalfEdge {
+      id,
+      vertex,
+      face,
+      cc_next: 0,
+      opposite: 0,
+    }
+  }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Face {
+  half_edge: usize,
+}
+
+/// Face-Edge iterator
+pub struct FEIter<'a> {
+  /// ID of the first half edge seen
+  first: &'a HalfEdge,
+  /// Current half edge
+  curr: Option<&'a HalfEdge>,
+  mesh: &'a DCEL,
+}
+
+impl<'a> Iterator for FEIter<'a> {
+  type Item = &'a HalfEdge;
+  fn next(&mut self) -> Option<Self::Item> {
+    let out = self.curr?;
+    self.curr = Some(out.next(self.mesh)).filter(|&next| next != self.first);
+    Some(out)
+  }
+}
+
+impl Face {
+  fn from_he(half_edge: &HalfEdge) -> Self {
+    Self {
+      half_edge: half_edge.id,
+    }
+  }
+  fn he<'a>(&self, mesh: &'a DCEL) -> &'a HalfEdge { &mesh.half_edges[self.half_edge] }
+  /// Returns edges adjacent to this face
+  pub fn adj_edges<'a>(&self, mesh: &'a DCEL) -> FEIter<'a> {
+    let first = self.he(mesh);
+    FEIter {
+      first,
+      curr: Some(first),
+      mesh,
+    }
+  }
+  /// Returns vertices adjacent to this face
+  pub fn adj_verts<'a>(&self, mesh: &'a DCEL) -> impl Iterator<Item = &'a Vertex> {
+    self.adj_edges(mesh).map(move |he| he.vert(mesh))
+  }
+  /// Returns ids of the vertices of this face in order
+  pub fn vert_ids<'a>(&self, mesh: &'a DCEL) -> impl Iterator<Item = usize> + 'a {
+    self.adj_edges(mesh).map(|he| he.vertex)
+  }
+  pub fn adj_faces<'a>(&self, mesh: &'a DCEL) -> impl Iterator<Item = &'a Face> {
+    self
+      .adj_edges(mesh)
+      .filter_map(move |he| he.opp(mesh).face(mesh))
+  }
+  /// Returns the centroid of the vertices of this face
+  pub fn centroid(&self, mesh: &DCEL) -> Vec3 {
+    let (sum, n) = self
+      .adj_verts(mesh)
+      .fold((Vec3::of(0.), 0.), |(acc, n), v| (acc + v.pos, n + 1.));
+    sum / n
+  }
+  /// Returns the unit normal of this face using Newell's method, which works for non-planar
+  /// polygons.
+  pub fn normal(&self, mesh: &DCEL) -> Vec3 {
+    let verts = self.adj_verts(mesh).map(|v| v.pos).collect::<Vec<_>>();
+    let n = verts.len();
+    (0..n)
+      .map(|i| verts[i].cross(&verts[(i + 1) % n]))
+      .fold(Vec3::of(0.), |acc, c| acc + c)
+      .norm()
+  }
+}
+
+/// A topographic list of graph elements.
+/// They are not in any particular order, but in theory adjacent elements
+/// should be next to each other for cache efficiency.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct DoublyConnectedEdgeList {
+  vertices: Vec<Vertex>,
+  faces: Vec<Face>,
+  half_edges: Vec<HalfEdge>,
+  /// Group of each face, which is passed on to the faces made from it
+  groups: Vec<usize>,
+}
+
+// dcel is a Doubly Connected Edge List
+pub type DCEL = DoublyConnectedEdgeList;
+
+/// Material libraries and names used by an OBJ, which faces of a mesh loaded from it refer to by
+/// their group.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ObjMaterials {
+  /// Files named by `mtllib`, relative to the OBJ
+  pub libs: Vec<String>,
+  /// Names given to `usemtl`, where faces after the i-th name are in group i + 1
+  pub names: Vec<String>,
+}
+
+fn invalid(msg: String) -> io::Error { io::Error::new(io::ErrorKind::InvalidData, msg) }
+
+/// Splits vertices where faces only meet at that vertex, such as the middle of a bowtie, into
+/// one copy for each fan of faces which are connected by edges around it.
+fn split_fans(vertices: &mut Vec<Vec3>, faces: &mut [Vec<usize>]) {
+  // corners of faces around each vertex, as the face and the index of the vertex in it
+  let mut corners = vec![vec![]; vertices.len()];
+  for (f, verts) in faces.iter().enumerate() {
+    for (i, &v) in verts.iter().enumerate() {
+      corners[v].push((f, i));
+    }
+  }
+  for (v, corners) in corners.into_iter().enumerate() {
+    // vertices at the other end of the two edges of each corner
+    let adj = corners
+      .iter()
+      .map(|&(f, i)| {
+        let n = faces[f].len();
+        [faces[f][(i + n - 1) % n], faces[f][(i + 1) % n]]
+      })
+      .collect::<Vec<_>>();
+    let mut fan = vec![None; corners.len()];
+    let mut fans = 0;
+    while let Some(start) = fan.iter().position(Option::is_none) {
+      fan[start] = Some(fans);
+      let mut stack = vec![start];
+      while let Some(c) = stack.pop() {
+        for (o, other) in adj.iter().enumerate() {
+          if fan[o].is_none() && other.iter().any(|x| adj[c].contains(x)) {
+            fan[o] = Some(fans);
+            stack.push(o);
+          }
+        }
+      }
+      fans += 1;
+    }
+    // the first fan keeps the original vertex
+    let pos = vertices[v];
+    let copies = (1..fans)
+      .map(|_| {
+        vertices.push(pos);
+        vertices.len() - 1
+      })
+      .collect::<Vec<_>>();
+    for (&(f, i), fan) in corners.iter().zip(fan) {
+      if let Some(copy) = fan.and_then(|fan: usize| fan.checked_sub(1)) {
+        faces[f][i] = copies[copy];
+      }
+    }
+  }
+}
+
+impl DoublyConnectedEdgeList {
+  /// Constructs a non-empty DoublyConnectedEdgeList from a set of vertices and their faces.
+  /// Faces must be consistently oriented, and edges with only one face are linked to half edges
+  /// with no face along the boundary. Vertices where separate fans of faces meet are split, so
+  /// there may be more vertices than given.
+  pub fn new(vertices: Vec<Vec3>, faces: &[Vec<usize>]) -> io::Result<Self> {
+    if faces.is_empty() {
+      return Err(invalid(String::from("Meshes require at least one face")));
+    }
+    for verts in faces {
+      if verts.len() < 3 {
+        return Err(invalid(format!(
+          "Face {:?} has fewer than 3 vertices",
+          verts
+        )));
+      }
+      if let Some(v) = verts.iter().find(|&&v| v >= vertices.len()) {
+        let msg = format!("Face refers to vertex {} of {}", v, vertices.len());
+        return Err(invalid(msg));
+      }
+      if verts.iter().collect::<HashSet<_>>().len() != verts.len() {
+        return Err(invalid(format!("Face {:?} repeats a vertex", verts)));
+      }
+    }
+    let (mut positions, mut faces) = (vertices, faces.to_vec());
+    split_fans(&mut positions, &mut faces);
+    let mut vertices = positions.into_iter().map(Vertex::new).collect::<Vec<_>>();
+    let mut hes = vec![];
+    let mut finished_faces: Vec<Face> = vec![];
+    let mut count = 0;
+    let mut new_id = || -> usize {
+      let curr: usize = count;
+      count += 1;
+      curr
+    };
+    let mut he: HashMap<(usize, usize), HalfEdge> = HashMap::new();
+    // edges which already have a half edge on either side
+    let mut paired = HashSet::new();
+    let mut complete = |from: usize, to: usize, mut curr: HalfEdge| -> io::Result<()> {
+      vertices[from].connect_he(&curr);
+      let shared = || {
+        let msg = format!(
+          "Edge {:?} is shared by more than two faces or faces are inconsistently oriented",
+          (from, to)
+        );
+        invalid(msg)
+      };
+      if !paired.insert((from.min(to), from.max(to))) {
+        return Err(shared());
+      }
+      match he.entry((to, from)) {
+        Entry::Occupied(o) => {
+          let mut opp = o.remove();
+          curr.connect_opposite(&mut opp);
+          hes.push(opp);
+          hes.push(curr);
+        },
+        Entry::Vacant(_) => {
+          paired.remove(&(from.min(to), from.max(to)));
+          if he.insert((from, to), curr).is_some() {
+            return Err(shared());
+          }
+        },
+      }
+      Ok(())
+    };
+    for (f, verts) in faces.iter().enumerate() {
+      let mut curr_vert = verts[0];
+      let mut curr = HalfEdge::build_initial(curr_vert, f, new_id());
+      let first_id = curr.id;
+      finished_faces.push(Face::from_he(&curr));
+      for &v in &verts[1..] {
+        let next = curr.build_next(v, f, new_id());
+        complete(curr_vert, v, curr)?;
+        curr_vert = v;
+        curr = next;
+      }
+      // need to link last created one to the first half_edge
+      curr.cc_next = first_id;
+      complete(curr_vert, verts[0], curr)?;
+    }
+    // Remaining half edges are on the boundary, so give them opposites with no face
+    let mut boundary_from = HashMap::new();
+    for ((_, to), mut inner) in he.drain() {
+      let mut outer = HalfEdge::build_initial(to, NO_FACE, new_id());
+      inner.connect_opposite(&mut outer);
+      if boundary_from.insert(to, outer.id).is_some() {
+        return Err(invalid(format!(
+          "Vertex {} is on more than one boundary",
+          to
+        )));
+      }
+      hes.push(inner);
+      hes.push(outer);
+    }
+    // have to sort here because the halfedges are processed in random order
+    hes.sort_unstable_by_key(|he| he.id);
+    for i in 0..hes.len() {
+      if hes[i].is_boundary() {
+        // boundary half edges are followed by the boundary half edge leaving where they end
+        let dest = hes[hes[i].opposite].vertex;
+        hes[i].cc_next = *boundary_from
+          .get(&dest)
+          .ok_or_else(|| invalid(format!("Boundary is not closed around vertex {}", dest)))?;
+        // start iteration around boundary vertices on the boundary
+        vertices[hes[i].vertex].half_edge = i;
+      }
+    }
+    Ok(Self {
+      vertices,
+      groups: vec![0; finished_faces.len()],
+      faces: finished_faces,
+      half_edges: hes,
+    })
+  }
+  /// Creates a DoublyConnectedEdgeList from an obj file, keeping polygonal faces intact. Faces
+  /// are grouped by the material they use, as given by the returned materials.
+  pub fn from_obj<P: AsRef<Path>>(path: P) -> io::Result<(Self, ObjMaterials)> {
+    let file = File::open(path)?;
+    let buf = io::BufReader::new(file);
+    let mut positions = vec![];
+    let mut faces = vec![];
+    let mut groups = vec![];
+    let mut materials = ObjMaterials::default();
+    let mut group = 0;
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    for line in buf.lines() {
+      let line = line?;
+      let parts = line
+        .splitn(2, '#')
+        .next()
+        .unwrap()
+        .split_whitespace()
+        .collect::<Vec<_>>();
+      match parts.as_slice() {
+        ["v", x, y, z, ..] => {
+          let pos = Vec3::from_str_radix([x, y, z], 10)
+            .map_err(|_| invalid("Could not parse vertex position"))?;
+          positions.push(pos);
+        },
+        ["mtllib", libs @ ..] => materials.libs.extend(libs.iter().map(|l| l.to_string())),
+        ["usemtl", name] => {
+          group = match materials.names.iter().position(|n| n == name) {
+            Some(i) => i + 1,
+            None => {
+              materials.names.push(name.to_string());
+              materials.names.len()
+            },
+          };
+        },
+        ["f", fs @ ..] => {
+          let mut face = fs
+            .iter()
+            .map(|v| {
+              let vertex = v
+                .split('/')
+                .next()
+                .and_then(|v| v.parse::<usize>().ok())
+                .ok_or_else(|| invalid("Could not parse vertex index"))?;
+              vertex
+                .checked_sub(1)
+                .ok_or_else(|| invalid("OBJ indices start at 1"))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+          // collapsed edges are dropped, and faces which collapse entirely are skipped
+          face.dedup();
+          if face.len() > 1 && face.first() == face.last() {
+            face.pop();
+          }
+          if face.len() < 3 {
+            continue;
+          }
+          faces.push(face);
+          groups.push(group);
+        },
+        // Everything else does not affect the topology
+        _ => (),
+      };
+    }
+    Ok((Self::new(positions, &faces)?.with_groups(groups), materials))
+  }
+  pub fn vertices(&self) -> &[Vertex] { &self.vertices }
+  pub fn faces(&self) -> &[Face] { &self.faces }
+  pub fn half_edges(&self) -> &[HalfEdge] { &self.half_edges }
+  pub fn groups(&self) -> &[usize] { &self.groups }
+  /// Sets the group of each face, such as which material it uses
+  pub fn with_groups(mut self, groups: Vec<usize>) -> Self {
+    assert_eq!(groups.len(), self.faces.len());
+    self.groups = groups;
+    self
+  }
+  /// Returns the faces as lists of vertex indices
+  pub fn face_lists(&self) -> Vec<Vec<usize>> {
+    self
+      .faces
+      .iter()
+      .map(|f| f.vert_ids(self).collect())
+      .collect()
+  }
+  #[cfg(test)]
+  /// validates that the mesh correctly satisfies the invariants of a DCEL
+  pub(crate) fn validate(&self) {
+    // check that faces match up
+    for (i, f) in self.faces.iter().enumerate() {
+      assert!(f.adj_edges(self).all(|he| he.face == i));
+    }
+    for he in &self.half_edges {
+      assert_eq!(he.opp(self).opp(self), he);
+      assert_eq!(he.next(self).vertex, he.opp(self).vertex);
+    }
+    for (i, v) in self.vertices.iter().enumerate() {
+      assert!(v.adj_edges(self).all(|he| he.vertex == i));
+    }
+  }
+  /// Creates a new vertex and returns its id
+  fn new_vert(&mut self, pos: Vec3) -> usize {
+    let id = self.vertices.len();
+    self.vertices.push(Vertex::new(pos));
+    id
+  }
+  /// Converts each face into triangles by fanning around its first vertex
+  pub fn triangulate(&mut self) -> io::Result<()> {
+    let (faces, groups): (Vec<_>, Vec<_>) = self
+      .face_lists()
+      .into_iter()
+      .zip(self.groups.iter())
+      .flat_map(|(f, &g)| {
+        (1..f.len() - 1)
+          .map(|i| (vec![f[0], f[i], f[i + 1]], g))
+          .collect::<Vec<_>>()
+      })
+      .unzip();
+    let verts = self.vertices.iter().map(|v| v.pos).collect();
+    *self = Self::new(verts, &faces)?.with_groups(groups);
+    Ok(())
+  }
+  fn update_half_edge(&mut self, he: HalfEdge) { self.half_edges[he.id] = he; }
+  /// splits a half edge by inserting a vertex a distance of factor between the two endpoints
+  /// Returns the new vertex created and discards the half-edge originally passed into the
+  pub fn split_half_edge(&mut self, mut he: HalfEdge, factor: f32) -> usize {
+    let factor = factor.min(1.).max(0.);
+    let next_id = he.next(self).id;
+    let mut opp = *he.opp(self);
+    let opp_next_id = opp.next(self).id;
+    // goes from v1 to v2
+    let [v1, v2] = he.adj_verts(&self);
+    let [f1, f2] = he.adj_face_ids(&self);
+    let (p1, p2) = (v1.pos, v2.pos);
+    let new_vert = self.new_vert(p1 + (p2 - p1) * factor);
+    // new_vert -> v2
+    let mut new_he1 = HalfEdge::build_initial(new_vert, f1, self.half_edges.len());
+    // new_vert -> v1
+    let mut new_he2 = HalfEdge::build_initial(new_vert, f2, self.half_edges.len() + 1);
+    he.cc_next = new_he1.id;
+    new_he1.cc_next = next_id;
+    he.connect_opposite(&mut new_he2);
+
+    opp.cc_next = new_he2.id;
+    new_he2.cc_next = opp_next_id;
+    opp.connect_opposite(&mut new_he1);
+    self.vertices[new_vert].connect_he(&new_he1);
+    self.half_edges.push(new_he1);
+    self.half_edges.push(new_he2);
+    self.update_half_edge(he);
+    self.update_half_edge(opp);
+
+    new_vert
+  }
+}
+
+#[cfg(test)]
+mod test_dcel {
+  use super::*;
+  #[test]
+  fn open_mesh() {
+    // Two triangles sharing an edge, with everything else on the boundary
+    let verts = vec![
+      Vec3::new(0., 0., 0.),
+      Vec3::new(1., 0., 0.),
+      Vec3::new(1., 1., 0.),
+      Vec3::new(0., 1., 0.),
+    ];
+    let mut mesh = DCEL::new(verts, &[vec![0, 1, 2], vec![0, 2, 3]]).unwrap();
+    mesh.validate();
+    assert_eq!(mesh.half_edges.len(), 10);
+    assert_eq!(mesh.vertices[0].adj_verts(&mesh).count(), 3);
+    let f0 = &mesh.faces[0];
+    assert!(f0.adj_edges(&mesh).all(|he| he.face(&mesh) == Some(f0)));
+    let he = mesh.half_edges[0];
+    mesh.split_half_edge(he, 0.5);
+    mesh.validate();
+    assert_eq!(mesh.faces[0].adj_verts(&mesh).count(), 4);
+  }
+  #[test]
+  fn bowtie() {
+    // Two triangles which only share vertex 0
+    let verts = vec![
+      Vec3::new(0., 0., 0.),
+      Vec3::new(1., 0., 0.),
+      Vec3::new(1., 1., 0.),
+      Vec3::new(-1., 0., 0.),
+      Vec3::new(-1., -1., 0.),
+    ];
+    let mesh = DCEL::new(verts, &[vec![0, 1, 2], vec![0, 3, 4]]).unwrap();
+    mesh.validate();
+    assert_eq!(mesh.vertices.len(), 6);
+    assert_eq!(mesh.vertices[5].pos, Vec3::of(0.));
+    assert_eq!(mesh.face_lists()[1], vec![5, 3, 4]);
+  }
+  #[test]
+  fn invalid_meshes() {
+    let verts = || (0..4).map(|i| Vec3::of(i as f32)).collect::<Vec<_>>();
+    assert!(DCEL::new(verts(), &[]).is_err());
+    assert!(DCEL::new(verts(), &[vec![0, 1]]).is_err());
+    assert!(DCEL::new(verts(), &[vec![0, 1, 4]]).is_err());
+    assert!(DCEL::new(verts(), &[vec![0, 1, 2, 1]]).is_err());
+    // inconsistently oriented
+    assert!(DCEL::new(verts(), &[vec![0, 1, 2], vec![0, 1, 3]]).is_err());
+    // three faces on one edge
+    let faces = [vec![0, 1, 2], vec![1, 0, 3], vec![0, 1, 3]];
+    assert!(DCEL::new(verts(), &faces).is_err());
+  }
+}
@@ -0,0 +1,303 @@
+use crate::{spectrum::Spectral, texture::Texture};
+use quick_maths::{Vec2, Vec3, Vector};
+use std::collections::HashMap;
+
+/// Upper limit on how many times edges are split when tessellating adaptively
+const MAX_LEVELS: u32 = 10;
+
+/// How finely to tessellate a mesh before displacing it
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum Tessellation {
+  /// Splits every triangle into four some number of times
+  Uniform(u32),
+  /// Splits edges until they are at most some length, measured in pixels on the film when there
+  /// is a camera and in object space otherwise.
+  Adaptive(f32),
+}
+
+/// Displacement of a mesh along its normals by a scalar texture
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct Builder {
+  /// Image whose luminance is the displacement
+  pub texture: String,
+  /// Distance displaced by a texture value of 1, defaults to 1
+  pub scale: Option<f32>,
+  pub tessellation: Tessellation,
+}
+
+/// A vertex with all of its attributes, so that it can be split along edges
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vertex {
+  pub pos: Vec3,
+  pub normal: Vec3,
+  pub uv: Vec2,
+}
+
+impl Vertex {
+  fn midpoint(&self, o: &Self) -> Self {
+    let normal = self.normal + o.normal;
+    Self {
+      pos: (self.pos + o.pos) / 2.,
+      normal: if normal.sqr_magn() > 0. {
+        normal.norm()
+      } else {
+        self.normal
+      },
+      uv: (self.uv + o.uv) / 2.,
+    }
+  }
+}
+
+/// Computes smooth normals for each vertex by summing area weighted face normals
+pub fn vertex_normals(pos: &[Vec3], tris: &[Vec3<u32>]) -> Vec<Vec3> {
+  let mut normals = vec![Vec3::of(0.); pos.len()];
+  for &Vector([a, b, c]) in tris {
+    let (a, b, c) = (a as usize, b as usize, c as usize);
+    // the magnitude of the cross product is twice the area
+    let n = (pos[b] - pos[a]).cross(&(pos[c] - pos[a]));
+    for &i in &[a, b, c] {
+      normals[i] = normals[i] + n;
+    }
+  }
+  normals
+    .into_iter()
+    .map(|n| if n.sqr_magn() > 0. { n.norm() } else { n })
+    .collect()
+}
+
+/// Computes normals which are only smoothed between faces meeting at less than the crease angle
+/// in degrees, or everywhere if there is none. Vertices are split where they need more than one
+/// normal, so this returns new positions, normals and triangles.
+pub fn crease_normals(
+  pos: &[Vec3],
+  tris: &[Vec3<u32>],
+  crease_angle: Option<f32>,
+) -> (Vec<Vec3>, Vec<Vec3>, Vec<Vec3<u32>>) {
+  // area weighted like vertex_normals
+  let face_normals = tris
+    .iter()
+    .map(|&Vector([a, b, c])| {
+      let (a, b, c) = (pos[a as usize], pos[b as usize], pos[c as usize]);
+      (b - a).cross(&(c - a))
+    })
+    .collect::<Vec<_>>();
+  let mut adj_faces = vec![vec![]; pos.len()];
+  for (t, Vector(vs)) in tris.iter().enumerate() {
+    for &v in vs {
+      adj_faces[v as usize].push(t);
+    }
+  }
+  let min_cos = crease_angle.map(|deg| deg.to_radians().cos());
+  let smooth = |a: &Vec3, b: &Vec3| match min_cos {
+    None => true,
+    Some(min_cos) => a.dot(b) >= min_cos * a.magn() * b.magn(),
+  };
+  let mut idxs = HashMap::new();
+  let (mut verts, mut normals) = (vec![], vec![]);
+  let tris = tris
+    .iter()
+    .enumerate()
+    .map(|(t, Vector(vs))| {
+      let mut tri = [0; 3];
+      for (slot, &v) in tri.iter_mut().zip(vs) {
+        let n = adj_faces[v as usize]
+          .iter()
+          .map(|&o| face_normals[o])
+          .filter(|n| smooth(&face_normals[t], n))
+          .fold(Vec3::of(0.), |acc, n| acc + n);
+        let n = if n.sqr_magn() > 0. { n.norm() } else { n };
+        let key = (v, [n.x().to_bits(), n.y().to_bits(), n.z().to_bits()]);
+        *slot = *idxs.entry(key).or_insert_with(|| {
+          verts.push(pos[v as usize]);
+          normals.push(n);
+          verts.len() as u32 - 1
+        });
+      }
+      Vector(tri)
+    })
+    .collect();
+  (verts, normals, tris)
+}
+
+/// Splits triangles until every edge is at most `max_len` as measured by `edge_len`, or for
+/// some number of levels if uniform. Whether an edge is split only depends on the positions of its
+/// endpoints, so neighboring triangles always agree even across seams where they do not share
+/// vertices.
+pub fn tessellate(
+  mut verts: Vec<Vertex>,
+  mut tris: Vec<Vec3<u32>>,
+  tessellation: Tessellation,
+  edge_len: impl Fn(Vec3, Vec3) -> f32,
+) -> (Vec<Vertex>, Vec<Vec3<u32>>) {
+  let (levels, max_len) = match tessellation {
+    Tessellation::Uniform(levels) => (levels, None),
+    Tessellation::Adaptive(max_len) => (MAX_LEVELS, Some(max_len)),
+  };
+  for _ in 0..levels {
+    let mut midpoints: HashMap<(u32, u32), Option<u32>> = HashMap::new();
+    let mut mid = |verts: &mut Vec<Vertex>, a: u32, b: u32| {
+      *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+        let (va, vb) = (verts[a as usize], verts[b as usize]);
+        if max_len.map_or(false, |max_len| edge_len(va.pos, vb.pos) <= max_len) {
+          return None;
+        }
+        verts.push(va.midpoint(&vb));
+        Some(verts.len() as u32 - 1)
+      })
+    };
+    let mut next = Vec::with_capacity(tris.len());
+    for &Vector(v) in &tris {
+      let m = [
+        mid(&mut verts, v[0], v[1]),
+        mid(&mut verts, v[1], v[2]),
+        mid(&mut verts, v[2], v[0]),
+      ];
+      match m.iter().filter(|m| m.is_some()).count() {
+        0 => next.push(Vector(v)),
+        1 => {
+          // rotate so that the split edge is from a to b
+          let i = m.iter().position(Option::is_some).unwrap();
+          let (a, b, c) = (v[i], v[(i + 1) % 3], v[(i + 2) % 3]);
+          let ab = m[i].unwrap();
+          next.push(Vec3::new(a, ab, c));
+          next.push(Vec3::new(ab, b, c));
+        },
+        2 => {
+          // rotate so that the edge which is not split is from c to a
+          let i = (m.iter().position(Option::is_none).unwrap() + 1) % 3;
+          let (a, b, c) = (v[i], v[(i + 1) % 3], v[(i + 2) % 3]);
+          let (ab, bc) = (m[i].unwrap(), m[(i + 1) % 3].unwrap());
+          next.push(Vec3::new(ab, b, bc));
+          next.push(Vec3::new(a, ab, bc));
+          next.push(Vec3::new(a, bc, c));
+        },
+        _ => {
+          let [ab, bc, ca] = [m[0].unwrap(), m[1].unwrap(), m[2].unwrap()];
+          next.push(Vec3::new(v[0], ab, ca));
+          next.push(Vec3::new(ab, v[1], bc));
+          next.push(Vec3::new(ca, bc, v[2]));
+          next.push(Vec3::new(ab, bc, ca));
+        },
+      }
+    }
+    let done = next.len() == tris.len();
+    tris = next;
+    if done {
+      break;
+    }
+  }
+  (verts, tris)
+}
+
+/// Moves each vertex along its normal by the luminance of the texture at its uv times scale,
+/// then recomputes normals from the displaced surface. Vertices which share a position, such as
+/// along seams of texture coordinates or normals, are moved to the average of where each would be
+/// displaced so that no cracks open along seams.
+pub fn displace(verts: &mut [Vertex], tris: &[Vec3<u32>], texture: &dyn Texture, scale: f32) {
+  let key = |p: &Vec3| [p.x().to_bits(), p.y().to_bits(), p.z().to_bits()];
+  let mut welded: HashMap<[u32; 3], usize> = HashMap::new();
+  let mut sums: Vec<(Vec3, f32)> = vec![];
+  let of_vert = verts
+    .iter()
+    .map(|v| {
+      let moved = v.pos + v.normal * (texture.sample(v.uv).luminance() * scale);
+      let i = *welded.entry(key(&v.pos)).or_insert_with(|| {
+        sums.push((Vec3::of(0.), 0.));
+        sums.len() - 1
+      });
+      sums[i] = (sums[i].0 + moved, sums[i].1 + 1.);
+      i
+    })
+    .collect::<Vec<_>>();
+  let pos = sums.into_iter().map(|(sum, n)| sum / n).collect::<Vec<_>>();
+  // normals are also smoothed across seams
+  let welded_tris = tris
+    .iter()
+    .map(|&Vector([a, b, c])| {
+      let w = |i: u32| of_vert[i as usize] as u32;
+      Vec3::new(w(a), w(b), w(c))
+    })
+    .collect::<Vec<_>>();
+  let normals = vertex_normals(&pos, &welded_tris);
+  for (v, &i) in verts.iter_mut().zip(&of_vert) {
+    v.pos = pos[i];
+    v.normal = normals[i];
+  }
+}
+
+#[cfg(test)]
+mod test_displace {
+  use super::*;
+  use crate::texture::constant::Constant;
+  fn quad() -> (Vec<Vertex>, Vec<Vec3<u32>>) {
+    let vert = |x: f32, z: f32| Vertex {
+      pos: Vec3::new(x, 0., z),
+      normal: Vec3::new(0., 1., 0.),
+      uv: Vec2::new(x, z),
+    };
+    let verts = vec![vert(0., 0.), vert(1., 0.), vert(1., 1.), vert(0., 1.)];
+    (verts, vec![Vec3::new(0, 2, 1), Vec3::new(0, 3, 2)])
+  }
+  #[test]
+  fn uniform_and_adaptive() {
+    let (verts, tris) = quad();
+    let uniform = Tessellation::Uniform(2);
+    let (_, uniform) = tessellate(verts.clone(), tris.clone(), uniform, |_, _| 0.);
+    assert_eq!(uniform.len(), 2 * 16);
+    let len = |a: Vec3, b: Vec3| (a - b).magn();
+    let (verts, adaptive) = tessellate(verts, tris, Tessellation::Adaptive(0.3), len);
+    for &Vector([a, b, c]) in &adaptive {
+      let p = |i: u32| verts[i as usize].pos;
+      assert!(len(p(a), p(b)) <= 0.3 && len(p(b), p(c)) <= 0.3 && len(p(c), p(a)) <= 0.3);
+    }
+  }
+  #[test]
+  fn constant_displacement() {
+    let (mut verts, tris) = quad();
+    displace(
+      &mut verts,
+      &tris,
+      &Constant::new(Spectral::from_mono(0.5)),
+      2.,
+    );
+    for v in &verts {
+      assert!((v.pos.y() - 1.).abs() < 1e-5);
+      assert!((v.normal - Vec3::new(0., 1., 0.)).magn() < 1e-5);
+    }
+  }
+  #[test]
+  fn creases_split_normals() {
+    // two triangles folded at a right angle along the x-axis
+    let pos = [
+      Vec3::new(0., 0., 0.),
+      Vec3::new(1., 0., 0.),
+      Vec3::new(0., 0., 1.),
+      Vec3::new(0., 1., 0.),
+    ];
+    let tris = [Vec3::new(0, 2, 1), Vec3::new(0, 1, 3)];
+    let (verts, normals, _) = crease_normals(&pos, &tris, None);
+    assert_eq!(verts.len(), 4);
+    assert!((normals[0] - Vec3::new(0., 1., 1.).norm()).magn() < 1e-5);
+    let (verts, normals, _) = crease_normals(&pos, &tris, Some(45.));
+    assert_eq!(verts.len(), 6);
+    assert!((normals[0] - Vec3::new(0., 1., 0.)).magn() < 1e-5);
+  }
+  #[test]
+  fn seams_stay_closed() {
+    // the quad with its diagonal split into separate vertices with different normals
+    let (mut verts, _) = quad();
+    verts.push(verts[0]);
+    verts.push(verts[2]);
+    verts[4].normal = Vec3::new(0., 1., 1.).norm();
+    let tris = [Vec3::new(0, 2, 1), Vec3::new(4, 3, 5)];
+    displace(
+      &mut verts,
+      &tris,
+      &Constant::new(Spectral::from_mono(1.)),
+      1.,
+    );
+    assert_eq!(verts[0].pos, verts[4].pos);
+    assert_eq!(verts[2].pos, verts[5].pos);
+    assert_eq!(verts[0].normal, verts[4].normal);
+  }
+}
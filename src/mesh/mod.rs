@@ -0,0 +1,8 @@
+/// Half edge mesh
+pub mod dcel;
+/// Displacement of triangle meshes by textures
+pub mod displace;
+/// Subdivision surfaces over half edge meshes
+pub mod subdivide;
+
+pub use dcel::DCEL;
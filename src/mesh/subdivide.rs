@@ -0,0 +1,309 @@
+use super::dcel::{HalfEdge, DCEL};
+use quick_maths::Vec3;
+use std::io;
+
+/// Subdivision scheme for smoothing meshes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Scheme {
+  /// Catmull-Clark subdivision, which works on any polygons and produces quads
+  CatmullClark,
+  /// Loop subdivision, which works on triangles and produces triangles
+  Loop,
+}
+
+impl Default for Scheme {
+  fn default() -> Self { Scheme::CatmullClark }
+}
+
+/// Information about the edges of a mesh shared by both schemes
+struct Edges {
+  /// Maps each half edge to the index of its edge
+  of_half_edge: Vec<usize>,
+  /// One half edge for each edge
+  half_edges: Vec<HalfEdge>,
+  /// Whether each edge is on the boundary or sharper than the crease angle
+  sharp: Vec<bool>,
+}
+
+impl Edges {
+  fn new(mesh: &DCEL, crease_angle: Option<f32>) -> Self {
+    let hes = mesh.half_edges();
+    let mut of_half_edge = vec![0; hes.len()];
+    let mut half_edges = vec![];
+    let mut sharp = vec![];
+    let normals = mesh
+      .faces()
+      .iter()
+      .map(|f| f.normal(mesh))
+      .collect::<Vec<_>>();
+    // Faces whose normals differ by more than the crease angle are creased
+    let min_cos = crease_angle.map(|deg| deg.to_radians().cos());
+    for he in hes {
+      let opp = he.opp(mesh);
+      if opp.id() < he.id() {
+        of_half_edge[he.id()] = of_half_edge[opp.id()];
+        continue;
+      }
+      of_half_edge[he.id()] = half_edges.len();
+      half_edges.push(*he);
+      let is_sharp = match (he.face_id(), opp.face_id(), min_cos) {
+        (Some(a), Some(b), Some(min_cos)) => normals[a].dot(&normals[b]) < min_cos,
+        (Some(_), Some(_), None) => false,
+        _ => true,
+      };
+      sharp.push(is_sharp);
+    }
+    Self {
+      of_half_edge,
+      half_edges,
+      sharp,
+    }
+  }
+  fn len(&self) -> usize { self.half_edges.len() }
+  fn is_sharp(&self, he: &HalfEdge) -> bool { self.sharp[self.of_half_edge[he.id()]] }
+}
+
+/// Returns the new position of a vertex on sharp edges, or none if it should be smoothed.
+/// Vertices with exactly two sharp edges slide along them, and more are kept as corners.
+fn sharp_vertex(mesh: &DCEL, edges: &Edges, v: usize, (self_w, adj_w): (f32, f32)) -> Option<Vec3> {
+  let vert = &mesh.vertices()[v];
+  let sharp = vert
+    .adj_edges(mesh)
+    .filter(|he| edges.is_sharp(he))
+    .map(|he| he.opp(mesh).vert(mesh).pos)
+    .collect::<Vec<_>>();
+  match sharp.as_slice() {
+    [] | [_] => None,
+    [a, b] => Some(vert.pos * self_w + (*a + *b) * adj_w),
+    _ => Some(vert.pos),
+  }
+}
+
+/// Applies one level of subdivision to a mesh, where each new face is in the group of the face
+/// it was made from.
+pub fn subdivide(mesh: &DCEL, scheme: Scheme, crease_angle: Option<f32>) -> io::Result<DCEL> {
+  match scheme {
+    Scheme::CatmullClark => catmull_clark(mesh, crease_angle),
+    Scheme::Loop => loop_subdivide(mesh, crease_angle),
+  }
+}
+
+/// Applies some number of levels of subdivision to a mesh
+pub fn subdivide_n(
+  mut mesh: DCEL,
+  levels: u32,
+  scheme: Scheme,
+  crease_angle: Option<f32>,
+) -> io::Result<DCEL> {
+  for _ in 0..levels {
+    mesh = subdivide(&mesh, scheme, crease_angle)?;
+  }
+  Ok(mesh)
+}
+
+pub fn catmull_clark(mesh: &DCEL, crease_angle: Option<f32>) -> io::Result<DCEL> {
+  let edges = Edges::new(mesh, crease_angle);
+  let verts = mesh.vertices();
+  let face_points = mesh
+    .faces()
+    .iter()
+    .map(|f| f.centroid(mesh))
+    .collect::<Vec<_>>();
+  let edge_points = edges
+    .half_edges
+    .iter()
+    .zip(edges.sharp.iter())
+    .map(|(he, &sharp)| {
+      let [a, b] = he.adj_verts(mesh);
+      let mid = (a.pos + b.pos) / 2.;
+      if sharp {
+        return mid;
+      }
+      let [f0, f1] = he.adj_face_ids(mesh);
+      (mid + (face_points[f0] + face_points[f1]) / 2.) / 2.
+    })
+    .collect::<Vec<_>>();
+  let vert_points = (0..verts.len()).map(|v| {
+    if let Some(p) = sharp_vertex(mesh, &edges, v, (0.75, 0.125)) {
+      return p;
+    }
+    let p = verts[v].pos;
+    let (mut f, mut r, mut n) = (Vec3::of(0.), Vec3::of(0.), 0.);
+    for he in verts[v].adj_edges(mesh) {
+      // adjacent edges here all have faces since the vertex is not on the boundary
+      f = f + face_points[he.face_id().unwrap()];
+      r = r + (p + he.opp(mesh).vert(mesh).pos) / 2.;
+      n += 1.;
+    }
+    if n == 0. {
+      // isolated vertex
+      return p;
+    }
+    (f / n + r * (2. / n) + p * (n - 3.)) / n
+  });
+  // New vertices are ordered as original, then edge, then face points
+  let edge_start = verts.len();
+  let face_start = edge_start + edges.len();
+  let positions = vert_points
+    .chain(edge_points.into_iter())
+    .chain(face_points.into_iter())
+    .collect::<Vec<_>>();
+  let faces = mesh
+    .faces()
+    .iter()
+    .enumerate()
+    .flat_map(|(i, f)| {
+      let hes = f.adj_edges(mesh).collect::<Vec<_>>();
+      let k = hes.len();
+      (0..k)
+        .map(|j| {
+          let (prev, curr) = (hes[(j + k - 1) % k], hes[j]);
+          vec![
+            curr.vert_id(),
+            edge_start + edges.of_half_edge[curr.id()],
+            face_start + i,
+            edge_start + edges.of_half_edge[prev.id()],
+          ]
+        })
+        .collect::<Vec<_>>()
+    })
+    .collect::<Vec<_>>();
+  // each face is split into one quad per side
+  let groups = mesh
+    .faces()
+    .iter()
+    .zip(mesh.groups())
+    .flat_map(|(f, &g)| std::iter::repeat(g).take(f.adj_edges(mesh).count()))
+    .collect();
+  Ok(DCEL::new(positions, &faces)?.with_groups(groups))
+}
+
+pub fn loop_subdivide(mesh: &DCEL, crease_angle: Option<f32>) -> io::Result<DCEL> {
+  if mesh.faces().iter().any(|f| f.adj_edges(mesh).count() != 3) {
+    let mut tris = mesh.clone();
+    tris.triangulate()?;
+    return loop_subdivide(&tris, crease_angle);
+  }
+  let edges = Edges::new(mesh, crease_angle);
+  let verts = mesh.vertices();
+  let edge_points = edges
+    .half_edges
+    .iter()
+    .zip(edges.sharp.iter())
+    .map(|(he, &sharp)| {
+      let [a, b] = he.adj_verts(mesh);
+      if sharp {
+        return (a.pos + b.pos) / 2.;
+      }
+      // vertices opposite to this edge in each triangle
+      let c = he.next(mesh).next(mesh).vert(mesh).pos;
+      let d = he.opp(mesh).next(mesh).next(mesh).vert(mesh).pos;
+      (a.pos + b.pos) * 0.375 + (c + d) * 0.125
+    })
+    .collect::<Vec<_>>();
+  let vert_points = (0..verts.len()).map(|v| {
+    if let Some(p) = sharp_vertex(mesh, &edges, v, (0.75, 0.125)) {
+      return p;
+    }
+    let p = verts[v].pos;
+    let (sum, n) = verts[v]
+      .adj_verts(mesh)
+      .fold((Vec3::of(0.), 0.), |(acc, n), a| (acc + a.pos, n + 1.));
+    if n == 0. {
+      return p;
+    }
+    let beta = if n == 3. { 3. / 16. } else { 3. / (8. * n) };
+    p * (1. - n * beta) + sum * beta
+  });
+  let edge_start = verts.len();
+  let positions = vert_points
+    .chain(edge_points.into_iter())
+    .collect::<Vec<_>>();
+  let edge_vert = |he: &HalfEdge| edge_start + edges.of_half_edge[he.id()];
+  let faces = mesh
+    .faces()
+    .iter()
+    .flat_map(|f| {
+      let hes = f.adj_edges(mesh).collect::<Vec<_>>();
+      let (e0, e1, e2) = (edge_vert(hes[0]), edge_vert(hes[1]), edge_vert(hes[2]));
+      vec![
+        vec![hes[0].vert_id(), e0, e2],
+        vec![hes[1].vert_id(), e1, e0],
+        vec![hes[2].vert_id(), e2, e1],
+        vec![e0, e1, e2],
+      ]
+    })
+    .collect::<Vec<_>>();
+  let groups = mesh
+    .groups()
+    .iter()
+    .flat_map(|&g| std::iter::repeat(g).take(4))
+    .collect();
+  Ok(DCEL::new(positions, &faces)?.with_groups(groups))
+}
+
+/// Converts a mesh into a list of vertices and triangles, fanning polygons into triangles, along
+/// with the group of each triangle.
+pub fn to_triangles(mesh: &DCEL) -> (Vec<Vec3>, Vec<Vec3<u32>>, Vec<usize>) {
+  let verts = mesh.vertices().iter().map(|v| v.pos).collect();
+  let (tris, groups) = mesh
+    .face_lists()
+    .into_iter()
+    .zip(mesh.groups().iter())
+    .flat_map(|(f, &g)| {
+      (1..f.len() - 1)
+        .map(|i| (Vec3::new(f[0] as u32, f[i] as u32, f[i + 1] as u32), g))
+        .collect::<Vec<_>>()
+    })
+    .unzip();
+  (verts, tris, groups)
+}
+
+#[cfg(test)]
+mod test_subdivide {
+  use super::*;
+  fn cube() -> DCEL {
+    let verts = (0..8)
+      .map(|i| Vec3::new((i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32) * 2. - 1.)
+      .collect();
+    let faces = [
+      vec![0, 2, 3, 1],
+      vec![4, 5, 7, 6],
+      vec![0, 1, 5, 4],
+      vec![2, 6, 7, 3],
+      vec![0, 4, 6, 2],
+      vec![1, 3, 7, 5],
+    ];
+    DCEL::new(verts, &faces).unwrap()
+  }
+  #[test]
+  fn catmull_clark_cube() {
+    let out = catmull_clark(&cube(), None).unwrap();
+    out.validate();
+    assert_eq!(out.vertices().len(), 8 + 12 + 6);
+    assert_eq!(out.faces().len(), 24);
+    // corners are pulled in towards the center
+    let corner = out.vertices()[0].pos;
+    assert!(corner.magn() < Vec3::of(1.).magn());
+    // but not if every edge is a crease
+    let sharp = catmull_clark(&cube(), Some(45.)).unwrap();
+    assert_eq!(sharp.vertices()[0].pos, Vec3::of(-1.));
+  }
+  #[test]
+  fn loop_cube() {
+    let out = loop_subdivide(&cube(), None).unwrap();
+    out.validate();
+    assert_eq!(out.faces().len(), 12 * 4);
+  }
+  #[test]
+  fn groups_are_kept() {
+    let groups = vec![0, 1, 1, 2, 2, 2];
+    let out = catmull_clark(&cube().with_groups(groups.clone()), None).unwrap();
+    assert_eq!(out.groups()[..8], [0, 0, 0, 0, 1, 1, 1, 1]);
+    let out = loop_subdivide(&cube().with_groups(groups), None).unwrap();
+    // each quad is split into two triangles and then four each
+    assert_eq!(out.groups().iter().filter(|&&g| g == 2).count(), 3 * 2 * 4);
+    let (_, tris, groups) = to_triangles(&out);
+    assert_eq!(tris.len(), groups.len());
+  }
+}
@@ -1,30 +1,88 @@
-use crate::spectrum::{Luminance, Spectrum, RGB};
+use super::{
+  nanometers_to_cie,
+  spd::{Illuminant, Spd},
+  CIE_TO_SRGB,
+};
+use crate::spectrum::{self, Luminance, Spectral, Spectrum, RGB};
+use serde::Deserialize;
+use std::{convert::TryFrom, io};
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub enum Builder {
   Monochrome(Luminance),
   RGB(RGB),
   Wavelength(f32),
-  // TODO more here
+  /// Blackbody emitter at a temperature in Kelvin, normalized to a peak of 1 by default
+  Blackbody {
+    temperature: f32,
+    normalize: Option<bool>,
+  },
+  /// Tabulated spectral power distribution file
+  Spd(String),
+  /// Standard CIE illuminant
+  Illuminant(Illuminant),
 }
 
-impl From<Builder> for Spectrum {
-  fn from(b: Builder) -> Self {
-    use Builder::*;
-    cfg_if::cfg_if! {
-      if #[cfg(feature="mono")] {
-        match b {
-          Monochrome(l) => l,
-          RGB(rgb) => super::srgb_to_gray(rgb),
-          Wavelength(_w) => todo!()
-        }
-      } else {
-        match b {
-          Monochrome(l) => crate::spectrum::RGB::of(l),
-          RGB(rgb) => rgb,
-          Wavelength(_w) => todo!()
-        }
-      }
-    }
+impl Builder {
+  /// Returns the spectral power distribution of this builder if it is defined by one
+  pub fn spd(&self) -> io::Result<Option<Spd>> {
+    Ok(Some(match self {
+      Builder::Monochrome(_) | Builder::RGB(_) | Builder::Wavelength(_) => return Ok(None),
+      &Builder::Blackbody {
+        temperature,
+        normalize,
+      } => Spd::Blackbody {
+        temperature,
+        normalized: normalize.unwrap_or(true),
+      },
+      Builder::Spd(src) => Spd::from_file(src)?,
+      Builder::Illuminant(i) => i.spd(),
+    }))
+  }
+  pub fn build(self) -> io::Result<Spectrum> {
+    Ok(match self {
+      Builder::Monochrome(l) => spectrum::from_mono(l),
+      Builder::RGB(rgb) => spectrum::from_rgb(rgb),
+      Builder::Wavelength(w) => spectrum::from_rgb(CIE_TO_SRGB.dot(&nanometers_to_cie(w)).max(0.)),
+      b => match b.spd()? {
+        Some(spd) => Spectrum::from_spd(&spd),
+        None => unreachable!(),
+      },
+    })
+  }
+}
+
+impl TryFrom<Builder> for Spectrum {
+  type Error = io::Error;
+  fn try_from(b: Builder) -> io::Result<Self> { b.build() }
+}
+
+/// All the ways a spectrum can be written in a scene file
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum SpectrumRepr {
+  Raw(Spectrum),
+  RGB(RGB),
+  Builder(Builder),
+}
+
+/// Deserializes a spectrum from either its raw representation, an RGB triple or a builder.
+pub fn deserialize_spectrum<'de, D>(d: D) -> Result<Spectrum, D::Error>
+where
+  D: serde::Deserializer<'de>, {
+  Ok(match SpectrumRepr::deserialize(d)? {
+    SpectrumRepr::Raw(s) => s,
+    SpectrumRepr::RGB(rgb) => spectrum::from_rgb(rgb),
+    SpectrumRepr::Builder(b) => b.build().map_err(serde::de::Error::custom)?,
+  })
+}
+
+#[cfg(test)]
+mod test_builder {
+  use super::*;
+  #[test]
+  fn missing_spd_file() {
+    let b = Builder::Spd(String::from("mireba_test_missing.spd"));
+    assert!(Spectrum::try_from(b).is_err());
   }
 }
@@ -28,17 +28,20 @@ pub fn cie_to_srgb(cie: &CIE) -> RGB {
     .min(1.)
 }
 
-pub fn wavelength_to_cie(w: f32) -> CIE {
+/// Returns the CIE 1931 color matching functions at a wavelength in angstroms
+pub fn wavelength_to_cie(w: f32) -> CIE { nanometers_to_cie(w / 10.) }
+
+/// Returns the CIE 1931 color matching functions at a wavelength in nanometers
+pub fn nanometers_to_cie(w: f32) -> CIE {
   // https://en.wikipedia.org/wiki/CIE_1931_color_space
   CIE::new(
-    gaussian(w, 1.056, 5998., 379., 310.)
-      + gaussian(w, 0.362, 4420., 160., 267.)
-      + gaussian(w, -0.065, 5011., 204., 262.),
-    gaussian(w, 0.821, 5688., 469., 405.) + gaussian(w, 0.286, 5309., 163., 311.),
-    gaussian(w, 1.217, 4370., 118., 360.) + gaussian(w, 0.681, 4590., 260., 138.),
+    gaussian(w, 1.056, 599.8, 37.9, 31.0)
+      + gaussian(w, 0.362, 442.0, 16.0, 26.7)
+      + gaussian(w, -0.065, 501.1, 20.4, 26.2),
+    gaussian(w, 0.821, 568.8, 46.9, 40.5) + gaussian(w, 0.286, 530.9, 16.3, 31.1),
+    gaussian(w, 1.217, 437.0, 11.8, 36.0) + gaussian(w, 0.681, 459.0, 26.0, 13.8),
   )
-  .min(0.)
-  .max(1.)
+  .max(0.)
 }
 
 fn gaussian(x: f32, alpha: f32, mu: f32, sigma_1: f32, sigma_2: f32) -> f32 {
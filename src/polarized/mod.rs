@@ -1,5 +1,7 @@
 mod color;
 pub use color::*;
 mod builder;
+pub mod mueller;
+pub mod spd;
 pub mod stokes;
-pub use builder::Builder;
+pub use builder::{deserialize_spectrum, Builder};
@@ -0,0 +1,187 @@
+use super::stokes::Stokes;
+use num::Complex;
+use quick_maths::{Mat4, Matrix, Vec3, Vector};
+
+/// Mueller matrices transform one stokes vector into another
+pub type Mueller = Mat4;
+
+// XXX note that matrices are stored column major, so they're written out as rows here and
+// transposed.
+fn from_rows(rows: [[f32; 4]; 4]) -> Mueller {
+  Matrix(Vector::with(|c| Vector::with(|r| rows[r][c])))
+}
+
+/// Composes two mueller matrices, so that `b` is applied first and then `a`.
+pub fn compose(a: &Mueller, b: &Mueller) -> Mueller {
+  Matrix(Vector::with(|c| {
+    Vector::with(|r| (0..4).map(|k| a.0[k][r] * b.0[c][k]).sum())
+  }))
+}
+
+/// Applies a mueller matrix to a stokes vector
+pub fn apply(m: &Mueller, s: &Stokes) -> Stokes {
+  Vector::with(|r| (0..4).map(|k| m.0[k][r] * s[k]).sum())
+}
+
+pub fn identity() -> Mueller { from_rows(IDENTITY) }
+
+const IDENTITY: [[f32; 4]; 4] = [[1., 0., 0., 0.], [0., 1., 0., 0.], [0., 0., 1., 0.], [
+  0., 0., 0., 1.,
+]];
+
+/// Scales intensity and removes any polarization
+pub fn depolarizer(v: f32) -> Mueller {
+  from_rows([[v, 0., 0., 0.], [0., 0., 0., 0.], [0., 0., 0., 0.], [
+    0., 0., 0., 0.,
+  ]])
+}
+
+/// Ideal linear polarizer with its transmission axis along the horizontal of the stokes basis
+pub fn linear_polarizer(v: f32) -> Mueller {
+  let h = v * 0.5;
+  from_rows([[h, h, 0., 0.], [h, h, 0., 0.], [0., 0., 0., 0.], [
+    0., 0., 0., 0.,
+  ]])
+}
+
+/// Mueller matrix which re-expresses a stokes vector in a reference frame rotated
+/// counter-clockwise by theta radians about the direction of travel.
+pub fn rotator(theta: f32) -> Mueller {
+  let (s, c) = (2.0 * theta).sin_cos();
+  from_rows([[1., 0., 0., 0.], [0., c, s, 0.], [0., -s, c, 0.], [
+    0., 0., 0., 1.,
+  ]])
+}
+
+/// Rotates an optical element (such as a polarizer) by theta radians about the direction of
+/// travel.
+pub fn rotated_element(theta: f32, m: &Mueller) -> Mueller {
+  compose(&rotator(-theta), &compose(m, &rotator(theta)))
+}
+
+/// Returns the mueller matrix converting stokes vectors of light travelling along `w` from the
+/// basis `from` into the basis `to`. `to` does not need to be perpendicular to `w`, it will be
+/// projected first.
+pub fn rotate_stokes_basis(w: &Vec3, from: &Vec3, to: &Vec3) -> Mueller {
+  let to = *to - *w * w.dot(to);
+  let theta = w.dot(&from.cross(&to)).atan2(from.dot(&to));
+  rotator(theta)
+}
+
+/// Re-expresses a mueller matrix which expects incoming stokes vectors in `in_local` and outputs
+/// them in `out_local`, so that it acts on vectors in `in_world` and outputs in `out_world`.
+pub fn change_basis(
+  m: &Mueller,
+  (in_fwd, in_local, in_world): (&Vec3, &Vec3, &Vec3),
+  (out_fwd, out_local, out_world): (&Vec3, &Vec3, &Vec3),
+) -> Mueller {
+  let to_local = rotate_stokes_basis(in_fwd, in_world, in_local);
+  let from_local = rotate_stokes_basis(out_fwd, out_local, out_world);
+  compose(&from_local, &compose(m, &to_local))
+}
+
+/// Returns the s-polarization axis for light travelling along `d` hitting a surface with normal
+/// `n`, i.e. the axis perpendicular to the plane of incidence.
+pub fn s_axis(n: &Vec3, d: &Vec3) -> Vec3 {
+  let s = n.cross(d);
+  if s.sqr_magn() < 1e-10 {
+    // Normal incidence, the plane of incidence is not well defined so pick any axis.
+    return super::stokes::stokes_basis(d);
+  }
+  s.norm()
+}
+
+/// Complex fresnel amplitude coefficients (r_s, r_p) for light arriving at a cosine of cos_i
+/// onto a material with relative complex index of refraction eta.
+pub fn fresnel_amplitudes(cos_i: f32, eta: Complex<f32>) -> (Complex<f32>, Complex<f32>) {
+  let cos_i = cos_i.abs().min(1.0);
+  let sin_i_sqr = 1.0 - cos_i * cos_i;
+  let cos_i = Complex::new(cos_i, 0.);
+  // Snell's law with complex eta, which also handles total internal reflection
+  let cos_t = (Complex::new(1.0, 0.) - (eta * eta).inv() * sin_i_sqr).sqrt();
+  let r_s = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+  let r_p = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+  (r_s, r_p)
+}
+
+/// Mueller matrix for specular reflection with the stokes vectors expressed in the s-p basis.
+/// For dielectrics the imaginary component of eta is 0, for conductors it's the absorption
+/// coefficient k.
+pub fn specular_reflection(cos_i: f32, eta: Complex<f32>) -> Mueller {
+  let (r_s, r_p) = fresnel_amplitudes(cos_i, eta);
+  let (rs, rp) = (r_s.norm_sqr(), r_p.norm_sqr());
+  let a = 0.5 * (rs + rp);
+  let b = 0.5 * (rs - rp);
+  let cross = r_s * r_p.conj();
+  let (c, s) = (cross.re, cross.im);
+  from_rows([[a, b, 0., 0.], [b, a, 0., 0.], [0., 0., c, s], [
+    0., 0., -s, c,
+  ]])
+}
+
+/// Mueller matrix for specular transmission through a dielectric interface with the stokes
+/// vectors expressed in the s-p basis.
+pub fn specular_transmission(cos_i: f32, eta: f32) -> Mueller {
+  let cos_i = cos_i.abs().min(1.0);
+  let sin_t_sqr = (1.0 - cos_i * cos_i) / (eta * eta);
+  if sin_t_sqr >= 1.0 {
+    // Total internal reflection
+    return depolarizer(0.0);
+  }
+  let cos_t = (1.0 - sin_t_sqr).sqrt();
+  let t_s = 2.0 * cos_i / (cos_i + eta * cos_t);
+  let t_p = 2.0 * cos_i / (eta * cos_i + cos_t);
+  let factor = eta * cos_t / cos_i;
+  let (ts, tp) = (factor * t_s * t_s, factor * t_p * t_p);
+  let a = 0.5 * (ts + tp);
+  let b = 0.5 * (ts - tp);
+  let c = (ts * tp).sqrt();
+  from_rows([[a, b, 0., 0.], [b, a, 0., 0.], [0., 0., c, 0.], [
+    0., 0., 0., c,
+  ]])
+}
+
+#[cfg(test)]
+mod test_mueller {
+  use super::*;
+  use crate::polarized::stokes::{degree_of_polarization, linear, unpolarized};
+
+  #[test]
+  fn brewster_angle_polarizes() {
+    let eta = 1.5f32;
+    let cos_brewster = eta.atan().cos();
+    let reflected = apply(
+      &specular_reflection(cos_brewster, Complex::new(eta, 0.)),
+      &unpolarized(),
+    );
+    assert!((degree_of_polarization(&reflected) - 1.0).abs() < 1e-3);
+  }
+
+  #[test]
+  fn energy_is_conserved() {
+    let eta = 1.33;
+    for &cos_i in &[1.0, 0.8, 0.5, 0.1] {
+      let r = apply(
+        &specular_reflection(cos_i, Complex::new(eta, 0.)),
+        &unpolarized(),
+      );
+      let t = apply(&specular_transmission(cos_i, eta), &unpolarized());
+      assert!(
+        (r[0] + t[0] - 1.0).abs() < 1e-3,
+        "{} {} {}",
+        cos_i,
+        r[0],
+        t[0]
+      );
+    }
+  }
+
+  #[test]
+  fn crossed_polarizers_block() {
+    let s = apply(&linear_polarizer(1.0), &unpolarized());
+    let crossed = rotated_element(std::f32::consts::FRAC_PI_2, &linear_polarizer(1.0));
+    assert!(apply(&crossed, &s)[0].abs() < 1e-5);
+    let rotated = apply(&rotator(0.3), &linear(1.0, 0.5));
+    assert!((rotated[2] - linear(1.0, 0.2)[2]).abs() < 1e-5);
+  }
+}
@@ -0,0 +1,255 @@
+use super::{nanometers_to_cie, CIE, CIE_TO_SRGB};
+use crate::spectrum::RGB;
+use std::{
+  fs::File,
+  io::{self, BufRead},
+  path::Path,
+};
+
+/// Range over which spectra are integrated when converting to color
+pub const MIN_WAVELENGTH: f32 = 380.0;
+pub const MAX_WAVELENGTH: f32 = 780.0;
+const STEP: f32 = 5.0;
+
+/// A spectral power distribution over wavelengths in nanometers
+#[derive(Debug, Clone, PartialEq)]
+pub enum Spd {
+  /// Planck's law for an ideal emitter at a temperature in Kelvin.
+  /// If normalized, the peak of the distribution is 1.
+  Blackbody { temperature: f32, normalized: bool },
+  /// Linearly interpolated (wavelength, value) pairs sorted by wavelength
+  Tabulated(Vec<(f32, f32)>),
+}
+
+/// Standard CIE illuminants, all scaled to a peak of 1
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum Illuminant {
+  /// Noon daylight
+  D65,
+  /// Incandescent tungsten
+  A,
+  /// Cool white fluorescent
+  F2,
+  /// Broadband daylight fluorescent
+  F7,
+  /// Narrow tri-band fluorescent
+  F11,
+}
+
+/// Spectral radiance of a blackbody in W / (sr m^2 nm)
+pub fn planck(wavelength: f32, temperature: f32) -> f32 {
+  const H: f64 = 6.62606957e-34;
+  const C: f64 = 299792458.0;
+  const K_B: f64 = 1.3806488e-23;
+  let l = wavelength as f64 * 1e-9;
+  let t = temperature as f64;
+  let radiance = (2.0 * H * C * C) / (l.powi(5) * ((H * C / (l * K_B * t)).exp() - 1.0));
+  // per meter -> per nanometer
+  (radiance * 1e-9) as f32
+}
+
+impl Spd {
+  /// Loads a tabulated spd from a file with a wavelength and value on each line, separated by
+  /// whitespace or a comma. Lines starting with # are ignored.
+  pub fn from_file(p: impl AsRef<Path>) -> io::Result<Self> {
+    let f = File::open(p.as_ref())?;
+    let buf = io::BufReader::new(f);
+    let mut samples = vec![];
+    for line in buf.lines() {
+      let line = line?;
+      let parts = line
+        .splitn(2, '#')
+        .next()
+        .unwrap()
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>();
+      match parts.as_slice() {
+        [] => (),
+        [l, v] => {
+          let parse = |s: &str| {
+            s.parse::<f32>()
+              .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+          };
+          samples.push((parse(l)?, parse(v)?));
+        },
+        _ =>
+          return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Expected wavelength and value in SPD file, got {:?}", line),
+          )),
+      }
+    }
+    if samples.is_empty() {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "SPD file has no samples",
+      ));
+    }
+    Ok(Self::tabulated(samples))
+  }
+  pub fn tabulated(mut samples: Vec<(f32, f32)>) -> Self {
+    samples.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    Spd::Tabulated(samples)
+  }
+  /// Creates a tabulated spd from regularly spaced samples
+  pub fn regular(start: f32, step: f32, values: &[f32]) -> Self {
+    let samples = values
+      .iter()
+      .enumerate()
+      .map(|(i, &v)| (start + step * i as f32, v))
+      .collect();
+    Spd::Tabulated(samples)
+  }
+  /// Scales this spd so that its peak is 1
+  pub fn normalized(self) -> Self {
+    match self {
+      Spd::Blackbody { temperature, .. } => Spd::Blackbody {
+        temperature,
+        normalized: true,
+      },
+      Spd::Tabulated(samples) => {
+        let peak = samples.iter().fold(0f32, |peak, &(_, v)| peak.max(v));
+        if peak <= 0. {
+          return Spd::Tabulated(samples);
+        }
+        Spd::Tabulated(samples.into_iter().map(|(l, v)| (l, v / peak)).collect())
+      },
+    }
+  }
+  /// Evaluates this spd at a wavelength in nanometers
+  pub fn eval(&self, wavelength: f32) -> f32 {
+    match self {
+      &Spd::Blackbody {
+        temperature,
+        normalized,
+      } => {
+        let v = planck(wavelength, temperature);
+        if !normalized {
+          return v;
+        }
+        // Wien's displacement law gives the peak
+        let peak = 2.8977721e6 / temperature;
+        v / planck(peak, temperature)
+      },
+      Spd::Tabulated(samples) => {
+        let i = samples.iter().position(|&(l, _)| l >= wavelength);
+        match i {
+          None => samples.last().unwrap().1,
+          Some(0) => samples[0].1,
+          Some(i) => {
+            let (l0, v0) = samples[i - 1];
+            let (l1, v1) = samples[i];
+            let t = (wavelength - l0) / (l1 - l0);
+            v0 + (v1 - v0) * t
+          },
+        }
+      },
+    }
+  }
+  /// Integrates this spd against the CIE color matching functions. It is normalized such that
+  /// a constant spd of 1 has a luminance (Y) of 1.
+  pub fn to_cie(&self) -> CIE {
+    let steps = ((MAX_WAVELENGTH - MIN_WAVELENGTH) / STEP) as u32;
+    let (xyz, y_sum) = (0..=steps).map(|i| MIN_WAVELENGTH + STEP * i as f32).fold(
+      (CIE::of(0.0), 0.0),
+      |(xyz, y_sum), l| {
+        let cmf = nanometers_to_cie(l);
+        (xyz + cmf * self.eval(l), y_sum + cmf.y())
+      },
+    );
+    xyz / y_sum
+  }
+  /// Converts this spd into linear sRGB
+  pub fn to_rgb(&self) -> RGB { CIE_TO_SRGB.dot(&self.to_cie()).max(0.) }
+}
+
+impl Illuminant {
+  pub fn spd(&self) -> Spd {
+    match self {
+      Illuminant::D65 => Spd::regular(380., 10., &D65).normalized(),
+      Illuminant::A => Spd::Blackbody {
+        temperature: 2856.0,
+        normalized: true,
+      },
+      Illuminant::F2 => Spd::regular(380., 5., &F2).normalized(),
+      Illuminant::F7 => Spd::regular(380., 5., &F7).normalized(),
+      Illuminant::F11 => Spd::regular(380., 5., &F11).normalized(),
+    }
+  }
+}
+
+/// CIE D65 from 380nm to 780nm in 10nm steps
+const D65: [f32; 41] = [
+  49.9755, 54.6482, 82.7549, 91.486, 93.4318, 86.6823, 104.865, 117.008, 117.812, 114.861, 115.923,
+  108.811, 109.354, 107.802, 104.79, 107.689, 104.405, 104.046, 100.0, 96.3342, 95.788, 88.6856,
+  90.0062, 89.5991, 87.6987, 83.2886, 83.6992, 80.0268, 80.2146, 82.2778, 78.2842, 69.7213,
+  71.6091, 74.349, 61.604, 69.8856, 75.087, 63.5927, 46.4182, 66.8054, 63.3828,
+];
+
+/// CIE F2 from 380nm to 780nm in 5nm steps
+const F2: [f32; 81] = [
+  1.18, 1.48, 1.84, 2.15, 3.44, 15.69, 3.85, 3.74, 4.19, 4.62, 5.06, 34.98, 11.81, 6.27, 6.63,
+  6.93, 7.19, 7.40, 7.54, 7.62, 7.65, 7.62, 7.62, 7.45, 7.28, 7.15, 7.05, 7.04, 7.16, 7.47, 8.04,
+  8.88, 10.01, 24.88, 16.64, 14.59, 16.16, 17.56, 18.62, 21.47, 22.79, 19.29, 18.66, 17.73, 16.54,
+  15.21, 13.80, 12.36, 10.95, 9.65, 8.40, 7.32, 6.31, 5.43, 4.68, 4.02, 3.45, 2.96, 2.55, 2.19,
+  1.89, 1.64, 1.53, 1.27, 1.10, 0.99, 0.88, 0.76, 0.68, 0.61, 0.56, 0.54, 0.51, 0.47, 0.47, 0.43,
+  0.46, 0.47, 0.40, 0.33, 0.27,
+];
+
+/// CIE F7 from 380nm to 780nm in 5nm steps
+const F7: [f32; 81] = [
+  2.56, 3.18, 3.84, 4.53, 6.15, 19.37, 7.37, 7.05, 7.71, 8.41, 9.15, 44.14, 17.52, 11.35, 12.00,
+  12.58, 13.08, 13.45, 13.71, 13.88, 13.95, 13.93, 13.82, 13.64, 13.43, 13.25, 13.08, 12.93, 12.78,
+  12.60, 12.44, 12.33, 12.26, 29.52, 17.05, 12.44, 12.58, 12.72, 12.83, 15.46, 16.75, 12.83, 12.67,
+  12.45, 12.19, 11.89, 11.60, 11.35, 11.12, 10.95, 10.76, 10.42, 10.11, 10.04, 10.02, 10.11, 9.87,
+  8.65, 7.27, 6.44, 5.83, 5.41, 5.04, 4.57, 4.12, 3.77, 3.46, 3.08, 2.73, 2.47, 2.25, 2.06, 1.90,
+  1.75, 1.62, 1.54, 1.45, 1.32, 1.17, 0.99, 0.81,
+];
+
+/// CIE F11 from 380nm to 780nm in 5nm steps
+const F11: [f32; 81] = [
+  0.91, 0.63, 0.46, 0.37, 1.29, 12.68, 1.59, 1.79, 2.46, 3.33, 4.49, 33.94, 12.13, 6.95, 7.19,
+  7.12, 6.72, 6.13, 5.46, 4.79, 5.66, 14.29, 14.96, 8.97, 4.72, 2.33, 1.47, 1.10, 0.89, 0.83, 1.18,
+  4.90, 39.59, 72.84, 32.61, 7.52, 2.83, 1.96, 1.67, 4.43, 11.28, 14.76, 12.73, 9.74, 7.33, 9.72,
+  55.27, 42.58, 13.18, 13.16, 12.26, 5.11, 2.07, 2.34, 3.58, 3.01, 2.48, 2.14, 1.54, 1.33, 1.46,
+  1.94, 2.00, 1.20, 1.35, 4.10, 5.58, 2.51, 0.57, 0.27, 0.23, 0.21, 0.24, 0.24, 0.20, 0.24, 0.32,
+  0.26, 0.16, 0.12, 0.09,
+];
+
+#[test]
+fn test_blackbody_white_point() {
+  use quick_maths::Vector;
+  // A ~6500K blackbody should be close to white, and a cooler one should be redder
+  let Vector([r, g, b]) = Spd::Blackbody {
+    temperature: 6504.0,
+    normalized: true,
+  }
+  .to_rgb();
+  assert!(
+    (r - g).abs() < 0.15 * g && (b - g).abs() < 0.15 * g,
+    "{} {} {}",
+    r,
+    g,
+    b
+  );
+  let Vector([r, _, b]) = Illuminant::A.spd().to_rgb();
+  assert!(r > b);
+}
+
+#[test]
+fn test_illuminants_peak_at_one() {
+  use Illuminant::*;
+  let peak = |spd: Spd| {
+    (MIN_WAVELENGTH as u32..=MAX_WAVELENGTH as u32)
+      .map(|l| spd.eval(l as f32))
+      .fold(0f32, f32::max)
+  };
+  for &illuminant in &[D65, F2, F7, F11] {
+    let max = peak(illuminant.spd());
+    assert!((max - 1.).abs() < 0.01, "{:?} peaks at {}", illuminant, max);
+  }
+  // A peaks in the infrared, so it is at most 1 over visible wavelengths
+  let a = peak(A.spd());
+  assert!(a > 0.5 && a <= 1., "{}", a);
+}
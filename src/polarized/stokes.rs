@@ -1,6 +1,48 @@
-use quick_maths::{Vec4, Vector};
+use quick_maths::{Vec3, Vec4, Vector};
 
 /// Represents a stokes vector
 pub type Stokes = Vec4;
 
 pub const fn unpolarized() -> Stokes { Vector([1., 0., 0., 0.]) }
+
+/// Fully linearly polarized light of some intensity, with the angle measured in radians from
+/// the horizontal axis of the Stokes basis.
+pub fn linear(intensity: f32, angle: f32) -> Stokes {
+  let (s, c) = (2.0 * angle).sin_cos();
+  Vector([intensity, intensity * c, intensity * s, 0.])
+}
+
+/// Fraction of the intensity of this Stokes vector which is polarized.
+pub fn degree_of_polarization(s: &Stokes) -> f32 {
+  let &Vector([s0, s1, s2, s3]) = s;
+  if s0 <= 0. {
+    return 0.;
+  }
+  ((s1 * s1 + s2 * s2 + s3 * s3).sqrt() / s0).min(1.)
+}
+
+/// Fraction of the intensity of this Stokes vector which is linearly polarized.
+pub fn degree_of_linear_polarization(s: &Stokes) -> f32 {
+  let &Vector([s0, s1, s2, _]) = s;
+  if s0 <= 0. {
+    return 0.;
+  }
+  ((s1 * s1 + s2 * s2).sqrt() / s0).min(1.)
+}
+
+/// Angle of linear polarization in radians in [-pi/2, pi/2].
+pub fn angle_of_polarization(s: &Stokes) -> f32 {
+  let &Vector([_, s1, s2, _]) = s;
+  0.5 * s2.atan2(s1)
+}
+
+/// Returns the canonical horizontal axis of the Stokes basis for light travelling along `w`.
+/// This is some arbitrary but consistent vector perpendicular to w.
+pub fn stokes_basis(w: &Vec3) -> Vec3 {
+  // https://graphics.pixar.com/library/OrthonormalB/paper.pdf
+  let &Vector([x, y, z]) = w;
+  let sign = 1f32.copysign(z);
+  let a = -(sign + z).recip();
+  let b = x * y * a;
+  Vec3::new(1.0 + sign * x * x * a, sign * b, -sign * x)
+}
@@ -29,3 +29,12 @@ macro_rules! impl_from_sampler {
 }
 
 impl_from_sampler!(uniform::Uniform, Samplers::Uniform);
+
+impl Samplers {
+  /// Returns the next sample in [0, 1)
+  pub fn sample(&mut self) -> DefaultFloat {
+    match self {
+      Samplers::Uniform(u) => u.sample(),
+    }
+  }
+}
@@ -0,0 +1,341 @@
+use super::RawScene;
+use crate::{
+  bounds::Bounds3, bsdf::BSDFImpl, camera::builder::Variant as CameraVariant, light::Lights,
+  spectrum::Spectrum, transform::Builder as TransformBuilder,
+};
+use quick_maths::{Transform4, Vec3, Vector};
+use std::{collections::HashSet, fmt, mem::size_of, path::Path};
+
+/// Lights stronger than this are likely to be in the wrong units
+const BRIGHT_LIGHT: f32 = 1e6;
+
+/// How serious a problem found in a scene is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+  /// Likely a mistake, but the scene still renders
+  Warning,
+  /// The scene fails to build or renders incorrectly
+  Error,
+}
+
+/// A problem found in a scene
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lint {
+  pub severity: Severity,
+  pub message: String,
+}
+
+impl fmt::Display for Lint {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.severity {
+      Severity::Warning => write!(f, "warning: {}", self.message),
+      Severity::Error => write!(f, "error: {}", self.message),
+    }
+  }
+}
+
+/// Summary of what a scene contains once its files are loaded
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Stats {
+  pub shapes: usize,
+  pub triangles: usize,
+  pub bsdfs: usize,
+  pub lights: usize,
+  /// Bounds of every shape in world space, None if there are none
+  pub bounds: Option<Bounds3>,
+  /// Approximate bytes used by shapes, bsdfs and the film
+  pub memory: usize,
+}
+
+impl fmt::Display for Stats {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "shapes:    {}", self.shapes)?;
+    writeln!(f, "triangles: {}", self.triangles)?;
+    writeln!(f, "bsdfs:     {}", self.bsdfs)?;
+    writeln!(f, "lights:    {}", self.lights)?;
+    match &self.bounds {
+      Some(b) => writeln!(f, "bounds:    {:?} to {:?}", b.min, b.max)?,
+      None => writeln!(f, "bounds:    empty")?,
+    }
+    write!(
+      f,
+      "memory:    {:.1} MiB",
+      self.memory as f64 / (1024. * 1024.)
+    )
+  }
+}
+
+/// Everything found by checking a scene
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Report {
+  pub lints: Vec<Lint>,
+  pub stats: Stats,
+}
+
+impl Report {
+  pub fn has_errors(&self) -> bool { self.lints.iter().any(|l| l.severity == Severity::Error) }
+  fn warn(&mut self, message: String) {
+    self.lints.push(Lint {
+      severity: Severity::Warning,
+      message,
+    })
+  }
+  fn error(&mut self, message: String) {
+    self.lints.push(Lint {
+      severity: Severity::Error,
+      message,
+    })
+  }
+}
+
+fn is_finite(v: &Vec3) -> bool {
+  let Vector(coords) = *v;
+  coords.iter().all(|c| c.is_finite())
+}
+
+/// Whether a transform and its inverse map points and directions to finite values, which fails
+/// for NaNs and for transforms that scale some axis to zero.
+fn finite_transform(t: &TransformBuilder) -> bool {
+  let t: Transform4 = t.clone().into();
+  let axes = [
+    Vec3::new(1., 0., 0.),
+    Vec3::new(0., 1., 0.),
+    Vec3::new(0., 0., 1.),
+  ];
+  [t.inv(), t].iter().all(|t| {
+    is_finite(&t.apply_point(&Vec3::of(0.))) && axes.iter().all(|a| is_finite(&t.apply_vec(a)))
+  })
+}
+
+impl RawScene {
+  /// Validates this scene without rendering it, loading each shape to find problems in its
+  /// files and to gather statistics.
+  pub fn check(self) -> Report {
+    let mut report = Report::default();
+    let RawScene {
+      lights,
+      camera,
+      shapes,
+      bsdfs,
+      bsdf_mapping,
+    } = self;
+
+    // references between shapes and bsdfs
+    let mut used = HashSet::new();
+    let mut ids = shapes.keys().collect::<Vec<_>>();
+    ids.sort();
+    for id in ids {
+      match bsdf_mapping.get(id) {
+        None => report.error(format!("shape {:?} has no bsdf mapping", id)),
+        Some(bsdf) if !bsdfs.contains_key(bsdf) =>
+          report.error(format!("shape {:?} uses missing bsdf {:?}", id, bsdf)),
+        Some(bsdf) => {
+          used.insert(bsdf);
+        },
+      }
+    }
+    for (shape, bsdf) in &bsdf_mapping {
+      if !shapes.contains_key(shape) {
+        report.warn(format!(
+          "bsdf {:?} is mapped to missing shape {:?}",
+          bsdf, shape
+        ));
+      }
+    }
+    for id in bsdfs.keys().filter(|id| !used.contains(id)) {
+      report.warn(format!("bsdf {:?} is not used by any shape", id));
+    }
+
+    // files which would fail to load
+    let mut missing = HashSet::new();
+    let files = shapes
+      .values()
+      .flat_map(|s| s.files())
+      .chain(bsdfs.values().flat_map(|b| b.files()));
+    for file in files {
+      if !Path::new(file).exists() && missing.insert(file.to_string()) {
+        report.error(format!("missing file {}", file));
+      }
+    }
+
+    // camera
+    let (near, far) = match camera.variant {
+      CameraVariant::Perspective {
+        near_clip,
+        far_clip,
+        x_fov,
+        ..
+      } => {
+        if !(x_fov > 0. && x_fov < 180.) {
+          report.error(format!(
+            "camera field of view {} is not within (0, 180)",
+            x_fov
+          ));
+        }
+        (near_clip, far_clip)
+      },
+      CameraVariant::Orthographic {
+        near_clip,
+        far_clip,
+        ..
+      } => (near_clip, far_clip),
+    };
+    if near.is_nan() || far.is_nan() || near >= far {
+      report.error(format!(
+        "camera near clip {} is not before its far clip {}",
+        near, far
+      ));
+    }
+    if !finite_transform(&camera.to_world) {
+      report.error(String::from(
+        "camera transform is not finite or not invertible",
+      ));
+    }
+    if let Err(e) = camera.validate() {
+      report.error(e.to_string());
+    }
+    let (width, height) = camera.film_builder.size;
+    if width == 0 || height == 0 {
+      report.error(format!("film has no pixels at {}x{}", width, height));
+    }
+
+    // lights
+    for (i, light) in lights.iter().enumerate() {
+      // there are only point and directional lights, which have no area to be zero, so area
+      // lights must be checked for it here once they are added
+      match light {
+        Lights::Point(_) | Lights::Dir(_) => (),
+      }
+      let strength = light.strength();
+      if strength.is_nan() || strength < 0. {
+        report.error(format!("light {} has an invalid intensity {}", i, strength));
+      } else if strength == 0. {
+        report.warn(format!("light {} emits no light", i));
+      } else if strength > BRIGHT_LIGHT {
+        report.warn(format!(
+          "light {} has a suspiciously high intensity {}",
+          i, strength
+        ));
+      }
+    }
+    if lights.is_empty() {
+      report.warn(String::from("scene has no lights"));
+    }
+
+    // shapes, which are loaded for their triangles and bounds
+    let mut stats = Stats {
+      bsdfs: bsdfs.len(),
+      lights: lights.len(),
+      memory: bsdfs.len() * size_of::<BSDFImpl>()
+        + (width as usize) * (height as usize) * size_of::<Spectrum>(),
+      ..Default::default()
+    };
+    let mut shapes = shapes.into_iter().collect::<Vec<_>>();
+    shapes.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (id, shape) in shapes {
+      stats.shapes += 1;
+      if !finite_transform(&shape.to_world) {
+        let msg = format!(
+          "shape {:?} has a transform which is not finite or not invertible",
+          id
+        );
+        report.error(msg);
+        continue;
+      }
+      if shape.files().iter().any(|f| missing.contains(*f)) {
+        continue;
+      }
+      let geometry = match shape.build(None) {
+        Ok(g) => g,
+        Err(e) => {
+          report.error(format!("shape {:?} failed to load: {}", id, e));
+          continue;
+        },
+      };
+      stats.memory += geometry.memory();
+      // meshes without triangles fail to load, so every shape here has bounds
+      if let Some(n) = geometry.triangles() {
+        stats.triangles += n;
+      }
+      let degenerate = geometry.degenerate_triangles();
+      if degenerate > 0 {
+        report.warn(format!(
+          "shape {:?} has {} degenerate triangles",
+          id, degenerate
+        ));
+      }
+      let b = geometry.bounds();
+      stats.bounds = Some(stats.bounds.map_or(b, |acc| acc.union(&b)));
+    }
+    report.stats = stats;
+    report
+  }
+}
+
+#[cfg(test)]
+mod test_check {
+  use super::*;
+  use crate::{bsdf::builder::Builder as BSDFBuilder, shapes::builder::Variant as ShapeVariant};
+  #[test]
+  fn finds_problems() {
+    let mut scene = RawScene::example();
+    scene
+      .bsdfs
+      .insert(String::from("unused"), BSDFBuilder::Debug);
+    scene
+      .shapes
+      .insert(String::from("flat"), crate::shapes::Builder {
+        to_world: TransformBuilder::Identity,
+        variant: ShapeVariant::Mesh {
+          verts: vec![Vec3::of(0.), Vec3::of(1.), Vec3::of(2.)],
+          normals: None,
+          uvs: None,
+          faces: vec![Vec3::new(0, 1, 2)],
+        },
+      });
+    scene
+      .shapes
+      .insert(String::from("empty"), crate::shapes::Builder {
+        to_world: TransformBuilder::Identity,
+        variant: ShapeVariant::Mesh {
+          verts: vec![],
+          normals: None,
+          uvs: None,
+          faces: vec![],
+        },
+      });
+    scene
+      .shapes
+      .insert(String::from("lost"), crate::shapes::Builder {
+        to_world: TransformBuilder::Scale(Vec3::of(0.)),
+        variant: ShapeVariant::Ply {
+          file: String::from("missing.ply"),
+        },
+      });
+    let report = scene.check();
+    let messages = report
+      .lints
+      .iter()
+      .map(|l| l.to_string())
+      .collect::<Vec<_>>();
+    for expected in &[
+      "error: shape \"flat\" has no bsdf mapping",
+      "error: shape \"lost\" has no bsdf mapping",
+      "warning: bsdf \"unused\" is not used by any shape",
+      "error: missing file missing.ply",
+      "error: shape \"lost\" has a transform which is not finite or not invertible",
+      "warning: shape \"flat\" has 1 degenerate triangles",
+      "error: shape \"empty\" failed to load: Mesh inline has no triangles",
+    ] {
+      assert!(
+        messages.iter().any(|m| m == expected),
+        "{} not in {:?}",
+        expected,
+        messages
+      );
+    }
+    assert!(report.has_errors());
+    assert_eq!(report.stats.shapes, 4);
+    assert_eq!(report.stats.triangles, 1);
+  }
+}
@@ -0,0 +1,169 @@
+use std::{
+  fmt, io,
+  path::{Path, PathBuf},
+};
+
+/// Errors from loading the files of a scene or building it
+#[derive(Debug)]
+pub enum SceneError {
+  /// A file could not be read
+  Io { path: PathBuf, source: io::Error },
+  /// An image could not be read or decoded
+  Image {
+    path: PathBuf,
+    source: image::ImageError,
+  },
+  /// A file is malformed, at some line if it is text
+  Parse {
+    path: PathBuf,
+    line: Option<usize>,
+    context: String,
+  },
+  /// A file uses a feature or option which is not implemented
+  Unsupported {
+    path: PathBuf,
+    line: Option<usize>,
+    context: String,
+  },
+  /// A value of the scene is out of range, such as a negative radius
+  Invalid { context: String },
+  /// Something refers to an id which does not exist
+  Dangling {
+    kind: &'static str,
+    id: String,
+    referrer: String,
+  },
+  /// An override of a field of the scene could not be applied
+  Override { field: String, context: String },
+}
+
+impl SceneError {
+  /// Wraps an error from reading a file. Loaders report malformed data as invalid data, so
+  /// those become parse errors.
+  pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+    let path = path.into();
+    if source.kind() == io::ErrorKind::InvalidData {
+      let context = source.to_string();
+      return SceneError::Parse {
+        path,
+        line: None,
+        context,
+      };
+    }
+    SceneError::Io { path, source }
+  }
+  pub fn image(path: impl Into<PathBuf>, source: image::ImageError) -> Self {
+    match source {
+      image::ImageError::IoError(e) => Self::io(path, e),
+      source => SceneError::Image {
+        path: path.into(),
+        source,
+      },
+    }
+  }
+  pub fn parse(path: impl Into<PathBuf>, line: Option<usize>, context: impl Into<String>) -> Self {
+    SceneError::Parse {
+      path: path.into(),
+      line,
+      context: context.into(),
+    }
+  }
+  pub fn unsupported(
+    path: impl Into<PathBuf>,
+    line: Option<usize>,
+    context: impl Into<String>,
+  ) -> Self {
+    SceneError::Unsupported {
+      path: path.into(),
+      line,
+      context: context.into(),
+    }
+  }
+  pub fn invalid(context: impl Into<String>) -> Self {
+    SceneError::Invalid {
+      context: context.into(),
+    }
+  }
+  pub fn dangling(kind: &'static str, id: impl Into<String>, referrer: impl Into<String>) -> Self {
+    SceneError::Dangling {
+      kind,
+      id: id.into(),
+      referrer: referrer.into(),
+    }
+  }
+  pub fn invalid_override(field: impl Into<String>, context: impl Into<String>) -> Self {
+    SceneError::Override {
+      field: field.into(),
+      context: context.into(),
+    }
+  }
+  /// Sets the file of an error raised by a parser which only saw the contents of that file
+  pub(crate) fn at(mut self, file: &Path) -> Self {
+    match &mut self {
+      SceneError::Parse { path, .. } | SceneError::Unsupported { path, .. }
+        if path.as_os_str().is_empty() =>
+        *path = file.to_path_buf(),
+      _ => (),
+    }
+    self
+  }
+  /// The file this error occurred in, if it came from one
+  pub fn path(&self) -> Option<&Path> {
+    match self {
+      SceneError::Io { path, .. }
+      | SceneError::Image { path, .. }
+      | SceneError::Parse { path, .. }
+      | SceneError::Unsupported { path, .. } => Some(path),
+      SceneError::Invalid { .. } | SceneError::Dangling { .. } | SceneError::Override { .. } =>
+        None,
+    }
+  }
+}
+
+/// Formats a location as path:line, as compilers do
+fn location(f: &mut fmt::Formatter<'_>, path: &Path, line: Option<usize>) -> fmt::Result {
+  write!(f, "{}", path.display())?;
+  match line {
+    Some(line) => write!(f, ":{}", line),
+    None => Ok(()),
+  }
+}
+
+impl fmt::Display for SceneError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      SceneError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+      SceneError::Image { path, source } => write!(f, "{}: {}", path.display(), source),
+      SceneError::Parse {
+        path,
+        line,
+        context,
+      } => {
+        location(f, path, *line)?;
+        write!(f, ": {}", context)
+      },
+      SceneError::Unsupported {
+        path,
+        line,
+        context,
+      } => {
+        location(f, path, *line)?;
+        write!(f, ": unsupported: {}", context)
+      },
+      SceneError::Invalid { context } => write!(f, "{}", context),
+      SceneError::Dangling { kind, id, referrer } =>
+        write!(f, "{}: missing {} {:?}", referrer, kind, id),
+      SceneError::Override { field, context } => write!(f, "--set {}: {}", field, context),
+    }
+  }
+}
+
+impl std::error::Error for SceneError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      SceneError::Io { source, .. } => Some(source),
+      SceneError::Image { source, .. } => Some(source),
+      _ => None,
+    }
+  }
+}
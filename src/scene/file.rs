@@ -0,0 +1,461 @@
+use super::{RawScene, SceneError};
+use serde_json::{Map, Value};
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+/// Keys whose string values are paths to files, which are relative to the file they are in
+const PATH_KEYS: [&str; 5] = ["file", "texture", "MTL", "File", "Spd"];
+
+/// Reads a JSON or TOML file into a tree, chosen by its extension
+fn read_tree(p: &Path) -> Result<Value, SceneError> {
+  let src = fs::read_to_string(p).map_err(|e| SceneError::io(p, e))?;
+  if p.extension().map_or(false, |ext| ext == "toml") {
+    toml::from_str(&src)
+      .map_err(|e| SceneError::parse(p, e.line_col().map(|(line, _)| line + 1), e.to_string()))
+  } else {
+    serde_json::from_str(&src).map_err(|e| SceneError::parse(p, Some(e.line()), e.to_string()))
+  }
+}
+
+/// Merges `over` into `base`, recursing into objects which are in both so that a file can add
+/// to a table it includes. Anything else in `over` replaces what is in `base`.
+pub fn merge(base: &mut Value, over: Value) {
+  match (base, over) {
+    (Value::Object(base), Value::Object(over)) =>
+      for (k, v) in over {
+        match base.get_mut(&k) {
+          Some(existing) => merge(existing, v),
+          None => {
+            base.insert(k, v);
+          },
+        }
+      },
+    (base, over) => *base = over,
+  }
+}
+
+/// Makes relative file paths in a tree relative to `dir` instead
+fn rebase_paths(v: &mut Value, dir: &Path) {
+  match v {
+    Value::Object(map) =>
+      for (k, v) in map.iter_mut() {
+        match v {
+          Value::String(s) if PATH_KEYS.contains(&k.as_str()) && Path::new(s).is_relative() =>
+            *s = dir.join(&s).to_string_lossy().into_owned(),
+          v => rebase_paths(v, dir),
+        }
+      },
+    Value::Array(items) => items.iter_mut().for_each(|v| rebase_paths(v, dir)),
+    _ => (),
+  }
+}
+
+/// A file read as part of a scene, which is merged over the files read before it
+struct Layer {
+  path: PathBuf,
+  tree: Value,
+  /// Whether another file included this one, so that its paths are relative to it
+  included: bool,
+}
+
+/// Reads a scene file and the files it includes, in the order they are merged: the includes of
+/// a file in order, then the file itself so that it takes precedence. `stack` holds the files
+/// currently being read, to find include cycles.
+fn read_with_includes(
+  p: &Path,
+  stack: &mut Vec<PathBuf>,
+  layers: &mut Vec<Layer>,
+) -> Result<(), SceneError> {
+  let canonical = fs::canonicalize(p).map_err(|e| SceneError::io(p, e))?;
+  if stack.contains(&canonical) {
+    return Err(SceneError::parse(p, None, "File includes itself"));
+  }
+  let mut tree = read_tree(p)?;
+  let includes = match &mut tree {
+    Value::Object(map) => map.remove("includes"),
+    _ =>
+      return Err(SceneError::parse(
+        p,
+        None,
+        "Expected a table at the top level",
+      )),
+  };
+  let includes = match includes {
+    None => vec![],
+    Some(Value::Array(includes)) => includes,
+    Some(v) =>
+      return Err(SceneError::parse(
+        p,
+        None,
+        format!("Invalid includes {}", v),
+      )),
+  };
+  let dir = p.parent().unwrap_or_else(|| Path::new(""));
+  stack.push(canonical);
+  for include in includes {
+    let include = match include {
+      Value::String(s) => dir.join(s),
+      v => return Err(SceneError::parse(p, None, format!("Invalid include {}", v))),
+    };
+    read_with_includes(&include, stack, layers)?;
+  }
+  stack.pop();
+  layers.push(Layer {
+    path: p.to_path_buf(),
+    tree,
+    included: !stack.is_empty(),
+  });
+  Ok(())
+}
+
+/// Parses an override of the form `path.to.field=value`, where the value is JSON or otherwise
+/// a string
+pub fn parse_override(s: &str) -> Option<(String, Value)> {
+  let eq = s.find('=')?;
+  let value = &s[eq + 1..];
+  let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+  Some((s[..eq].to_string(), value))
+}
+
+/// Sets the field at a dot separated path in a tree, where numbers index into arrays. Only
+/// variables can be added, other fields must already exist so that misspelled ones are caught.
+fn set(tree: &mut Value, field: &str, value: Value) -> Result<(), SceneError> {
+  let adds = field.split('.').next() == Some("variables");
+  let mut node = tree;
+  for key in field.split('.') {
+    if adds && node.is_null() {
+      *node = Value::Object(Map::new());
+    }
+    node = match node {
+      Value::Object(map) => {
+        if !adds && !map.contains_key(key) {
+          let keys = map.keys().map(String::as_str).collect::<Vec<_>>();
+          let msg = format!("no field {:?}, expected one of {}", key, keys.join(", "));
+          return Err(SceneError::invalid_override(field, msg));
+        }
+        // new variables start as tables, which are replaced if they are the field being set
+        map.entry(key).or_insert_with(|| Value::Object(Map::new()))
+      },
+      Value::Array(items) => match key.parse::<usize>() {
+        Ok(i) if i < items.len() => &mut items[i],
+        _ => {
+          let msg = format!(
+            "{:?} is not an index of an array of length {}",
+            key,
+            items.len()
+          );
+          return Err(SceneError::invalid_override(field, msg));
+        },
+      },
+      v => {
+        let msg = format!(
+          "cannot set {:?} of {}, which is not a table or array",
+          key, v
+        );
+        return Err(SceneError::invalid_override(field, msg));
+      },
+    };
+  }
+  *node = value;
+  Ok(())
+}
+
+/// Replaces `$name` in the strings of a tree by the variable of that name. A string which is
+/// only a variable becomes its value, so that variables can be numbers, arrays or tables.
+fn substitute(v: &mut Value, vars: &Map<String, Value>) -> Result<(), String> {
+  match v {
+    Value::String(s) => {
+      let is_name = |c: char| c.is_alphanumeric() || c == '_';
+      if let Some(value) = s
+        .strip_prefix('$')
+        .filter(|name| !name.is_empty() && name.chars().all(is_name))
+        .map(|name| {
+          vars
+            .get(name)
+            .ok_or_else(|| format!("Undefined variable ${}", name))
+        })
+      {
+        *v = value?.clone();
+        return Ok(());
+      }
+      let mut out = String::new();
+      let mut rest = s.as_str();
+      while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+        let len = after.find(|c| !is_name(c)).unwrap_or(after.len());
+        if len == 0 {
+          out.push('$');
+        } else {
+          match vars.get(&after[..len]) {
+            Some(Value::String(value)) => out.push_str(value),
+            Some(value) => out.push_str(&value.to_string()),
+            None => return Err(format!("Undefined variable ${}", &after[..len])),
+          }
+        }
+        rest = &after[len..];
+      }
+      out.push_str(rest);
+      *s = out;
+    },
+    Value::Array(items) =>
+      for item in items {
+        substitute(item, vars)?;
+      },
+    Value::Object(map) =>
+      for (_, v) in map.iter_mut() {
+        substitute(v, vars)?;
+      },
+    _ => (),
+  }
+  Ok(())
+}
+
+impl RawScene {
+  /// Reads a scene from a JSON or TOML file, by its extension, along with the files it
+  /// includes. Paths in an included file are relative to that file.
+  pub fn from_file(p: impl AsRef<Path>) -> Result<Self, SceneError> { Self::from_file_with(p, &[]) }
+  /// Reads a scene from a file as in `from_file`, replacing `$name`s by the scene's `variables`
+  /// before rebasing paths, then sets fields of it by their dot separated path. Variables are
+  /// overridden by setting `variables.name`.
+  pub fn from_file_with(
+    p: impl AsRef<Path>,
+    overrides: &[(String, Value)],
+  ) -> Result<Self, SceneError> {
+    let p = p.as_ref();
+    let mut layers = vec![];
+    read_with_includes(p, &mut vec![], &mut layers)?;
+    // variables are resolved first, so that they are substituted into each file before its
+    // paths are rebased
+    let (var_overrides, overrides): (Vec<_>, Vec<_>) = overrides
+      .iter()
+      .partition(|(field, _)| field.split('.').next() == Some("variables"));
+    let mut vars = Value::Object(Map::new());
+    for layer in &mut layers {
+      if let Some(v) = layer
+        .tree
+        .as_object_mut()
+        .and_then(|map| map.remove("variables"))
+      {
+        let mut table = Map::new();
+        table.insert(String::from("variables"), v);
+        merge(&mut vars, Value::Object(table));
+      }
+    }
+    for (field, value) in var_overrides {
+      set(&mut vars, field, value.clone())?;
+    }
+    let vars = match vars.as_object_mut().and_then(|map| map.remove("variables")) {
+      None => Map::new(),
+      Some(Value::Object(vars)) => vars,
+      Some(v) =>
+        return Err(SceneError::parse(
+          p,
+          None,
+          format!("Invalid variables {}", v),
+        )),
+    };
+    let mut tree = Value::Object(Map::new());
+    for Layer {
+      path,
+      tree: mut layer,
+      included,
+    } in layers
+    {
+      substitute(&mut layer, &vars).map_err(|msg| SceneError::parse(&path, None, msg))?;
+      if included {
+        rebase_paths(&mut layer, path.parent().unwrap_or_else(|| Path::new("")));
+      }
+      merge(&mut tree, layer);
+    }
+    for (field, value) in overrides {
+      let mut value = value.clone();
+      substitute(&mut value, &vars).map_err(|msg| SceneError::invalid_override(field, msg))?;
+      set(&mut tree, field, value)?;
+    }
+    serde_json::from_value(tree).map_err(|e| SceneError::parse(p, None, e.to_string()))
+  }
+  /// Sets fields of a scene by their dot separated path, for scenes imported from other formats
+  pub fn set(self, overrides: &[(String, Value)]) -> Result<Self, SceneError> {
+    if overrides.is_empty() {
+      return Ok(self);
+    }
+    let mut tree =
+      serde_json::to_value(self).map_err(|e| SceneError::invalid_override("", e.to_string()))?;
+    for (field, value) in overrides {
+      set(&mut tree, field, value.clone())?;
+    }
+    serde_json::from_value(tree).map_err(|e| {
+      let fields = overrides
+        .iter()
+        .map(|(f, _)| f.as_str())
+        .collect::<Vec<_>>();
+      SceneError::invalid_override(fields.join(","), e.to_string())
+    })
+  }
+}
+
+#[cfg(test)]
+mod test_file {
+  use super::*;
+  use std::env::temp_dir;
+  #[test]
+  fn includes() {
+    let dir = temp_dir().join("mireba_includes");
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    let mut example = serde_json::to_value(RawScene::example()).unwrap();
+    let camera = example.as_object_mut().unwrap().remove("camera").unwrap();
+    fs::write(
+      dir.join("lib/camera.json"),
+      format!("{{\"camera\": {}}}", camera),
+    )
+    .unwrap();
+    fs::write(
+      dir.join("lib/materials.toml"),
+      "[bsdfs]\nmesh = { MTL = \"mesh.mtl\" }\nother = \"Debug\"\n",
+    )
+    .unwrap();
+    example["includes"] = serde_json::json!(["lib/camera.json", "lib/materials.toml"]);
+    example["bsdfs"]["other"] = serde_json::json!({ "MTL": "/abs.mtl" });
+    fs::write(dir.join("scene.json"), example.to_string()).unwrap();
+
+    let scene = RawScene::from_file(dir.join("scene.json")).unwrap();
+    assert_eq!(scene.bsdfs.len(), 3);
+    assert_eq!(scene.camera.film_builder.size, (512, 512));
+    let files = scene
+      .bsdfs
+      .values()
+      .flat_map(|b| b.files())
+      .collect::<Vec<_>>();
+    let rebased = dir.join("lib").join("mesh.mtl");
+    assert!(files.contains(&rebased.to_str().unwrap()), "{:?}", files);
+    // the including file overrides what it includes
+    assert!(files.contains(&"/abs.mtl"), "{:?}", files);
+
+    fs::write(dir.join("cycle.toml"), "includes = [\"cycle.toml\"]\n").unwrap();
+    let err = RawScene::from_file(dir.join("cycle.toml")).unwrap_err();
+    assert!(err.to_string().ends_with("File includes itself"), "{}", err);
+  }
+  #[test]
+  fn nested_includes() {
+    let dir = temp_dir().join("mireba_nested_includes");
+    fs::create_dir_all(dir.join("lib").join("inner")).unwrap();
+    fs::write(
+      dir.join("lib").join("materials.json"),
+      r#"{ "includes": ["inner/more.toml"], "bsdfs": { "outer": { "MTL": "outer.mtl" } } }"#,
+    )
+    .unwrap();
+    fs::write(
+      dir.join("lib").join("inner").join("more.toml"),
+      "[bsdfs]\ninner = { MTL = \"inner.mtl\" }\n",
+    )
+    .unwrap();
+    let mut example = serde_json::to_value(RawScene::example()).unwrap();
+    example["includes"] = serde_json::json!(["lib/materials.json"]);
+    fs::write(dir.join("scene.json"), example.to_string()).unwrap();
+
+    // read through a relative path, so that rebasing twice would change the paths
+    let depth = std::env::current_dir().unwrap().components().count() - 1;
+    let relative = (0..depth)
+      .fold(PathBuf::new(), |p, _| p.join(".."))
+      .join(dir.strip_prefix("/").unwrap());
+    let scene = RawScene::from_file(relative.join("scene.json")).unwrap();
+    let lib = relative.join("lib");
+    assert_eq!(scene.bsdfs["outer"].files(), vec![lib
+      .join("outer.mtl")
+      .to_str()
+      .unwrap()]);
+    assert_eq!(scene.bsdfs["inner"].files(), vec![lib
+      .join("inner")
+      .join("inner.mtl")
+      .to_str()
+      .unwrap()]);
+  }
+  #[test]
+  fn variables_in_includes() {
+    let dir = temp_dir().join("mireba_include_variables");
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::write(
+      dir.join("lib").join("materials.toml"),
+      "[bsdfs]\nmtl = { MTL = \"$kind/a.mtl\" }\nabs = { MTL = \"$root/b.mtl\" }\n",
+    )
+    .unwrap();
+    let mut example = serde_json::to_value(RawScene::example()).unwrap();
+    example["includes"] = serde_json::json!(["lib/materials.toml"]);
+    example["variables"] = serde_json::json!({ "kind": "glossy", "root": "/abs" });
+    let p = dir.join("scene.json");
+    fs::write(&p, example.to_string()).unwrap();
+
+    // variables are substituted before the paths of the included file are rebased
+    let lib = dir.join("lib");
+    let scene = RawScene::from_file(&p).unwrap();
+    assert_eq!(scene.bsdfs["mtl"].files(), vec![lib
+      .join("glossy")
+      .join("a.mtl")
+      .to_str()
+      .unwrap()]);
+    assert_eq!(scene.bsdfs["abs"].files(), vec!["/abs/b.mtl"]);
+    let overrides = [parse_override("variables.kind=matte").unwrap()];
+    let scene = RawScene::from_file_with(&p, &overrides).unwrap();
+    assert_eq!(scene.bsdfs["mtl"].files(), vec![lib
+      .join("matte")
+      .join("a.mtl")
+      .to_str()
+      .unwrap()]);
+  }
+  #[test]
+  fn variables_and_overrides() {
+    let dir = temp_dir().join("mireba_variables");
+    fs::create_dir_all(&dir).unwrap();
+    let mut example = serde_json::to_value(RawScene::example()).unwrap();
+    example["variables"] = serde_json::json!({ "size": [64, 64], "lib": "materials" });
+    example["camera"]["film_builder"]["size"] = serde_json::json!("$size");
+    example["bsdfs"]["mtl"] = serde_json::json!({ "MTL": "$lib/a.mtl" });
+    let p = dir.join("scene.json");
+    fs::write(&p, example.to_string()).unwrap();
+
+    let scene = RawScene::from_file(&p).unwrap();
+    assert_eq!(scene.camera.film_builder.size, (64, 64));
+    assert_eq!(scene.bsdfs["mtl"].files(), vec!["materials/a.mtl"]);
+
+    let overrides = [
+      "variables.size=[128, 32]",
+      "variables.lib=other",
+      "variables.new.a=1",
+    ]
+    .iter()
+    .map(|s| parse_override(s).unwrap())
+    .collect::<Vec<_>>();
+    let scene = RawScene::from_file_with(&p, &overrides).unwrap();
+    assert_eq!(scene.camera.film_builder.size, (128, 32));
+    assert_eq!(scene.bsdfs["mtl"].files(), vec!["other/a.mtl"]);
+
+    let scene = scene
+      .set(&[parse_override("camera.film_builder.size.1=16").unwrap()])
+      .unwrap();
+    assert_eq!(scene.camera.film_builder.size, (128, 16));
+    let err = scene
+      .set(&[parse_override("camera.film_builder.size.2=16").unwrap()])
+      .unwrap_err();
+    assert!(
+      err
+        .to_string()
+        .starts_with("--set camera.film_builder.size.2: "),
+      "{}",
+      err
+    );
+    // misspelled fields are not added
+    let err = scene
+      .set(&[parse_override("camera.film_buider.size=[8, 8]").unwrap()])
+      .unwrap_err();
+    assert!(
+      err
+        .to_string()
+        .starts_with("--set camera.film_buider.size: no field \"film_buider\""),
+      "{}",
+      err
+    );
+  }
+}
@@ -0,0 +1,959 @@
+use super::{RawScene, SceneError};
+use crate::{
+  bsdf::{builder::Builder as BSDFBuilder, AlphaMode},
+  camera::builder::{Builder as CameraBuilder, Variant as CameraVariant},
+  film::builder::Builder as FilmBuilder,
+  light::{dir::Dir, point::Point, Lights},
+  shapes::{
+    builder::Variant as ShapeVariant, triangle_list::IndexedTriangles, Builder as ShapeBuilder,
+  },
+  spectrum::from_rgb,
+  texture::builder::Builder as TextureBuilder,
+  transform::Builder as TransformBuilder,
+};
+use quick_maths::{Transform4, Vec2, Vec3};
+use serde::Deserialize;
+use std::{
+  collections::{HashMap, HashSet},
+  f32::consts::PI,
+  fs, io,
+  path::{Path, PathBuf},
+};
+
+fn invalid(msg: String) -> io::Error { io::Error::new(io::ErrorKind::InvalidData, msg) }
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Gltf {
+  scene: Option<usize>,
+  scenes: Vec<GltfScene>,
+  nodes: Vec<Node>,
+  meshes: Vec<Mesh>,
+  accessors: Vec<Accessor>,
+  buffer_views: Vec<BufferView>,
+  buffers: Vec<Buffer>,
+  materials: Vec<Material>,
+  textures: Vec<Texture>,
+  images: Vec<Image>,
+  cameras: Vec<Camera>,
+  extensions: RootExtensions,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct GltfScene {
+  nodes: Vec<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Node {
+  children: Vec<usize>,
+  mesh: Option<usize>,
+  camera: Option<usize>,
+  /// Column major
+  matrix: Option<[f32; 16]>,
+  translation: Option<[f32; 3]>,
+  /// Quaternion as x, y, z, w
+  rotation: Option<[f32; 4]>,
+  scale: Option<[f32; 3]>,
+  extensions: NodeExtensions,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct NodeExtensions {
+  #[serde(rename = "KHR_lights_punctual")]
+  light: Option<LightRef>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LightRef {
+  light: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct Mesh {
+  primitives: Vec<Primitive>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Primitive {
+  attributes: HashMap<String, usize>,
+  indices: Option<usize>,
+  material: Option<usize>,
+  /// Defaults to triangles
+  mode: Option<u32>,
+}
+
+/// Mode of primitives which are lists of triangles
+const TRIANGLES: u32 = 4;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Accessor {
+  buffer_view: Option<usize>,
+  #[serde(default)]
+  byte_offset: usize,
+  component_type: u32,
+  count: usize,
+  #[serde(rename = "type")]
+  kind: String,
+  #[serde(default)]
+  normalized: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BufferView {
+  buffer: usize,
+  #[serde(default)]
+  byte_offset: usize,
+  byte_length: usize,
+  byte_stride: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Buffer {
+  uri: Option<String>,
+  byte_length: usize,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Material {
+  pbr_metallic_roughness: Pbr,
+  /// OPAQUE, MASK or BLEND, where missing is opaque
+  alpha_mode: Option<String>,
+  alpha_cutoff: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Pbr {
+  base_color_factor: [f32; 4],
+  base_color_texture: Option<TextureInfo>,
+  metallic_factor: f32,
+  roughness_factor: f32,
+  metallic_roughness_texture: Option<TextureInfo>,
+}
+
+impl Default for Pbr {
+  fn default() -> Self {
+    Self {
+      base_color_factor: [1.; 4],
+      base_color_texture: None,
+      metallic_factor: 1.,
+      roughness_factor: 1.,
+      metallic_roughness_texture: None,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+struct TextureInfo {
+  index: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct Texture {
+  source: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Image {
+  uri: Option<String>,
+  buffer_view: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Camera {
+  #[serde(rename = "type")]
+  kind: String,
+  perspective: Option<Perspective>,
+  orthographic: Option<Orthographic>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Perspective {
+  aspect_ratio: Option<f32>,
+  /// Vertical field of view in radians
+  yfov: f32,
+  znear: f32,
+  zfar: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Orthographic {
+  xmag: f32,
+  ymag: f32,
+  znear: f32,
+  zfar: f32,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RootExtensions {
+  #[serde(rename = "KHR_lights_punctual")]
+  lights: Option<PunctualLights>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PunctualLights {
+  lights: Vec<PunctualLight>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PunctualLight {
+  #[serde(rename = "type")]
+  kind: String,
+  color: Option<[f32; 3]>,
+  intensity: Option<f32>,
+}
+
+/// A parsed glTF file and the contents of its buffers
+struct Document {
+  gltf: Gltf,
+  buffers: Vec<Vec<u8>>,
+  dir: PathBuf,
+}
+
+/// Decodes standard base64, ignoring padding
+fn decode_base64(s: &str) -> io::Result<Vec<u8>> {
+  let value = |c: u8| match c {
+    b'A'..=b'Z' => Ok(c - b'A'),
+    b'a'..=b'z' => Ok(c - b'a' + 26),
+    b'0'..=b'9' => Ok(c - b'0' + 52),
+    b'+' => Ok(62),
+    b'/' => Ok(63),
+    _ => Err(invalid(format!("Invalid base64 character {:?}", c as char))),
+  };
+  let digits = s
+    .bytes()
+    .filter(|&c| c != b'=')
+    .map(value)
+    .collect::<io::Result<Vec<_>>>()?;
+  let mut out = Vec::with_capacity(digits.len() * 3 / 4);
+  for chunk in digits.chunks(4) {
+    let bits = chunk
+      .iter()
+      .enumerate()
+      .fold(0u32, |acc, (i, &d)| acc | (d as u32) << (18 - 6 * i));
+    out.extend_from_slice(&bits.to_be_bytes()[1..chunk.len()]);
+  }
+  Ok(out)
+}
+
+/// Reads a uri which is either a base64 data uri or a path relative to the glTF file
+fn read_uri(dir: &Path, uri: &str) -> io::Result<Vec<u8>> {
+  if uri.starts_with("data:") {
+    let data = uri
+      .splitn(2, ";base64,")
+      .nth(1)
+      .ok_or_else(|| invalid(String::from("Only base64 data uris are supported")))?;
+    return decode_base64(data);
+  }
+  fs::read(dir.join(uri.replace("%20", " ")))
+}
+
+/// Magic number at the start of binary glTF files
+const GLB_MAGIC: &[u8] = b"glTF";
+const GLB_JSON: u32 = 0x4E4F_534A;
+const GLB_BIN: u32 = 0x004E_4942;
+
+fn u32_le(bytes: &[u8], i: usize) -> io::Result<u32> {
+  let b = bytes
+    .get(i..i + 4)
+    .ok_or_else(|| invalid(String::from("Binary glTF ended early")))?;
+  Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+impl Document {
+  fn load(p: impl AsRef<Path>) -> io::Result<Self> { Self::load_buffers(p, |_, _| true) }
+  /// Loads a glTF file, only reading the buffers which are wanted and leaving the rest empty
+  fn load_buffers(p: impl AsRef<Path>, wanted: impl Fn(&Gltf, usize) -> bool) -> io::Result<Self> {
+    let p = p.as_ref();
+    let dir = p.parent().map_or_else(PathBuf::new, Path::to_path_buf);
+    let bytes = fs::read(p)?;
+    let (json, mut bin) = if bytes.starts_with(GLB_MAGIC) {
+      // header of magic, version and length followed by chunks of length, type and data
+      let mut chunks = vec![];
+      let mut i = 12;
+      while i + 8 <= bytes.len() {
+        let (len, kind) = (u32_le(&bytes, i)? as usize, u32_le(&bytes, i + 4)?);
+        let data = bytes
+          .get(i + 8..i + 8 + len)
+          .ok_or_else(|| invalid(String::from("Binary glTF chunk ended early")))?;
+        chunks.push((kind, data));
+        i += 8 + len;
+      }
+      let chunk = |kind| {
+        chunks
+          .iter()
+          .find(|(k, _)| *k == kind)
+          .map(|(_, d)| d.to_vec())
+      };
+      let json =
+        chunk(GLB_JSON).ok_or_else(|| invalid(String::from("Binary glTF is missing JSON")))?;
+      (json, chunk(GLB_BIN))
+    } else {
+      (bytes, None)
+    };
+    let gltf: Gltf = serde_json::from_slice(&json).map_err(|e| invalid(e.to_string()))?;
+    let buffers = gltf
+      .buffers
+      .iter()
+      .enumerate()
+      .map(|(i, b)| {
+        if !wanted(&gltf, i) {
+          return Ok(vec![]);
+        }
+        let data = match &b.uri {
+          Some(uri) => read_uri(&dir, uri)?,
+          None => bin
+            .take()
+            .ok_or_else(|| invalid(String::from("Buffer without uri outside of .glb")))?,
+        };
+        if data.len() < b.byte_length {
+          return Err(invalid(String::from(
+            "glTF buffer is shorter than its length",
+          )));
+        }
+        Ok(data)
+      })
+      .collect::<io::Result<Vec<_>>>()?;
+    Ok(Self { gltf, buffers, dir })
+  }
+  fn view(&self, v: usize) -> io::Result<&[u8]> {
+    let view = self
+      .gltf
+      .buffer_views
+      .get(v)
+      .ok_or_else(|| invalid(format!("Missing buffer view {}", v)))?;
+    let buffer = self
+      .buffers
+      .get(view.buffer)
+      .ok_or_else(|| invalid(format!("Missing buffer {}", view.buffer)))?;
+    let end = view.byte_offset.checked_add(view.byte_length);
+    end
+      .and_then(|end| buffer.get(view.byte_offset..end))
+      .ok_or_else(|| invalid(format!("Buffer view {} is out of bounds", v)))
+  }
+  /// Reads every element of an accessor, returning the components of each element
+  fn read_accessor(&self, a: usize) -> io::Result<Vec<Vec<f32>>> {
+    let acc = self
+      .gltf
+      .accessors
+      .get(a)
+      .ok_or_else(|| invalid(format!("Missing accessor {}", a)))?;
+    let n = match acc.kind.as_str() {
+      "SCALAR" => 1,
+      "VEC2" => 2,
+      "VEC3" => 3,
+      "VEC4" | "MAT2" => 4,
+      "MAT3" => 9,
+      "MAT4" => 16,
+      k => return Err(invalid(format!("Unknown accessor type {}", k))),
+    };
+    let size = match acc.component_type {
+      5120 | 5121 => 1,
+      5122 | 5123 => 2,
+      5125 | 5126 => 4,
+      c => return Err(invalid(format!("Unknown component type {}", c))),
+    };
+    // accessors without a buffer view are all zeros
+    let v = match acc.buffer_view {
+      None => return Ok(vec![vec![0.; n]; acc.count]),
+      Some(v) => v,
+    };
+    let data = self.view(v)?;
+    let stride = self.gltf.buffer_views[v].byte_stride.unwrap_or(n * size);
+    let component = |i: usize| -> io::Result<f32> {
+      let b = data
+        .get(i..i + size)
+        .ok_or_else(|| invalid(format!("Accessor {} is out of bounds", a)))?;
+      let (v, max) = match acc.component_type {
+        5120 => (b[0] as i8 as f32, 127.),
+        5121 => (b[0] as f32, 255.),
+        5122 => (i16::from_le_bytes([b[0], b[1]]) as f32, 32767.),
+        5123 => (u16::from_le_bytes([b[0], b[1]]) as f32, 65535.),
+        5125 => (u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32, 1.),
+        _ => (f32::from_le_bytes([b[0], b[1], b[2], b[3]]), 1.),
+      };
+      Ok(if acc.normalized {
+        (v / max).max(-1.)
+      } else {
+        v
+      })
+    };
+    (0..acc.count)
+      .map(|e| {
+        (0..n)
+          .map(|c| component(acc.byte_offset + e * stride + c * size))
+          .collect()
+      })
+      .collect()
+  }
+  /// Reads indices directly from the buffer, since large indices do not fit in an f32
+  fn read_indices(&self, a: usize) -> io::Result<Vec<u32>> {
+    let acc = self
+      .gltf
+      .accessors
+      .get(a)
+      .ok_or_else(|| invalid(format!("Missing accessor {}", a)))?;
+    let size = match acc.component_type {
+      5121 => 1,
+      5123 => 2,
+      5125 => 4,
+      c => return Err(invalid(format!("Invalid index component type {}", c))),
+    };
+    let v = acc
+      .buffer_view
+      .ok_or_else(|| invalid(String::from("Indices require a buffer view")))?;
+    let data = self.view(v)?;
+    let stride = self.gltf.buffer_views[v].byte_stride.unwrap_or(size);
+    (0..acc.count)
+      .map(|e| {
+        let i = acc.byte_offset + e * stride;
+        let b = data
+          .get(i..i + size)
+          .ok_or_else(|| invalid(format!("Accessor {} is out of bounds", a)))?;
+        Ok(match size {
+          1 => b[0] as u32,
+          2 => u16::from_le_bytes([b[0], b[1]]) as u32,
+          _ => u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        })
+      })
+      .collect()
+  }
+}
+
+/// Loads one primitive of a mesh in a glTF file as triangles
+pub fn load_primitive(
+  p: impl AsRef<Path>,
+  mesh: usize,
+  primitive: usize,
+) -> Result<IndexedTriangles, SceneError> {
+  let p = p.as_ref();
+  let doc = Document::load(p).map_err(|e| SceneError::io(p, e))?;
+  let prim = doc
+    .gltf
+    .meshes
+    .get(mesh)
+    .and_then(|m| m.primitives.get(primitive))
+    .ok_or_else(|| {
+      let id = format!("{}.{}", mesh, primitive);
+      SceneError::dangling("glTF primitive", id, p.display().to_string())
+    })?;
+  if prim.mode.unwrap_or(TRIANGLES) != TRIANGLES {
+    return Err(SceneError::unsupported(
+      p,
+      None,
+      "glTF primitives which are not triangles",
+    ));
+  }
+  let src = format!("{}#{}.{}", p.display(), mesh, primitive);
+  triangles(&doc, prim, src).map_err(|e| SceneError::io(p, e))
+}
+
+/// Positions, normals, texture coordinates and triangles of a primitive
+type PrimitiveData = (Vec<Vec3>, Vec<Vec3>, Option<Vec<Vec2>>, Vec<Vec3<u32>>);
+
+/// Reads the triangles of a primitive with its normals and texture coordinates, where normals are
+/// empty if it has none.
+fn read_primitive(doc: &Document, prim: &Primitive) -> io::Result<PrimitiveData> {
+  let attr = |name: &str| {
+    prim
+      .attributes
+      .get(name)
+      .map(|&a| doc.read_accessor(a))
+      .transpose()
+  };
+  let verts = attr("POSITION")?
+    .ok_or_else(|| invalid(String::from("Primitive is missing positions")))?
+    .into_iter()
+    .map(|v| Vec3::new(v[0], v[1], v[2]))
+    .collect::<Vec<_>>();
+  let norms = attr("NORMAL")?
+    .unwrap_or_default()
+    .into_iter()
+    .map(|n| Vec3::new(n[0], n[1], n[2]))
+    .collect::<Vec<_>>();
+  // glTF has the origin of textures in the top left, as do bitmaps
+  let uvs = attr("TEXCOORD_0")?.map(|uvs| {
+    uvs
+      .into_iter()
+      .map(|uv| Vec2::new(uv[0], uv[1]))
+      .collect::<Vec<_>>()
+  });
+  let uv_count = uvs.as_ref().map_or(verts.len(), Vec::len);
+  if (!norms.is_empty() && norms.len() != verts.len()) || uv_count != verts.len() {
+    return Err(invalid(String::from(
+      "Primitive attributes have different lengths",
+    )));
+  }
+  let indices = match prim.indices {
+    Some(a) => doc.read_indices(a)?,
+    None => (0..verts.len() as u32).collect(),
+  };
+  if indices.iter().any(|&i| i as usize >= verts.len()) {
+    return Err(invalid(String::from(
+      "Primitive refers to missing vertices",
+    )));
+  }
+  let faces = indices
+    .chunks_exact(3)
+    .map(|f| Vec3::new(f[0], f[1], f[2]))
+    .collect();
+  Ok((verts, norms, uvs, faces))
+}
+
+/// Reads the triangles of a primitive with its normals and texture coordinates
+fn triangles(doc: &Document, prim: &Primitive, src: String) -> io::Result<IndexedTriangles> {
+  let (verts, norms, uvs, faces) = read_primitive(doc, prim)?;
+  let tris = IndexedTriangles::new(src, verts, norms, faces);
+  Ok(match uvs {
+    Some(uvs) => tris.with_uvs(uvs),
+    None => tris,
+  })
+}
+
+/// Loads an image stored in a glTF file
+pub fn load_image(p: impl AsRef<Path>, image: usize) -> Result<image::DynamicImage, SceneError> {
+  let p = p.as_ref();
+  // only the buffer holding the image is read
+  let holds_image = |gltf: &Gltf, buffer: usize| {
+    let view = gltf.images.get(image).and_then(|img| img.buffer_view);
+    view
+      .and_then(|v| gltf.buffer_views.get(v))
+      .map(|v| v.buffer)
+      == Some(buffer)
+  };
+  let doc = Document::load_buffers(p, holds_image).map_err(|e| SceneError::io(p, e))?;
+  let img = doc.gltf.images.get(image).ok_or_else(|| {
+    SceneError::dangling("glTF image", image.to_string(), p.display().to_string())
+  })?;
+  let bytes = match (&img.uri, img.buffer_view) {
+    (Some(uri), _) => read_uri(&doc.dir, uri),
+    (None, Some(v)) => doc.view(v).map(<[u8]>::to_vec),
+    (None, None) => Err(invalid(format!("Image {} has no data", image))),
+  }
+  .map_err(|e| SceneError::io(p, e))?;
+  image::load_from_memory(&bytes).map_err(|e| SceneError::image(p, e))
+}
+
+impl Node {
+  /// Transform from this node to its parent, as translation * rotation * scale
+  fn local(&self) -> TransformBuilder {
+    if let Some(m) = self.matrix {
+      return TransformBuilder::Matrix(m);
+    }
+    let t = self.translation.unwrap_or([0.; 3]);
+    let s = self.scale.unwrap_or([1.; 3]);
+    TransformBuilder::Compose(vec![
+      TransformBuilder::Translate(Vec3::new(t[0], t[1], t[2])),
+      TransformBuilder::from_quat(self.rotation.unwrap_or([0., 0., 0., 1.])),
+      TransformBuilder::Scale(Vec3::new(s[0], s[1], s[2])),
+    ])
+  }
+}
+
+/// Width of the film of imported cameras, the height follows from the aspect ratio
+const FILM_WIDTH: u32 = 512;
+/// Bsdf id used for primitives without a material
+const DEFAULT_MATERIAL: &str = "default";
+
+/// Imports the default scene of a glTF 2.0 file (.gltf or .glb). The file is read once and its
+/// meshes are copied into the scene, metallic-roughness materials become principled bsdfs and
+/// punctual lights become point or directional lights. The first camera found is used, or a
+/// camera at (0,0,5) looking at the origin if there is none.
+pub fn from_gltf(p: impl AsRef<Path>) -> Result<RawScene, SceneError> {
+  let p = p.as_ref();
+  let file = p.to_string_lossy().into_owned();
+  let doc = Document::load(p).map_err(|e| SceneError::io(p, e))?;
+  let missing =
+    |kind: &'static str, i: usize| SceneError::dangling(kind, i.to_string(), file.clone());
+  let gltf = &doc.gltf;
+  let roots = match gltf.scenes.get(gltf.scene.unwrap_or(0)) {
+    Some(s) => s.nodes.clone(),
+    // without scenes every node which is not a child is a root
+    None => (0..gltf.nodes.len())
+      .filter(|i| !gltf.nodes.iter().any(|n| n.children.contains(i)))
+      .collect(),
+  };
+  let mut scene = RawScene {
+    lights: vec![],
+    camera: default_camera(),
+    shapes: HashMap::new(),
+    bsdfs: HashMap::new(),
+    bsdf_mapping: HashMap::new(),
+  };
+  scene.bsdfs.insert(
+    String::from(DEFAULT_MATERIAL),
+    BSDFBuilder::Diffuse(from_rgb(Vec3::of(0.8))),
+  );
+  for (i, m) in gltf.materials.iter().enumerate() {
+    let pbr = &m.pbr_metallic_roughness;
+    let [r, g, b, a] = pbr.base_color_factor;
+    // images in their own files are read directly instead of through the glTF
+    let base_color_texture = pbr
+      .base_color_texture
+      .as_ref()
+      .and_then(|t| gltf.textures.get(t.index)?.source)
+      .map(
+        |image| match gltf.images.get(image).and_then(|i| i.uri.as_ref()) {
+          Some(uri) if !uri.starts_with("data:") => {
+            let path = doc.dir.join(uri.replace("%20", " "));
+            TextureBuilder::File(path.to_string_lossy().into_owned())
+          },
+          _ => TextureBuilder::Gltf {
+            file: file.clone(),
+            image,
+          },
+        },
+      );
+    if pbr.metallic_roughness_texture.is_some() {
+      eprintln!(
+        "Ignoring the metallic roughness texture of material {}, using its factors instead",
+        i
+      );
+    }
+    scene
+      .bsdfs
+      .insert(format!("material{}", i), BSDFBuilder::Principled {
+        base_color: from_rgb(Vec3::new(r, g, b)),
+        base_color_texture,
+        metallic: pbr.metallic_factor,
+        roughness: pbr.roughness_factor,
+        alpha: Some(a),
+        alpha_mode: match m.alpha_mode.as_deref() {
+          Some("MASK") => AlphaMode::Mask(m.alpha_cutoff.unwrap_or(0.5)),
+          Some("BLEND") => AlphaMode::Blend,
+          _ => AlphaMode::Opaque,
+        },
+      });
+  }
+  let mut found_camera = false;
+  // nodes form trees, so each node is visited once unless the hierarchy has a cycle
+  let mut visited = HashSet::new();
+  let mut stack = roots
+    .into_iter()
+    .map(|n| (n, TransformBuilder::Identity))
+    .collect::<Vec<_>>();
+  while let Some((n, parent)) = stack.pop() {
+    let node = gltf.nodes.get(n).ok_or_else(|| missing("glTF node", n))?;
+    if !visited.insert(n) {
+      let msg = format!("Node {} is reached more than once in the node hierarchy", n);
+      return Err(SceneError::parse(p, None, msg));
+    }
+    let to_world = TransformBuilder::Compose(vec![parent, node.local()]);
+    if let Some(m) = node.mesh {
+      let mesh = gltf.meshes.get(m).ok_or_else(|| missing("glTF mesh", m))?;
+      for (i, prim) in mesh.primitives.iter().enumerate() {
+        if prim.mode.unwrap_or(TRIANGLES) != TRIANGLES {
+          eprintln!(
+            "Skipping primitive {} of mesh {} which is not triangles",
+            i, m
+          );
+          continue;
+        }
+        let id = format!("node{}_primitive{}", n, i);
+        let bsdf = prim.material.map_or_else(
+          || String::from(DEFAULT_MATERIAL),
+          |m| format!("material{}", m),
+        );
+        let (verts, normals, uvs, faces) =
+          read_primitive(&doc, prim).map_err(|e| SceneError::io(p, e))?;
+        scene.shapes.insert(id.clone(), ShapeBuilder {
+          to_world: to_world.clone(),
+          variant: ShapeVariant::Mesh {
+            verts,
+            normals: Some(normals).filter(|n| !n.is_empty()),
+            uvs,
+            faces,
+          },
+        });
+        scene.bsdf_mapping.insert(id, bsdf);
+      }
+    }
+    let world: Transform4 = to_world.clone().into();
+    if let (Some(c), false) = (node.camera, found_camera) {
+      let camera = gltf
+        .cameras
+        .get(c)
+        .ok_or_else(|| missing("glTF camera", c))?;
+      scene.camera = import_camera(camera, to_world.clone());
+      found_camera = true;
+    }
+    if let Some(LightRef { light }) = node.extensions.light {
+      let light = gltf
+        .extensions
+        .lights
+        .as_ref()
+        .and_then(|l| l.lights.get(light))
+        .ok_or_else(|| missing("glTF light", light))?;
+      let color = from_rgb(
+        light
+          .color
+          .map_or(Vec3::of(1.), |[r, g, b]| Vec3::new(r, g, b)),
+      );
+      let intensity = light.intensity.unwrap_or(1.);
+      let pos = world.apply_point(&Vec3::of(0.));
+      // lights point down -z in their local space
+      let dir = world.apply_vec(&Vec3::new(0., 0., -1.)).norm();
+      match light.kind.as_str() {
+        "directional" => scene.lights.push(Lights::Dir(Dir::new(
+          dir * DIR_LIGHT_DIST,
+          intensity,
+          color,
+        ))),
+        "point" => scene
+          .lights
+          .push(Lights::Point(Point::new(pos, intensity, color))),
+        kind => {
+          eprintln!(
+            "Approximating {} light on node {} as a point light",
+            kind, n
+          );
+          scene
+            .lights
+            .push(Lights::Point(Point::new(pos, intensity, color)));
+        },
+      }
+    }
+    for &c in &node.children {
+      stack.push((c, to_world.clone()));
+    }
+  }
+  Ok(scene)
+}
+
+/// How far away directional lights are placed from what they light
+const DIR_LIGHT_DIST: f32 = 1e3;
+
+fn default_camera() -> CameraBuilder {
+  CameraBuilder {
+    film_builder: FilmBuilder {
+      size: (FILM_WIDTH, FILM_WIDTH),
+    },
+    to_world: TransformBuilder::LookAt {
+      origin: Vec3::new(0., 0., 5.),
+      towards: Vec3::of(0.),
+      up: Vec3::new(0., 1., 0.),
+    },
+    variant: CameraVariant::Perspective {
+      x_fov: 45.,
+      near_clip: 1e-3,
+      far_clip: 1e3,
+      aspect: 1.,
+    },
+    sampler: None,
+    polarizer: None,
+  }
+}
+
+fn import_camera(c: &Camera, to_world: TransformBuilder) -> CameraBuilder {
+  let (variant, aspect) = match (c.kind.as_str(), &c.perspective, &c.orthographic) {
+    ("orthographic", _, Some(o)) => (
+      CameraVariant::Orthographic {
+        near_clip: o.znear,
+        far_clip: o.zfar,
+        aspect: o.xmag / o.ymag,
+      },
+      o.xmag / o.ymag,
+    ),
+    (_, Some(p), _) => {
+      let aspect = p.aspect_ratio.unwrap_or(1.);
+      let x_fov = 2. * ((p.yfov / 2.).tan() * aspect).atan();
+      (
+        CameraVariant::Perspective {
+          x_fov: x_fov.to_degrees(),
+          near_clip: p.znear,
+          far_clip: p.zfar.unwrap_or(1e4),
+          aspect,
+        },
+        aspect,
+      )
+    },
+    _ => return default_camera(),
+  };
+  let height = ((FILM_WIDTH as f32 / aspect).round() as u32).max(1);
+  CameraBuilder {
+    film_builder: FilmBuilder {
+      size: (FILM_WIDTH, height),
+    },
+    // glTF cameras look down -z, but cameras here look down +z
+    to_world: TransformBuilder::Compose(vec![
+      to_world,
+      TransformBuilder::Rotate(Vec3::new(0., 1., 0.), PI),
+    ]),
+    variant,
+    sampler: None,
+    polarizer: None,
+  }
+}
+
+#[cfg(test)]
+mod test_gltf {
+  use super::*;
+  #[test]
+  fn base64() {
+    assert_eq!(decode_base64("aGVsbG8=").unwrap(), b"hello");
+    assert_eq!(decode_base64("AAECAw==").unwrap(), vec![0, 1, 2, 3]);
+  }
+  #[test]
+  fn embedded_triangle() {
+    // one triangle with positions as floats followed by u16 indices, as a data uri
+    let mut bin = vec![];
+    for c in &[0f32, 0., 0., 1., 0., 0., 0., 1., 0.] {
+      bin.extend_from_slice(&c.to_le_bytes());
+    }
+    for i in &[0u16, 1, 2] {
+      bin.extend_from_slice(&i.to_le_bytes());
+    }
+    const CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let encoded = bin
+      .chunks(3)
+      .flat_map(|c| {
+        let bits = c
+          .iter()
+          .enumerate()
+          .fold(0u32, |acc, (i, &b)| acc | (b as u32) << (16 - 8 * i));
+        (0..=c.len()).map(move |i| CHARS[(bits >> (18 - 6 * i) & 63) as usize] as char)
+      })
+      .collect::<String>();
+    assert_eq!(decode_base64(&encoded).unwrap(), bin);
+    let json = format!(
+      r#"{{
+        "scenes": [{{ "nodes": [0] }}],
+        "nodes": [{{ "mesh": 0, "translation": [0, 0, -2] }}],
+        "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "indices": 1 }}] }}],
+        "accessors": [
+          {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }},
+          {{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+        ],
+        "bufferViews": [
+          {{ "buffer": 0, "byteLength": 36 }},
+          {{ "buffer": 0, "byteOffset": 36, "byteLength": 6 }}
+        ],
+        "buffers": [{{ "byteLength": 42, "uri": "data:application/octet-stream;base64,{}" }}]
+      }}"#,
+      encoded
+    );
+    let p = std::env::temp_dir().join("mireba_test_embedded_triangle.gltf");
+    fs::write(&p, json).unwrap();
+    let scene = from_gltf(&p).expect("Failed to import glTF");
+    assert_eq!(scene.shapes.len(), 1);
+    let tris = load_primitive(&p, 0, 0).expect("Failed to load primitive");
+    assert_eq!(tris.len(), 1);
+    fs::remove_file(p).unwrap();
+  }
+  #[test]
+  fn texture_rows() {
+    use crate::{shapes::Shape, texture::Texture};
+    use quick_maths::Ray3;
+    // one triangle spanning a texture whose top row is red and bottom row is blue
+    let dir = std::env::temp_dir();
+    let mut bin = vec![];
+    for c in &[0f32, 0., 0., 1., 0., 0., 0., 1., 0., 0., 0., 1., 0., 0., 1.] {
+      bin.extend_from_slice(&c.to_le_bytes());
+    }
+    for i in &[0u16, 1, 2] {
+      bin.extend_from_slice(&i.to_le_bytes());
+    }
+    fs::write(dir.join("mireba_test_uv_triangle.bin"), &bin).unwrap();
+    let mut img = image::RgbImage::new(1, 2);
+    img.put_pixel(0, 0, image::Rgb([255, 0, 0]));
+    img.put_pixel(0, 1, image::Rgb([0, 0, 255]));
+    img.save(dir.join("mireba_test_uv_rows.png")).unwrap();
+    let json = r#"{
+      "scenes": [{ "nodes": [0] }],
+      "nodes": [{ "mesh": 0 }],
+      "meshes": [{ "primitives": [{
+        "attributes": { "POSITION": 0, "TEXCOORD_0": 1 }, "indices": 2, "material": 0
+      }] }],
+      "materials": [{ "pbrMetallicRoughness": { "baseColorTexture": { "index": 0 } } }],
+      "textures": [{ "source": 0 }],
+      "images": [{ "uri": "mireba_test_uv_rows.png" }],
+      "accessors": [
+        { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" },
+        { "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2" },
+        { "bufferView": 2, "componentType": 5123, "count": 3, "type": "SCALAR" }
+      ],
+      "bufferViews": [
+        { "buffer": 0, "byteLength": 36 },
+        { "buffer": 0, "byteOffset": 36, "byteLength": 24 },
+        { "buffer": 0, "byteOffset": 60, "byteLength": 6 }
+      ],
+      "buffers": [{ "byteLength": 66, "uri": "mireba_test_uv_triangle.bin" }]
+    }"#;
+    let p = dir.join("mireba_test_uv_triangle.gltf");
+    fs::write(&p, json).unwrap();
+    let mut scene = from_gltf(&p).expect("Failed to import glTF");
+    let texture = match scene.bsdfs.remove("material0") {
+      Some(BSDFBuilder::Principled {
+        base_color_texture: Some(t),
+        ..
+      }) => t.build().expect("Failed to load texture"),
+      b => panic!("Expected a textured material but got {:?}", b),
+    };
+    let tris = load_primitive(&p, 0, 0).expect("Failed to load primitive");
+    let sample = |x: f32, y: f32| {
+      let ray = Ray3::new(Vec3::new(x, y, 1.), Vec3::new(0., 0., -1.));
+      let si = tris.intersect_ray(&ray).expect("Missed the triangle");
+      texture.sample(si.uv)
+    };
+    // v = 0 is the top row of the image in glTF
+    let (top, bottom) = (sample(0.1, 0.1), sample(0.1, 0.8));
+    let (red, blue) = (
+      from_rgb(Vec3::new(1., 0., 0.)),
+      from_rgb(Vec3::new(0., 0., 1.)),
+    );
+    assert_eq!((top, bottom), (red, blue));
+    for f in &["mireba_test_uv_triangle.bin", "mireba_test_uv_rows.png"] {
+      fs::remove_file(dir.join(f)).unwrap();
+    }
+    fs::remove_file(p).unwrap();
+  }
+  #[test]
+  fn node_cycle() {
+    let json = r#"{
+      "scenes": [{ "nodes": [0] }],
+      "nodes": [{ "children": [1] }, { "children": [0] }]
+    }"#;
+    let p = std::env::temp_dir().join("mireba_test_node_cycle.gltf");
+    fs::write(&p, json).unwrap();
+    let err = from_gltf(&p).unwrap_err().to_string();
+    assert!(err.contains("more than once"), "{}", err);
+    fs::remove_file(p).unwrap();
+  }
+  #[test]
+  fn missing_buffers_and_accessors() {
+    let load = |attributes: &str, indices: usize| {
+      let json = format!(
+        r#"{{
+          "meshes": [{{ "primitives": [{{ "attributes": {}, "indices": {} }}] }}],
+          "accessors": [
+            {{ "componentType": 5126, "count": 3, "type": "VEC3" }},
+            {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }}
+          ],
+          "bufferViews": [{{ "buffer": 1, "byteLength": 36 }}],
+          "buffers": []
+        }}"#,
+        attributes, indices
+      );
+      let p = std::env::temp_dir().join("mireba_test_missing_buffers.gltf");
+      fs::write(&p, json).unwrap();
+      let err = load_primitive(&p, 0, 0).unwrap_err().to_string();
+      fs::remove_file(p).unwrap();
+      err
+    };
+    let err = load(r#"{ "POSITION": 1 }"#, 0);
+    assert!(err.contains("Missing buffer 1"), "{}", err);
+    let err = load(r#"{ "POSITION": 0 }"#, 7);
+    assert!(err.contains("Missing accessor 7"), "{}", err);
+  }
+}
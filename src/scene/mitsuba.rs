@@ -0,0 +1,597 @@
+use super::{
+  xml::{self, Element},
+  RawScene, SceneError,
+};
+use crate::{
+  bsdf::builder::Builder as BSDFBuilder,
+  camera::builder::{Builder as CameraBuilder, Variant as CameraVariant},
+  film::builder::Builder as FilmBuilder,
+  light::{dir::Dir, point::Point, Lights},
+  sampler::builder::{Builder as SamplerBuilder, Variant as SamplerVariant},
+  shapes::{builder::Variant as ShapeVariant, Builder as ShapeBuilder},
+  spectrum::{from_mono, from_rgb, Spectrum},
+  transform::Builder as TransformBuilder,
+};
+use quick_maths::{Transform4, Vec3};
+use std::{
+  collections::HashMap,
+  fs,
+  path::{Path, PathBuf},
+};
+
+/// Errors are raised without the file, which is added once the whole scene is imported
+fn invalid(e: &Element, msg: String) -> SceneError {
+  SceneError::parse(
+    PathBuf::new(),
+    Some(e.line),
+    format!("{} in <{}>", msg, e.name),
+  )
+}
+
+fn unsupported(e: &Element, msg: String) -> SceneError {
+  SceneError::unsupported(PathBuf::new(), Some(e.line), msg)
+}
+
+/// Bsdf used by shapes which do not specify one, matching Mitsuba's default
+const DEFAULT_BSDF: &str = "default";
+
+/// How far away directional lights are placed, since they are offset from the points they light
+const DIR_LIGHT_DIST: f32 = 1e3;
+
+/// Converts a subset of Mitsuba 2 scene elements into a raw scene
+struct Importer<'a> {
+  dir: &'a Path,
+  scene: RawScene,
+  /// Used to name shapes without an id
+  shape_count: usize,
+}
+
+/// Splits a list of numbers separated by commas or whitespace
+fn numbers(e: &Element, s: &str) -> Result<Vec<f32>, SceneError> {
+  s.split(|c: char| c == ',' || c.is_whitespace())
+    .filter(|v| !v.is_empty())
+    .map(|v| {
+      v.parse()
+        .map_err(|_| invalid(e, format!("Invalid number {:?}", v)))
+    })
+    .collect()
+}
+
+fn required<'e>(e: &'e Element, attr: &str) -> Result<&'e str, SceneError> {
+  e.attr(attr)
+    .ok_or_else(|| invalid(e, format!("Missing attribute {}", attr)))
+}
+
+/// Reads a vector from either x, y, z attributes or a value attribute
+fn vector(e: &Element, default: f32) -> Result<Vec3, SceneError> {
+  if let Some(v) = e.attr("value") {
+    return match numbers(e, v)?.as_slice() {
+      &[s] => Ok(Vec3::of(s)),
+      &[x, y, z] => Ok(Vec3::new(x, y, z)),
+      _ => Err(invalid(
+        e,
+        format!("Expected 1 or 3 values but got {:?}", v),
+      )),
+    };
+  }
+  let mut out = Vec3::of(default);
+  for (i, axis) in ["x", "y", "z"].iter().enumerate() {
+    if let Some(v) = e.attr(axis) {
+      out[i] = numbers(e, v)?
+        .first()
+        .copied()
+        .ok_or_else(|| invalid(e, format!("Empty {}", axis)))?;
+    }
+  }
+  Ok(out)
+}
+
+/// Finds the child property with some name
+fn property<'e>(e: &'e Element, name: &str) -> Option<&'e Element> {
+  e.children.iter().find(|c| c.attr("name") == Some(name))
+}
+
+fn float(e: &Element, name: &str) -> Result<Option<f32>, SceneError> {
+  property(e, name)
+    .map(|p| {
+      let v = required(p, "value")?;
+      v.parse()
+        .map_err(|_| invalid(p, format!("Invalid number {:?}", v)))
+    })
+    .transpose()
+}
+
+fn string<'e>(e: &'e Element, name: &str) -> Result<Option<&'e str>, SceneError> {
+  property(e, name).map(|p| required(p, "value")).transpose()
+}
+
+/// Reads a color from an `rgb`, `spectrum` or `float` property
+fn spectrum(e: &Element, name: &str) -> Result<Option<Spectrum>, SceneError> {
+  let p = match property(e, name) {
+    None => return Ok(None),
+    Some(p) => p,
+  };
+  let vals = numbers(p, required(p, "value")?)?;
+  Ok(Some(match (p.name.as_str(), vals.as_slice()) {
+    (_, &[v]) => from_mono(v),
+    ("rgb", &[r, g, b]) => from_rgb(Vec3::new(r, g, b)),
+    _ =>
+      return Err(invalid(
+        p,
+        String::from("Expected a single value or rgb triple"),
+      )),
+  }))
+}
+
+/// Reads a `<transform>` element, where each operation is applied after the previous ones
+fn transform(e: &Element) -> Result<TransformBuilder, SceneError> {
+  let mut ops = vec![];
+  for op in &e.children {
+    ops.push(match op.name.as_str() {
+      "translate" => TransformBuilder::Translate(vector(op, 0.)?),
+      "scale" => TransformBuilder::Scale(vector(op, 1.)?),
+      "rotate" => {
+        let angle: f32 = required(op, "angle")?
+          .parse()
+          .map_err(|_| invalid(op, String::from("Invalid angle")))?;
+        TransformBuilder::Rotate(vector(op, 0.)?, angle.to_radians())
+      },
+      "lookat" => {
+        let point = |attr| numbers(op, required(op, attr)?);
+        let to_vec = |v: Vec<f32>| match v.as_slice() {
+          &[x, y, z] => Ok(Vec3::new(x, y, z)),
+          _ => Err(invalid(op, String::from("Expected 3 values"))),
+        };
+        let up = op.attr("up").unwrap_or("0, 1, 0");
+        TransformBuilder::LookAt {
+          origin: to_vec(point("origin")?)?,
+          towards: to_vec(point("target")?)?,
+          up: to_vec(numbers(op, up)?)?,
+        }
+      },
+      "matrix" => {
+        let m = numbers(op, required(op, "value")?)?;
+        if m.len() != 16 {
+          return Err(invalid(op, String::from("Expected 16 values")));
+        }
+        // Mitsuba matrices are row major
+        let mut col_major = [0.; 16];
+        for (i, v) in m.into_iter().enumerate() {
+          col_major[(i % 4) * 4 + i / 4] = v;
+        }
+        TransformBuilder::Matrix(col_major)
+      },
+      other => return Err(unsupported(op, format!("transform {}", other))),
+    });
+  }
+  ops.reverse();
+  Ok(TransformBuilder::Compose(ops))
+}
+
+fn to_world(e: &Element) -> Result<TransformBuilder, SceneError> {
+  e.children
+    .iter()
+    .find(|c| c.name == "transform")
+    .map_or(Ok(TransformBuilder::Identity), transform)
+}
+
+impl<'a> Importer<'a> {
+  fn path(&self, e: &Element, name: &str) -> Result<String, SceneError> {
+    let file = string(e, name)?.ok_or_else(|| invalid(e, format!("Missing {}", name)))?;
+    Ok(self.dir.join(file).to_string_lossy().into_owned())
+  }
+  fn bsdf(&mut self, e: &Element) -> Result<BSDFBuilder, SceneError> {
+    let kind = required(e, "type")?;
+    Ok(match kind {
+      // two sided surfaces are handled the same as one sided ones
+      "twosided" => {
+        let inner = e
+          .children
+          .iter()
+          .find(|c| c.name == "bsdf")
+          .ok_or_else(|| invalid(e, String::from("twosided requires a nested bsdf")))?;
+        self.bsdf(inner)?
+      },
+      "diffuse" =>
+        BSDFBuilder::Diffuse(spectrum(e, "reflectance")?.unwrap_or_else(|| from_mono(0.5))),
+      "dielectric" | "roughdielectric" => BSDFBuilder::Dielectric {
+        eta: float(e, "int_ior")?.unwrap_or(1.5046) / float(e, "ext_ior")?.unwrap_or(1.000_277),
+        exponent: exponent(e)?,
+      },
+      "conductor" | "roughconductor" => BSDFBuilder::Conductor {
+        eta: float(e, "eta")?.unwrap_or(0.),
+        k: float(e, "k")?.unwrap_or(1.),
+        exponent: exponent(e)?,
+      },
+      other => {
+        eprintln!(
+          "Unsupported bsdf {} on line {}, using diffuse instead",
+          other, e.line
+        );
+        BSDFBuilder::Diffuse(from_mono(0.5))
+      },
+    })
+  }
+  fn shape(&mut self, e: &Element) -> Result<(), SceneError> {
+    let kind = required(e, "type")?;
+    let variant = match kind {
+      "obj" => ShapeVariant::Obj {
+        file: self.path(e, "filename")?,
+        use_mtls: None,
+        subdivision_levels: None,
+        subdivision: None,
+        crease_angle: None,
+        displacement: None,
+      },
+      "ply" => ShapeVariant::Ply {
+        file: self.path(e, "filename")?,
+      },
+      "stl" => ShapeVariant::Stl {
+        file: self.path(e, "filename")?,
+        binary: None,
+      },
+      "sphere" => ShapeVariant::Sphere {
+        center: property(e, "center").map_or(Ok(Vec3::of(0.)), |p| vector(p, 0.))?,
+        radius: float(e, "radius")?.unwrap_or(1.),
+      },
+      "cube" => ShapeVariant::Box {
+        min: Vec3::of(-1.),
+        max: Vec3::of(1.),
+      },
+      "cylinder" => {
+        // Mitsuba cylinders go between two points, which are only supported along z
+        let p0 = property(e, "p0").map_or(Ok(Vec3::of(0.)), |p| vector(p, 0.))?;
+        let p1 = property(e, "p1").map_or(Ok(Vec3::new(0., 0., 1.)), |p| vector(p, 0.))?;
+        if p0.x() != p1.x() || p0.y() != p1.y() {
+          return Err(unsupported(
+            e,
+            String::from("cylinders which are not along z"),
+          ));
+        }
+        ShapeVariant::Cylinder {
+          radius: float(e, "radius")?.unwrap_or(1.),
+          z_min: p0.z().min(p1.z()),
+          z_max: p0.z().max(p1.z()),
+          phi_max: None,
+        }
+      },
+      "disk" => ShapeVariant::Disk {
+        radius: 1.,
+        inner_radius: None,
+        height: None,
+        phi_max: None,
+      },
+      // [-1, 1]^2 on the xy plane facing +z
+      "rectangle" => ShapeVariant::Plane {
+        normal: Vec3::new(0., 0., 1.),
+        w: 0.,
+        up: Vec3::new(0., 1., 0.),
+        width: 2.,
+        height: 2.,
+      },
+      other => {
+        eprintln!("Skipping unsupported shape {} on line {}", other, e.line);
+        return Ok(());
+      },
+    };
+    let id = match e.attr("id") {
+      Some(id) => id.to_string(),
+      None => format!("shape{}", self.shape_count),
+    };
+    self.shape_count += 1;
+    let mut bsdf = String::from(DEFAULT_BSDF);
+    for c in &e.children {
+      match c.name.as_str() {
+        "ref" => bsdf = required(c, "id")?.to_string(),
+        "bsdf" => {
+          bsdf = format!("{}_bsdf", id);
+          let b = self.bsdf(c)?;
+          self.scene.bsdfs.insert(bsdf.clone(), b);
+        },
+        "emitter" => {
+          let kind = required(c, "type")?;
+          return Err(unsupported(c, format!("{} emitters on shapes", kind)));
+        },
+        _ => (),
+      }
+    }
+    if !self.scene.bsdfs.contains_key(&bsdf) {
+      return Err(SceneError::dangling("bsdf", bsdf, format!("shape {}", id)));
+    }
+    self.scene.shapes.insert(id.clone(), ShapeBuilder {
+      to_world: to_world(e)?,
+      variant,
+    });
+    self.scene.bsdf_mapping.insert(id, bsdf);
+    Ok(())
+  }
+  fn emitter(&mut self, e: &Element) -> Result<(), SceneError> {
+    let kind = required(e, "type")?;
+    let light = match kind {
+      "point" => {
+        let pos = property(e, "position").map_or(Ok(Vec3::of(0.)), |p| vector(p, 0.))?;
+        let pos = Transform4::from(to_world(e)?).apply_point(&pos);
+        let intensity = spectrum(e, "intensity")?.unwrap_or_else(|| from_mono(1.));
+        Lights::Point(Point::new(pos, 1., intensity))
+      },
+      "directional" => {
+        let dir = property(e, "direction").map_or(Ok(Vec3::new(0., 0., 1.)), |p| vector(p, 0.))?;
+        let dir = Transform4::from(to_world(e)?).apply_vec(&dir).norm();
+        let irradiance = spectrum(e, "irradiance")?.unwrap_or_else(|| from_mono(1.));
+        Lights::Dir(Dir::new(dir * DIR_LIGHT_DIST, 1., irradiance))
+      },
+      other => {
+        eprintln!("Skipping unsupported emitter {} on line {}", other, e.line);
+        return Ok(());
+      },
+    };
+    self.scene.lights.push(light);
+    Ok(())
+  }
+  fn sensor(&mut self, e: &Element) -> Result<(), SceneError> {
+    let film = e.children.iter().find(|c| c.name == "film");
+    let size = |name: &str, default: u32| -> Result<u32, SceneError> {
+      Ok(
+        film
+          .map_or(Ok(None), |f| float(f, name))?
+          .map_or(default, |v| v as u32),
+      )
+    };
+    let (width, height) = (size("width", 768)?, size("height", 576)?);
+    let aspect = width as f32 / height as f32;
+    let fov = float(e, "fov")?.unwrap_or(45.);
+    // Convert the field of view to be along x
+    let x_fov = match string(e, "fov_axis")?.unwrap_or("x") {
+      "y" => 2. * ((fov.to_radians() / 2.).tan() * aspect).atan().to_degrees(),
+      _ => fov,
+    };
+    let near_clip = float(e, "near_clip")?.unwrap_or(1e-2);
+    let far_clip = float(e, "far_clip")?.unwrap_or(1e4);
+    let variant = match required(e, "type")? {
+      "orthographic" => CameraVariant::Orthographic {
+        near_clip,
+        far_clip,
+        aspect,
+      },
+      "perspective" | "thinlens" => CameraVariant::Perspective {
+        x_fov,
+        near_clip,
+        far_clip,
+        aspect,
+      },
+      other => return Err(unsupported(e, format!("sensor {}", other))),
+    };
+    let sampler = e
+      .children
+      .iter()
+      .find(|c| c.name == "sampler")
+      .map(|s| -> Result<_, SceneError> {
+        Ok(SamplerBuilder {
+          seed: float(s, "seed")?.unwrap_or(0.) as u64,
+          variant: SamplerVariant::Uniform,
+        })
+      })
+      .transpose()?;
+    self.scene.camera = CameraBuilder {
+      film_builder: FilmBuilder {
+        size: (width, height),
+      },
+      to_world: to_world(e)?,
+      variant,
+      sampler,
+      polarizer: None,
+    };
+    Ok(())
+  }
+}
+
+/// Mitsuba's default sensor, which is at the origin looking down +z
+fn default_camera() -> CameraBuilder {
+  let (width, height) = (768, 576);
+  CameraBuilder {
+    film_builder: FilmBuilder {
+      size: (width, height),
+    },
+    to_world: TransformBuilder::Identity,
+    variant: CameraVariant::Perspective {
+      x_fov: 45.,
+      near_clip: 1e-2,
+      far_clip: 1e4,
+      aspect: width as f32 / height as f32,
+    },
+    sampler: None,
+    polarizer: None,
+  }
+}
+
+/// Converts a Mitsuba roughness into a lobe exponent, smooth surfaces are nearly mirrors
+fn exponent(e: &Element) -> Result<f32, SceneError> {
+  let alpha = float(e, "alpha")?.unwrap_or(0.01).max(1e-3);
+  Ok(2. / (alpha * alpha) - 2.)
+}
+
+/// Replaces `$name` in every attribute with its value from defaults or overrides
+fn substitute(e: &mut Element, params: &HashMap<String, String>) -> Result<(), SceneError> {
+  let line = e.line;
+  for (_, v) in e.attrs.iter_mut() {
+    let mut out = String::with_capacity(v.len());
+    let mut rest = v.as_str();
+    while let Some(start) = rest.find('$') {
+      out.push_str(&rest[..start]);
+      rest = &rest[start + 1..];
+      let len = rest
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or_else(|| rest.len());
+      let name = &rest[..len];
+      let value = params.get(name).ok_or_else(|| {
+        SceneError::parse(
+          PathBuf::new(),
+          Some(line),
+          format!("Undefined parameter ${}", name),
+        )
+      })?;
+      out.push_str(value);
+      rest = &rest[len..];
+    }
+    out.push_str(rest);
+    *v = out;
+  }
+  for c in e.children.iter_mut() {
+    substitute(c, params)?;
+  }
+  Ok(())
+}
+
+/// Imports a subset of the Mitsuba 2 XML scene format. Supported elements are:
+/// - `<default name value/>`, whose parameters are used as `$name` in attributes and can be
+///   overridden by `params`
+/// - `<shape>` of type obj, ply, stl, sphere, cube, cylinder (along z), disk and rectangle, with
+///   a nested `<bsdf>` or `<ref id/>`. Area lights are not supported, so shapes with a nested
+///   `<emitter>` are an error.
+/// - `<bsdf>` of type diffuse, dielectric, conductor and their rough variants, or twosided
+/// - `<sensor>` of type perspective or orthographic with a `<film>` width and height
+/// - `<emitter>` of type point or directional
+/// - `<transform>` with translate, rotate, scale, lookat and matrix
+///
+/// Integrators are ignored, and unsupported shapes, bsdfs and emitters are skipped with a
+/// warning.
+pub fn from_mitsuba(
+  p: impl AsRef<Path>,
+  params: &HashMap<String, String>,
+) -> Result<RawScene, SceneError> {
+  let p = p.as_ref();
+  let src = fs::read_to_string(p).map_err(|e| SceneError::io(p, e))?;
+  let dir = p.parent().unwrap_or_else(|| Path::new(""));
+  parse(&src, dir, params).map_err(|e| e.at(p))
+}
+
+fn parse(src: &str, dir: &Path, params: &HashMap<String, String>) -> Result<RawScene, SceneError> {
+  let mut root = xml::parse(src)?;
+  if root.name != "scene" {
+    return Err(invalid(&root, String::from("Expected a <scene>")));
+  }
+  let mut all_params = HashMap::new();
+  for d in root.children.iter().filter(|c| c.name == "default") {
+    all_params.insert(
+      required(d, "name")?.to_string(),
+      required(d, "value")?.to_string(),
+    );
+  }
+  all_params.extend(params.iter().map(|(k, v)| (k.clone(), v.clone())));
+  substitute(&mut root, &all_params)?;
+  let mut scene = RawScene {
+    lights: vec![],
+    camera: default_camera(),
+    shapes: HashMap::new(),
+    bsdfs: HashMap::new(),
+    bsdf_mapping: HashMap::new(),
+  };
+  scene.bsdfs.insert(
+    String::from(DEFAULT_BSDF),
+    BSDFBuilder::Diffuse(from_mono(0.5)),
+  );
+  let mut importer = Importer {
+    dir,
+    scene,
+    shape_count: 0,
+  };
+  // bsdfs are declared first so shapes can refer to them regardless of order
+  for c in root.children.iter().filter(|c| c.name == "bsdf") {
+    let id = required(c, "id")?.to_string();
+    let b = importer.bsdf(c)?;
+    importer.scene.bsdfs.insert(id, b);
+  }
+  for c in &root.children {
+    match c.name.as_str() {
+      "shape" => importer.shape(c)?,
+      "emitter" => importer.emitter(c)?,
+      "sensor" => importer.sensor(c)?,
+      "bsdf" | "default" | "integrator" => (),
+      other => eprintln!(
+        "Skipping unsupported element <{}> on line {}",
+        other, c.line
+      ),
+    }
+  }
+  Ok(importer.scene)
+}
+
+#[cfg(test)]
+mod test_mitsuba {
+  use super::*;
+  #[test]
+  fn cornell_like() {
+    let src = r#"
+      <scene version="2.0.0">
+        <default name="radius" value="0.5"/>
+        <integrator type="path"/>
+        <sensor type="perspective">
+          <float name="fov" value="40"/>
+          <transform name="to_world">
+            <lookat origin="0, 0, -5" target="0, 0, 0" up="0, 1, 0"/>
+          </transform>
+          <film type="hdrfilm">
+            <integer name="width" value="64"/>
+            <integer name="height" value="32"/>
+          </film>
+        </sensor>
+        <bsdf type="diffuse" id="red">
+          <rgb name="reflectance" value="0.8, 0.1, 0.1"/>
+        </bsdf>
+        <shape type="sphere">
+          <float name="radius" value="$radius"/>
+          <ref id="red"/>
+        </shape>
+        <shape type="cube">
+          <transform name="to_world">
+            <scale value="2"/>
+            <translate x="1"/>
+          </transform>
+        </shape>
+        <emitter type="point">
+          <point name="position" value="0, 2, 0"/>
+          <rgb name="intensity" value="10, 10, 10"/>
+        </emitter>
+      </scene>
+    "#;
+    let mut params = HashMap::new();
+    let scene = parse(src, Path::new(""), &params).expect("Failed to import scene");
+    assert_eq!(scene.shapes.len(), 2);
+    assert_eq!(scene.lights.len(), 1);
+    assert_eq!(scene.bsdf_mapping["shape0"], "red");
+    assert_eq!(scene.bsdf_mapping["shape1"], DEFAULT_BSDF);
+    assert_eq!(scene.camera.film_builder.size, (64, 32));
+    match &scene.shapes["shape0"].variant {
+      ShapeVariant::Sphere { radius, .. } => assert_eq!(*radius, 0.5),
+      v => panic!("Unexpected shape {:?}", v),
+    }
+    params.insert(String::from("radius"), String::from("2"));
+    let scene = parse(src, Path::new(""), &params).expect("Failed to import scene");
+    match &scene.shapes["shape0"].variant {
+      ShapeVariant::Sphere { radius, .. } => assert_eq!(*radius, 2.),
+      v => panic!("Unexpected shape {:?}", v),
+    }
+  }
+  #[test]
+  fn transform_order() {
+    let src = r#"
+      <scene version="2.0.0">
+        <shape type="rectangle">
+          <transform name="to_world">
+            <scale value="2"/>
+            <translate x="1"/>
+            <matrix value="-1 0 0 0  0 1 0 0  0 0 1 0  0 0 0 1"/>
+          </transform>
+        </shape>
+      </scene>
+    "#;
+    let scene = parse(src, Path::new(""), &HashMap::new()).expect("Failed to import scene");
+    let to_world = Transform4::from(scene.shapes["shape0"].to_world.clone());
+    // scaled, then translated and then reflected
+    let p = to_world.apply_point(&Vec3::new(1., 0., 0.));
+    assert!((p - Vec3::new(-3., 0., 0.)).magn() < 1e-5, "{:?}", p);
+    let area =
+      r#"<scene version="2.0.0"><shape type="sphere"><emitter type="area"/></shape></scene>"#;
+    assert!(parse(area, Path::new(""), &HashMap::new()).is_err());
+  }
+}
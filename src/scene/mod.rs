@@ -0,0 +1,261 @@
+/// Validating scenes without rendering them
+pub mod check;
+/// Errors from loading scenes
+pub mod error;
+/// Reading JSON and TOML scene files and their includes
+pub mod file;
+/// Importing glTF 2.0 scenes
+pub mod gltf;
+/// Importing Mitsuba 2 XML scenes
+pub mod mitsuba;
+/// Importing PBRT-v3 scenes
+pub mod pbrt;
+/// XML parsing for scene formats
+pub mod xml;
+
+use crate::{
+  accelerator::Accelerator,
+  bsdf::{builder::Builder as BSDFBuilder, BSDFImpl},
+  camera::{builder::Builder as CameraBuilder, Cameras},
+  integrator::Integrator,
+  interaction::SurfaceInteraction,
+  light::Lights,
+  shapes::{Builder as ShapeBuilder, Shapes},
+  spectrum::from_rgb,
+  transform::Builder as TransformBuilder,
+};
+pub use error::SceneError;
+use quick_maths::{Ray3, Vec3, Vector};
+use std::collections::HashMap;
+
+// TODO add Serde for RawScene
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct RawScene {
+  /// List of lights
+  #[serde(default)]
+  lights: Vec<Lights>,
+  /// Camera
+  camera: CameraBuilder,
+  /// List of shapes with optional ids
+  #[serde(default)]
+  shapes: HashMap<String, ShapeBuilder>,
+  /// List of BSDFs with optional ids
+  #[serde(default)]
+  bsdfs: HashMap<String, BSDFBuilder>,
+  /// Mapping between shapes -> bsdf
+  #[serde(default)]
+  bsdf_mapping: HashMap<String, String>,
+}
+
+impl RawScene {
+  /// Create an acceleration structure from a raw scene, loading the files it refers to
+  pub fn build<El, Acc: Accelerator>(self) -> Result<Scene<El, Acc>, SceneError> {
+    let RawScene {
+      lights,
+      camera,
+      shapes,
+      bsdfs,
+      bsdf_mapping,
+    } = self;
+    let mut id_to_idx = HashMap::new();
+    let mut built = vec![];
+    for (i, (id, b)) in bsdfs.into_iter().enumerate() {
+      id_to_idx.insert(id, i);
+      built.push(b.build()?);
+    }
+    let mut bsdfs = built;
+    camera.validate()?;
+    // the camera is built first so that meshes can be tessellated by their size on screen
+    let camera: Cameras = camera.into();
+    let mut all_shapes = vec![];
+    for (shape_id, shape_builder) in shapes {
+      let bsdf = bsdf_mapping
+        .get(&shape_id)
+        .ok_or_else(|| SceneError::dangling("bsdf_mapping entry", &shape_id, "shapes"))?;
+      let idx = *id_to_idx
+        .get(bsdf)
+        .ok_or_else(|| SceneError::dangling("bsdf", bsdf, format!("shape {}", shape_id)))?;
+      let mut geometry = shape_builder.build(Some(&camera))?;
+      // primitives without a material of their own use the shape's bsdf
+      let mut slots = vec![idx];
+      for mtl in geometry.take_materials() {
+        slots.push(bsdfs.len());
+        bsdfs.push(BSDFImpl::MTL(mtl));
+      }
+      all_shapes.push(Shapes::new(geometry, slots)?);
+    }
+    let accelerator = Acc::build(all_shapes.into_iter());
+    Ok(Scene {
+      lights,
+      camera,
+      env_light: None,
+      accelerator,
+      bsdfs,
+    })
+  }
+  /// Creates an example
+  pub fn example() -> Self {
+    let lights = vec![Lights::Point(crate::light::point::Point::new(
+      Vec3::new(0.0, 1.0, -1.0),
+      10.0,
+      from_rgb(Vec3::new(0.3, 0.7, 0.9)),
+    ))];
+    let mut shapes = HashMap::new();
+    shapes.insert(String::from("central_sphere"), ShapeBuilder {
+      to_world: TransformBuilder::Identity,
+      variant: crate::shapes::builder::Variant::Sphere {
+        center: Vec3::new(0., 0., 10.),
+        radius: 1.0,
+      },
+    });
+    let mut bsdfs = HashMap::new();
+    bsdfs.insert(
+      String::from("debug"),
+      BSDFBuilder::Diffuse(from_rgb(Vec3::new(0.7, 0.8, 0.5))),
+    );
+    let mut bsdf_mapping = HashMap::new();
+    bsdf_mapping.insert(String::from("central_sphere"), String::from("debug"));
+    Self {
+      lights,
+      camera: CameraBuilder {
+        film_builder: crate::film::builder::Builder { size: (512, 512) },
+        to_world: TransformBuilder::LookAt {
+          origin: Vec3::of(0.0),
+          towards: Vec3::new(0., 0., 1.),
+          up: Vec3::new(0., 1., 0.),
+        },
+        variant: crate::camera::builder::Variant::Perspective {
+          x_fov: 30.0,
+          near_clip: 1.0e-3,
+          far_clip: 1.0e3,
+          aspect: 1.0,
+        },
+        sampler: None,
+        polarizer: None,
+      },
+      // TODO fill in examples here
+      shapes,
+      bsdfs,
+      bsdf_mapping,
+    }
+  }
+}
+
+/// How many surfaces a ray passes through because of their alpha before it is stopped
+const MAX_ALPHA_SKIPS: usize = 64;
+
+/// How far along a ray to continue past surfaces it passes through
+const ALPHA_EPS: f32 = 1e-4;
+
+/// Deterministic value in [0, 1) from a position
+fn hash_unit(p: &Vec3) -> f32 {
+  let Vector(coords) = *p;
+  let mut h: u32 = 0x811c_9dc5;
+  for c in coords.iter() {
+    h = (h ^ c.to_bits()).wrapping_mul(0x0100_0193);
+    h ^= h >> 15;
+  }
+  (h >> 8) as f32 / (1u32 << 24) as f32
+}
+
+#[derive(Debug)]
+pub struct Scene<EnvLight, Acc> {
+  /// List of lights for this scene
+  pub lights: Vec<Lights>,
+
+  /// Camera for this scene
+  pub camera: Cameras,
+
+  /// Environment light
+  pub env_light: Option<EnvLight>,
+
+  /// List of BSDFs used in this implementation
+  bsdfs: Vec<BSDFImpl>,
+
+  /// Acceleration data structure
+  accelerator: Acc,
+}
+
+impl<El, Acc> Scene<El, Acc>
+where
+  Acc: Accelerator,
+{
+  // TODO build something that creates a scene from iterators of shapes
+  // pub fn new(items: Vec<Lights>, ...)
+  pub fn render<I: Integrator>(&self, int: I) { int.render(self); }
+  /// Intersects a ray with the scene, passing through surfaces where their material's alpha is
+  /// 0. Partially covered surfaces are hit with a probability of their alpha, which is the same
+  /// for all rays at a point so shadows match what the camera sees.
+  pub fn intersect_ray(&self, r: &Ray3) -> Option<(SurfaceInteraction, &Shapes)> {
+    let mut start = 0.;
+    for _ in 0..MAX_ALPHA_SKIPS {
+      let (mut si, shape) = self
+        .accelerator
+        .intersect_ray(&Ray3::new(r.at(start), r.dir))?;
+      si.it.t += start;
+      let alpha = self.bsdf(&si).alpha(&si);
+      if alpha >= 1. || alpha > hash_unit(&si.it.p) {
+        return Some((si, shape));
+      }
+      start = si.it.t + ALPHA_EPS;
+    }
+    None
+  }
+  /// Returns the bsdf of the material at some surface interaction
+  pub fn bsdf(&self, si: &SurfaceInteraction) -> &BSDFImpl { &self.bsdfs[si.material] }
+}
+
+#[cfg(test)]
+mod test_scene {
+  use super::*;
+  use crate::accelerator::naive::Naive;
+  #[test]
+  fn dangling_bsdf() {
+    let mut scene = RawScene::example();
+    scene
+      .bsdf_mapping
+      .insert(String::from("central_sphere"), String::from("missing"));
+    match scene.build::<(), Naive>() {
+      Err(SceneError::Dangling { kind, id, .. }) =>
+        assert_eq!((kind, id.as_str()), ("bsdf", "missing")),
+      r => panic!("Expected a dangling bsdf but got {:?}", r.map(|_| ())),
+    }
+  }
+  #[test]
+  fn polarizer_requires_feature() {
+    let mut scene = RawScene::example();
+    scene.camera.polarizer = Some(crate::camera::polarizer::Polarizer::DivisionOfFocalPlane);
+    let built = scene.build::<(), Naive>().map(|_| ());
+    assert_eq!(built.is_ok(), cfg!(feature = "polarized"), "{:?}", built);
+  }
+  #[test]
+  fn invalid_shapes() {
+    use crate::shapes::builder::Variant;
+    let build = |variant: Variant| {
+      let mut scene = RawScene::example();
+      scene.shapes.get_mut("central_sphere").unwrap().variant = variant;
+      scene.build::<(), Naive>().map(|_| ())
+    };
+    let mesh = |verts: usize, faces: Vec<Vec3<u32>>| Variant::Mesh {
+      verts: vec![Vec3::of(0.); verts],
+      normals: None,
+      uvs: None,
+      faces,
+    };
+    let sphere = Variant::Sphere {
+      center: Vec3::of(0.),
+      radius: -1.,
+    };
+    let invalid = |variant| matches!(build(variant), Err(SceneError::Invalid { .. }));
+    assert!(invalid(sphere));
+    assert!(invalid(mesh(0, vec![])));
+    assert!(invalid(mesh(3, vec![Vec3::new(0, 1, 3)])));
+    let geometry = RawScene::example()
+      .shapes
+      .remove("central_sphere")
+      .unwrap()
+      .build(None)
+      .unwrap();
+    assert!(Shapes::new(geometry, vec![]).is_err());
+  }
+}
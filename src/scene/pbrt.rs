@@ -0,0 +1,873 @@
+use super::{RawScene, SceneError};
+use crate::{
+  bsdf::builder::Builder as BSDFBuilder,
+  camera::builder::{Builder as CameraBuilder, Variant as CameraVariant},
+  film::builder::Builder as FilmBuilder,
+  light::{dir::Dir, point::Point, Lights},
+  polarized::Builder as SpectrumBuilder,
+  sampler::builder::Builder as SamplerBuilder,
+  shapes::{builder::Variant as ShapeVariant, Builder as ShapeBuilder},
+  spectrum::{from_mono, from_rgb, Spectrum},
+  transform::Builder as TransformBuilder,
+};
+use quick_maths::{Transform4, Vec2, Vec3};
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+/// Material used before any `Material` directive, matching PBRT's default
+const DEFAULT_BSDF: &str = "default";
+
+/// How far away directional lights are placed, since they are offset from the points they light
+const DIR_LIGHT_DIST: f32 = 1e3;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  Ident(String),
+  Str(String),
+  Num(f32),
+  Open,
+  Close,
+}
+
+/// Where a token came from, as an index into the list of files read and a line number
+#[derive(Debug, Clone, Copy)]
+struct Loc {
+  file: usize,
+  line: usize,
+}
+
+fn tokenize(src: &str, file: usize) -> Result<Vec<(Token, Loc)>, (String, usize)> {
+  let mut out = vec![];
+  let mut line = 1;
+  let mut chars = src.char_indices().peekable();
+  while let Some(&(i, c)) = chars.peek() {
+    let loc = Loc { file, line };
+    match c {
+      '\n' => {
+        line += 1;
+        chars.next();
+      },
+      c if c.is_whitespace() => {
+        chars.next();
+      },
+      '#' =>
+        while chars.peek().map_or(false, |&(_, c)| c != '\n') {
+          chars.next();
+        },
+      '[' | ']' => {
+        chars.next();
+        out.push((if c == '[' { Token::Open } else { Token::Close }, loc));
+      },
+      '"' => {
+        chars.next();
+        let start = i + 1;
+        let end = loop {
+          match chars.next() {
+            None => return Err((String::from("Unterminated string"), line)),
+            Some((_, '\n')) => return Err((String::from("Unterminated string"), line)),
+            Some((j, '"')) => break j,
+            Some(_) => (),
+          }
+        };
+        out.push((Token::Str(src[start..end].to_string()), loc));
+      },
+      _ => {
+        let start = i;
+        while chars
+          .peek()
+          .map_or(false, |&(_, c)| !(c.is_whitespace() || "[]\"#".contains(c)))
+        {
+          chars.next();
+        }
+        let end = chars.peek().map_or(src.len(), |&(j, _)| j);
+        let word = &src[start..end];
+        let token = if c.is_alphabetic() {
+          Token::Ident(word.to_string())
+        } else {
+          let n = word
+            .parse()
+            .map_err(|_| (format!("Invalid number {:?}", word), line))?;
+          Token::Num(n)
+        };
+        out.push((token, loc));
+      },
+    }
+  }
+  Ok(out)
+}
+
+/// Value of a parameter, which is either a list of numbers or strings
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+  Nums(Vec<f32>),
+  Strs(Vec<String>),
+}
+
+/// A parameter such as `"float radius" [1]`
+#[derive(Debug, Clone, PartialEq)]
+struct Param {
+  kind: String,
+  name: String,
+  value: Value,
+}
+
+/// Arguments of a directive, which are positional values followed by named parameters
+#[derive(Debug, Default)]
+struct Args {
+  nums: Vec<f32>,
+  strs: Vec<String>,
+  params: Vec<Param>,
+}
+
+impl Args {
+  fn param(&self, name: &str) -> Option<&Param> { self.params.iter().find(|p| p.name == name) }
+  fn floats(&self, name: &str) -> Option<&[f32]> {
+    match self.param(name).map(|p| &p.value) {
+      Some(Value::Nums(v)) => Some(v),
+      _ => None,
+    }
+  }
+  fn float(&self, name: &str) -> Option<f32> { self.floats(name).and_then(|v| v.first().copied()) }
+  fn string(&self, name: &str) -> Option<&str> {
+    match self.param(name).map(|p| &p.value) {
+      Some(Value::Strs(v)) => v.first().map(String::as_str),
+      _ => None,
+    }
+  }
+  fn point(&self, name: &str) -> Option<Vec3> {
+    match self.floats(name)? {
+      &[x, y, z, ..] => Some(Vec3::new(x, y, z)),
+      _ => None,
+    }
+  }
+  /// Reads a color given as rgb, a single float, a blackbody temperature with a scale or a
+  /// spectrum file relative to `dir`. Sampled spectra are averaged.
+  fn spectrum(&self, name: &str, dir: &Path) -> Option<Spectrum> {
+    let p = self.param(name)?;
+    let v = match &p.value {
+      Value::Nums(v) => v,
+      Value::Strs(files) => {
+        let file = match (p.kind.as_str(), files.as_slice()) {
+          ("spectrum", [file]) => dir.join(file),
+          (kind, _) => {
+            eprintln!("Ignoring unsupported {} value for {}", kind, name);
+            return None;
+          },
+        };
+        let spd = SpectrumBuilder::Spd(file.to_string_lossy().into_owned());
+        return spd
+          .build()
+          .map_err(|e| eprintln!("Failed to read spectrum {}: {}", file.display(), e))
+          .ok();
+      },
+    };
+    Some(match (p.kind.as_str(), v.as_slice()) {
+      ("blackbody", &[temperature, scale]) => {
+        let blackbody = SpectrumBuilder::Blackbody {
+          temperature,
+          normalize: Some(true),
+        };
+        blackbody.build().ok()? * scale
+      },
+      ("blackbody", _) => {
+        eprintln!("Expected a temperature and scale for blackbody {}", name);
+        return None;
+      },
+      (_, &[s]) => from_mono(s),
+      ("rgb", &[r, g, b]) | ("color", &[r, g, b]) => from_rgb(Vec3::new(r, g, b)),
+      ("spectrum", v) if v.len() % 2 == 0 => {
+        let vals = v.iter().skip(1).step_by(2);
+        from_mono(vals.sum::<f32>() * 2. / v.len() as f32)
+      },
+      (kind, _) => {
+        eprintln!("Ignoring unsupported {} value for {}", kind, name);
+        return None;
+      },
+    })
+  }
+}
+
+#[derive(Debug, Clone)]
+struct GraphicsState {
+  /// Current transform as a product of transforms, so the last is applied first
+  ctm: Vec<TransformBuilder>,
+  material: String,
+}
+
+/// Converts a PBRT-v3 scene description into a raw scene
+struct Importer {
+  files: Vec<PathBuf>,
+  /// File which included each file, so that include cycles can be found
+  parents: Vec<Option<usize>>,
+  /// Token streams of the current file and the files which included it
+  streams: Vec<std::iter::Peekable<std::vec::IntoIter<(Token, Loc)>>>,
+  state: GraphicsState,
+  stack: Vec<GraphicsState>,
+  scene: RawScene,
+  camera_to_world: TransformBuilder,
+  camera: Option<Args>,
+  film: (u32, u32),
+  /// Used to name shapes and materials
+  count: usize,
+}
+
+impl Importer {
+  fn error(&self, loc: Loc, msg: String) -> SceneError {
+    SceneError::parse(self.files[loc.file].clone(), Some(loc.line), msg)
+  }
+  /// Includes a file from the file some token is in, unless that file is already being read
+  fn include(&mut self, p: PathBuf, loc: Loc) -> Result<(), SceneError> {
+    let key = |p: &Path| fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf());
+    let target = key(&p);
+    let mut includer = Some(loc.file);
+    while let Some(f) = includer {
+      if key(&self.files[f]) == target {
+        return Err(self.error(loc, format!("{} includes itself", p.display())));
+      }
+      includer = self.parents[f];
+    }
+    let src = fs::read_to_string(&p).map_err(|e| SceneError::io(&p, e))?;
+    self.read(src, p, Some(loc.file))
+  }
+  fn read(&mut self, src: String, p: PathBuf, parent: Option<usize>) -> Result<(), SceneError> {
+    let file = self.files.len();
+    self.files.push(p);
+    self.parents.push(parent);
+    let tokens = tokenize(&src, file).map_err(|(msg, line)| self.error(Loc { file, line }, msg))?;
+    self.streams.push(tokens.into_iter().peekable());
+    Ok(())
+  }
+  fn peek(&mut self) -> Option<&Token> {
+    while self.streams.last_mut()?.peek().is_none() {
+      self.streams.pop();
+    }
+    self.streams.last_mut()?.peek().map(|(t, _)| t)
+  }
+  fn next(&mut self) -> Option<(Token, Loc)> {
+    self.peek()?;
+    self.streams.last_mut()?.next()
+  }
+  /// Reads values up to the next directive
+  fn args(&mut self) -> Result<Args, SceneError> {
+    let mut args = Args::default();
+    loop {
+      if matches!(self.peek(), None | Some(Token::Ident(_))) {
+        return Ok(args);
+      }
+      let (t, at) = self.next().unwrap();
+      match t {
+        Token::Num(n) => args.nums.push(n),
+        Token::Str(s) => {
+          let decl: Vec<String> = s.split_whitespace().map(String::from).collect();
+          if let [kind, name] = decl.as_slice() {
+            let value = self.value(at)?;
+            args.params.push(Param {
+              kind: kind.clone(),
+              name: name.clone(),
+              value,
+            });
+          } else if args.params.is_empty() {
+            args.strs.push(s);
+          } else {
+            return Err(self.error(at, format!("Invalid parameter declaration {:?}", s)));
+          }
+        },
+        Token::Open => {
+          // Positional arguments may also be bracketed
+          while let Some((t, at)) = self.next() {
+            match t {
+              Token::Close => break,
+              Token::Num(n) => args.nums.push(n),
+              t => return Err(self.error(at, format!("Unexpected {:?} in list", t))),
+            }
+          }
+        },
+        t => return Err(self.error(at, format!("Unexpected {:?}", t))),
+      }
+    }
+  }
+  /// Reads the value of a parameter, which is a single value or a bracketed list
+  fn value(&mut self, loc: Loc) -> Result<Value, SceneError> {
+    let mut tokens = vec![];
+    match self.next() {
+      Some((Token::Open, _)) => loop {
+        match self.next() {
+          None => return Err(self.error(loc, String::from("Unterminated list"))),
+          Some((Token::Close, _)) => break,
+          Some(t) => tokens.push(t),
+        }
+      },
+      Some(t) => tokens.push(t),
+      None => return Err(self.error(loc, String::from("Missing parameter value"))),
+    }
+    let mut nums = vec![];
+    let mut strs = vec![];
+    for (t, at) in tokens {
+      match t {
+        Token::Num(n) => nums.push(n),
+        Token::Str(s) => strs.push(s),
+        t => return Err(self.error(at, format!("Unexpected {:?} in parameter", t))),
+      }
+    }
+    Ok(if strs.is_empty() {
+      Value::Nums(nums)
+    } else {
+      Value::Strs(strs)
+    })
+  }
+  fn nums<const N: usize>(&self, args: &Args, loc: Loc) -> Result<[f32; N], SceneError> {
+    let mut out = [0.; N];
+    if args.nums.len() != N {
+      return Err(self.error(loc, format!("Expected {} numbers", N)));
+    }
+    out.copy_from_slice(&args.nums);
+    Ok(out)
+  }
+  /// Directory of the file some token is in, which relative paths are resolved against
+  fn dir(&self, loc: Loc) -> &Path {
+    self.files[loc.file]
+      .parent()
+      .unwrap_or_else(|| Path::new(""))
+  }
+  fn to_world(&self) -> TransformBuilder { TransformBuilder::Compose(self.state.ctm.clone()) }
+  fn shape(&mut self, args: &Args, loc: Loc) -> Result<(), SceneError> {
+    let kind = args.strs.first().map(String::as_str).unwrap_or("");
+    let variant = match kind {
+      "trianglemesh" => {
+        let floats = |name: &str| -> Result<&[f32], SceneError> {
+          args
+            .floats(name)
+            .ok_or_else(|| self.error(loc, format!("trianglemesh requires {}", name)))
+        };
+        let p = floats("P")?;
+        if p.len() % 3 != 0 {
+          return Err(self.error(loc, String::from("trianglemesh P must be a list of points")));
+        }
+        let num_verts = p.len() / 3;
+        let indices = floats("indices")?;
+        if indices.len() % 3 != 0 {
+          return Err(self.error(loc, String::from("trianglemesh indices must be triangles")));
+        }
+        if let Some(&i) = indices
+          .iter()
+          .find(|&&i| i < 0. || i.fract() != 0. || i as usize >= num_verts)
+        {
+          return Err(self.error(loc, format!("Invalid trianglemesh index {}", i)));
+        }
+        let faces = indices
+          .chunks_exact(3)
+          .map(|f| Vec3::new(f[0] as u32, f[1] as u32, f[2] as u32))
+          .collect();
+        let vec3s = |v: &[f32]| -> Vec<Vec3> {
+          v.chunks_exact(3)
+            .map(|p| Vec3::new(p[0], p[1], p[2]))
+            .collect()
+        };
+        let normals = args.floats("N");
+        if normals.map_or(false, |n| n.len() != p.len()) {
+          return Err(self.error(loc, String::from("trianglemesh needs one N per point")));
+        }
+        let uvs = args.floats("uv").or_else(|| args.floats("st"));
+        if uvs.map_or(false, |uv| uv.len() != 2 * num_verts) {
+          return Err(self.error(loc, String::from("trianglemesh needs one uv per point")));
+        }
+        ShapeVariant::Mesh {
+          verts: vec3s(p),
+          normals: normals.map(vec3s),
+          uvs: uvs.map(|v| v.chunks_exact(2).map(|p| Vec2::new(p[0], p[1])).collect()),
+          faces,
+        }
+      },
+      "plymesh" => {
+        let file = args
+          .string("filename")
+          .ok_or_else(|| self.error(loc, String::from("plymesh requires a filename")))?;
+        ShapeVariant::Ply {
+          file: self.dir(loc).join(file).to_string_lossy().into_owned(),
+        }
+      },
+      "sphere" => ShapeVariant::Sphere {
+        center: Vec3::of(0.),
+        radius: args.float("radius").unwrap_or(1.),
+      },
+      "disk" => ShapeVariant::Disk {
+        radius: args.float("radius").unwrap_or(1.),
+        inner_radius: args.float("innerradius"),
+        height: args.float("height"),
+        phi_max: args.float("phimax"),
+      },
+      "cylinder" => ShapeVariant::Cylinder {
+        radius: args.float("radius").unwrap_or(1.),
+        z_min: args.float("zmin").unwrap_or(-1.),
+        z_max: args.float("zmax").unwrap_or(1.),
+        phi_max: args.float("phimax"),
+      },
+      other => {
+        eprintln!(
+          "Skipping unsupported shape {:?} on line {}",
+          other, loc.line
+        );
+        return Ok(());
+      },
+    };
+    let id = format!("shape{}", self.count);
+    self.count += 1;
+    self.scene.shapes.insert(id.clone(), ShapeBuilder {
+      to_world: self.to_world(),
+      variant,
+    });
+    self
+      .scene
+      .bsdf_mapping
+      .insert(id, self.state.material.clone());
+    Ok(())
+  }
+  fn light(&mut self, args: &Args, loc: Loc) {
+    let to_world = Transform4::from(self.to_world());
+    let scale = args.float("scale").unwrap_or(1.);
+    let light = match args.strs.first().map(String::as_str).unwrap_or("") {
+      "point" => {
+        let from = to_world.apply_point(&args.point("from").unwrap_or_else(|| Vec3::of(0.)));
+        let intensity = args
+          .spectrum("I", self.dir(loc))
+          .unwrap_or_else(|| from_mono(1.));
+        Lights::Point(Point::new(from, scale, intensity))
+      },
+      "distant" => {
+        let from = args.point("from").unwrap_or_else(|| Vec3::of(0.));
+        let to = args.point("to").unwrap_or_else(|| Vec3::new(0., 0., 1.));
+        let dir = to_world.apply_vec(&(to - from)).norm();
+        let radiance = args
+          .spectrum("L", self.dir(loc))
+          .unwrap_or_else(|| from_mono(1.));
+        Lights::Dir(Dir::new(dir * DIR_LIGHT_DIST, scale, radiance))
+      },
+      other => {
+        eprintln!(
+          "Skipping unsupported light {:?} on line {}",
+          other, loc.line
+        );
+        return;
+      },
+    };
+    self.scene.lights.push(light);
+  }
+  /// Builds the camera once the film's size is known
+  fn build_camera(&mut self) {
+    let args = match self.camera.take() {
+      None => return,
+      Some(args) => args,
+    };
+    let (width, height) = self.film;
+    let aspect = width as f32 / height as f32;
+    // PBRT's field of view is along the shorter axis
+    let fov = args.float("fov").unwrap_or(90.);
+    let x_fov = if aspect > 1. {
+      2. * ((fov.to_radians() / 2.).tan() * aspect).atan().to_degrees()
+    } else {
+      fov
+    };
+    let near_clip = args.float("hither").unwrap_or(1e-3);
+    let far_clip = args.float("yon").unwrap_or(1e4);
+    let variant = match args.strs.first().map(String::as_str).unwrap_or("") {
+      "orthographic" => CameraVariant::Orthographic {
+        near_clip,
+        far_clip,
+        aspect,
+      },
+      other => {
+        if other != "perspective" {
+          eprintln!("Unsupported camera {:?}, using perspective instead", other);
+        }
+        CameraVariant::Perspective {
+          x_fov,
+          near_clip,
+          far_clip,
+          aspect,
+        }
+      },
+    };
+    self.scene.camera = CameraBuilder {
+      film_builder: FilmBuilder {
+        size: (width, height),
+      },
+      to_world: self.camera_to_world.clone(),
+      variant,
+      sampler: Some(SamplerBuilder::default()),
+      polarizer: None,
+    };
+  }
+  fn directive(&mut self, name: &str, loc: Loc) -> Result<(), SceneError> {
+    let args = self.args()?;
+    match name {
+      "Identity" => self.state.ctm.clear(),
+      "Translate" => {
+        let [x, y, z] = self.nums(&args, loc)?;
+        self
+          .state
+          .ctm
+          .push(TransformBuilder::Translate(Vec3::new(x, y, z)));
+      },
+      "Scale" => {
+        let [x, y, z] = self.nums(&args, loc)?;
+        self
+          .state
+          .ctm
+          .push(TransformBuilder::Scale(Vec3::new(x, y, z)));
+      },
+      "Rotate" => {
+        let [deg, x, y, z] = self.nums(&args, loc)?;
+        let tf = TransformBuilder::Rotate(Vec3::new(x, y, z), deg.to_radians());
+        self.state.ctm.push(tf);
+      },
+      "LookAt" => {
+        let [ex, ey, ez, lx, ly, lz, ux, uy, uz] = self.nums(&args, loc)?;
+        // PBRT's look at transforms from world to camera space
+        let look_at = TransformBuilder::LookAt {
+          origin: Vec3::new(ex, ey, ez),
+          towards: Vec3::new(lx, ly, lz),
+          up: Vec3::new(ux, uy, uz),
+        };
+        self
+          .state
+          .ctm
+          .push(TransformBuilder::Inverse(Box::new(look_at)));
+      },
+      "Transform" | "ConcatTransform" => {
+        // PBRT matrices are given column major
+        let m: [f32; 16] = self.nums(&args, loc)?;
+        if name == "Transform" {
+          self.state.ctm.clear();
+        }
+        self.state.ctm.push(TransformBuilder::Matrix(m));
+      },
+      "Camera" => {
+        self.camera_to_world = TransformBuilder::Inverse(Box::new(self.to_world()));
+        self.camera = Some(args);
+      },
+      "Film" => {
+        let res = |name: &str, default: u32| args.float(name).map_or(default, |v| v as u32);
+        self.film = (res("xresolution", 640), res("yresolution", 480));
+      },
+      // Only uniform sampling is supported, which is used for any sampler
+      "Sampler" | "Integrator" | "PixelFilter" | "Accelerator" => (),
+      "WorldBegin" => {
+        self.build_camera();
+        self.state.ctm.clear();
+      },
+      "WorldEnd" => (),
+      "AttributeBegin" | "TransformBegin" => self.stack.push(self.state.clone()),
+      "AttributeEnd" | "TransformEnd" => {
+        let prev = self
+          .stack
+          .pop()
+          .ok_or_else(|| self.error(loc, format!("Unmatched {}", name)))?;
+        if name == "AttributeEnd" {
+          self.state = prev;
+        } else {
+          self.state.ctm = prev.ctm;
+        }
+      },
+      "Material" => {
+        let kind = args.strs.first().cloned().unwrap_or_default();
+        let bsdf = material(&kind, &args, self.dir(loc));
+        let id = format!("material{}", self.count);
+        self.count += 1;
+        self.scene.bsdfs.insert(id.clone(), bsdf);
+        self.state.material = id;
+      },
+      "MakeNamedMaterial" => {
+        let id = args
+          .strs
+          .first()
+          .cloned()
+          .ok_or_else(|| self.error(loc, String::from("Missing material name")))?;
+        let kind = args.string("type").unwrap_or("matte").to_string();
+        let bsdf = material(&kind, &args, self.dir(loc));
+        self.scene.bsdfs.insert(id, bsdf);
+      },
+      "NamedMaterial" => {
+        let id = args
+          .strs
+          .first()
+          .cloned()
+          .ok_or_else(|| self.error(loc, String::from("Missing material name")))?;
+        if !self.scene.bsdfs.contains_key(&id) {
+          let referrer = format!("{}:{}", self.files[loc.file].display(), loc.line);
+          return Err(SceneError::dangling("material", id, referrer));
+        }
+        self.state.material = id;
+      },
+      "Shape" => self.shape(&args, loc)?,
+      "LightSource" => self.light(&args, loc),
+      "Include" => {
+        let file = args
+          .strs
+          .first()
+          .ok_or_else(|| self.error(loc, String::from("Missing file to include")))?;
+        let p = self.dir(loc).join(file);
+        self.include(p, loc)?;
+      },
+      other => eprintln!(
+        "Skipping unsupported directive {} on line {} of {}",
+        other,
+        loc.line,
+        self.files[loc.file].display()
+      ),
+    }
+    Ok(())
+  }
+  fn run(&mut self) -> Result<(), SceneError> {
+    while let Some((t, loc)) = self.next() {
+      match t {
+        Token::Ident(name) => self.directive(&name, loc)?,
+        t => return Err(self.error(loc, format!("Expected a directive but got {:?}", t))),
+      }
+    }
+    // Scenes without WorldBegin still get their camera
+    self.build_camera();
+    Ok(())
+  }
+}
+
+/// Converts a PBRT material into the closest bsdf, with spectrum files relative to `dir`
+fn material(kind: &str, args: &Args, dir: &Path) -> BSDFBuilder {
+  let reflectance = |name: &str, default: f32| {
+    args
+      .spectrum(name, dir)
+      .unwrap_or_else(|| from_mono(default))
+  };
+  let roughness = args.float("roughness").unwrap_or(0.1);
+  match kind {
+    "matte" => BSDFBuilder::Diffuse(reflectance("Kd", 0.5)),
+    // a diffuse lobe and a highlight
+    "plastic" => BSDFBuilder::InlineMTL {
+      illum: 2,
+      diffuse: reflectance("Kd", 0.25),
+      specular: reflectance("Ks", 0.25),
+      shininess: exponent(roughness),
+    },
+    "metal" => BSDFBuilder::Conductor {
+      eta: args.float("eta").unwrap_or(0.2),
+      k: args.float("k").unwrap_or(3.9),
+      exponent: exponent(roughness),
+    },
+    // a mirror reflection, with a sharp highlight so lights can be seen in it
+    "mirror" => BSDFBuilder::InlineMTL {
+      illum: 3,
+      diffuse: from_mono(0.),
+      specular: reflectance("Kr", 0.9),
+      shininess: exponent(0.),
+    },
+    "glass" => BSDFBuilder::Dielectric {
+      eta: args
+        .float("index")
+        .or_else(|| args.float("eta"))
+        .unwrap_or(1.5),
+      exponent: exponent(args.float("uroughness").unwrap_or(0.)),
+    },
+    other => {
+      eprintln!("Unsupported material {}, using matte instead", other);
+      BSDFBuilder::Diffuse(reflectance("Kd", 0.5))
+    },
+  }
+}
+
+/// Converts a PBRT roughness into a lobe exponent
+fn exponent(roughness: f32) -> f32 {
+  let alpha = roughness.max(1e-3);
+  2. / (alpha * alpha) - 2.
+}
+
+/// Imports a PBRT-v3 scene. Supported directives are transforms, `Camera` (perspective or
+/// orthographic), `Film`, `WorldBegin`, `AttributeBegin/End`, `TransformBegin/End`, `Shape`
+/// (trianglemesh, plymesh, sphere, disk and cylinder), `Material` and named materials (matte,
+/// plastic, metal, mirror and glass), `LightSource` (point and distant) and `Include`.
+///
+/// Samplers, integrators and filters are ignored, and anything else is skipped with a warning.
+pub fn from_pbrt(p: impl AsRef<Path>) -> Result<RawScene, SceneError> {
+  let p = p.as_ref();
+  let src = fs::read_to_string(p).map_err(|e| SceneError::io(p, e))?;
+  parse(src, p.to_path_buf())
+}
+
+fn parse(src: String, p: PathBuf) -> Result<RawScene, SceneError> {
+  let mut scene = RawScene::example();
+  scene.shapes.clear();
+  scene.lights.clear();
+  scene.bsdfs.clear();
+  scene.bsdf_mapping.clear();
+  scene.bsdfs.insert(
+    String::from(DEFAULT_BSDF),
+    BSDFBuilder::Diffuse(from_mono(0.5)),
+  );
+  let mut importer = Importer {
+    files: vec![],
+    parents: vec![],
+    streams: vec![],
+    state: GraphicsState {
+      ctm: vec![],
+      material: String::from(DEFAULT_BSDF),
+    },
+    stack: vec![],
+    scene,
+    camera_to_world: TransformBuilder::Identity,
+    camera: None,
+    film: (640, 480),
+    count: 0,
+  };
+  importer.read(src, p, None)?;
+  importer.run()?;
+  Ok(importer.scene)
+}
+
+#[cfg(test)]
+mod test_pbrt {
+  use super::*;
+  #[test]
+  fn tokens() {
+    let tokens = tokenize("Shape \"sphere\" # comment\n \"float radius\" [ 2.5 ]", 0).unwrap();
+    let tokens: Vec<_> = tokens.into_iter().map(|(t, _)| t).collect();
+    assert_eq!(tokens, vec![
+      Token::Ident(String::from("Shape")),
+      Token::Str(String::from("sphere")),
+      Token::Str(String::from("float radius")),
+      Token::Open,
+      Token::Num(2.5),
+      Token::Close,
+    ]);
+  }
+  #[test]
+  fn include_and_attributes() {
+    let dir = std::env::temp_dir();
+    let included = dir.join("mireba_test_include.pbrt");
+    fs::write(
+      &included,
+      "Shape \"trianglemesh\" \"integer indices\" [0 1 2]\n\
+       \"point P\" [0 0 0 1 0 0 0 1 0]\n",
+    )
+    .unwrap();
+    let src = String::from(
+      r#"
+      LookAt 0 0 -5  0 0 0  0 1 0
+      Camera "perspective" "float fov" 45
+      Film "image" "integer xresolution" [64] "integer yresolution" [32]
+      Sampler "halton" "integer pixelsamples" 16
+      WorldBegin
+      LightSource "point" "rgb I" [10 10 10] "point from" [0 2 0]
+      AttributeBegin
+        Material "matte" "rgb Kd" [0.8 0.1 0.1]
+        Translate 1 0 0
+        Shape "sphere" "float radius" 0.5
+      AttributeEnd
+      Include "mireba_test_include.pbrt"
+      Texture "checks" "spectrum" "checkerboard"
+      WorldEnd
+    "#,
+    );
+    let scene = parse(src, dir.join("main.pbrt")).expect("Failed to import scene");
+    fs::remove_file(included).unwrap();
+    assert_eq!(scene.shapes.len(), 2);
+    assert_eq!(scene.lights.len(), 1);
+    assert_eq!(scene.camera.film_builder.size, (64, 32));
+    assert_ne!(scene.bsdf_mapping["shape1"], DEFAULT_BSDF);
+    // The material is restored after the attribute block
+    assert_eq!(scene.bsdf_mapping["shape2"], DEFAULT_BSDF);
+    match &scene.shapes["shape2"].variant {
+      ShapeVariant::Mesh { verts, faces, .. } => {
+        assert_eq!(verts.len(), 3);
+        assert_eq!(faces.len(), 1);
+      },
+      v => panic!("Unexpected shape {:?}", v),
+    }
+  }
+  #[test]
+  fn include_cycle() {
+    let dir = std::env::temp_dir();
+    let a = dir.join("mireba_test_cycle_a.pbrt");
+    let b = dir.join("mireba_test_cycle_b.pbrt");
+    fs::write(&a, "Include \"mireba_test_cycle_b.pbrt\"\n").unwrap();
+    fs::write(&b, "Include \"mireba_test_cycle_a.pbrt\"\n").unwrap();
+    let res = from_pbrt(&a);
+    fs::remove_file(a).unwrap();
+    fs::remove_file(b).unwrap();
+    assert!(res.is_err());
+  }
+  #[test]
+  fn invalid_trianglemesh() {
+    let mesh = |params: &str| {
+      let src = format!(
+        "WorldBegin\nShape \"trianglemesh\" \"point P\" [0 0 0 1 0 0 0 1 0] {}\n",
+        params
+      );
+      parse(src, PathBuf::from("mesh.pbrt"))
+    };
+    assert!(mesh("\"integer indices\" [0 1 2]").is_ok());
+    assert!(mesh("\"integer indices\" [0 1 3]").is_err());
+    assert!(mesh("\"integer indices\" [0 1 -1]").is_err());
+    assert!(mesh("\"integer indices\" [0 1]").is_err());
+    assert!(mesh("\"integer indices\" [0 1 2] \"normal N\" [0 0 1]").is_err());
+    assert!(mesh("\"integer indices\" [0 1 2] \"float uv\" [0 0 1 0]").is_err());
+  }
+  #[test]
+  fn materials() {
+    use crate::{
+      interaction::{Interaction, SurfaceInteraction},
+      spectrum::Spectral,
+    };
+    let bsdf = |kind: &str, params: &str| {
+      let src = format!(
+        "WorldBegin\nMaterial \"{}\" {}\nShape \"sphere\"\nWorldEnd\n",
+        kind, params
+      );
+      let mut scene = parse(src, PathBuf::from("material.pbrt")).unwrap();
+      let id = scene.bsdf_mapping["shape1"].clone();
+      scene.bsdfs.remove(&id).unwrap().build().unwrap()
+    };
+    let si = SurfaceInteraction {
+      it: Interaction::new(),
+      normal: Vec3::new(0., 0., 1.),
+      uv: Vec2::of(0.),
+      wi: Vec3::new(0., 0., -1.),
+      material: 0,
+    };
+    // mirrors reflect through a specular lobe rather than a glossy one
+    let mirror = bsdf("mirror", "\"rgb Kr\" [0.5 0.5 0.5]");
+    let lobes = mirror.specular(&si);
+    assert_eq!(lobes.len(), 1);
+    let half = from_rgb(Vec3::of(0.5)).luminance();
+    assert!((lobes[0].weight.luminance() - half).abs() < 1e-5);
+    // plastic highlights are tinted by Ks
+    let up = Vec3::new(0., 0., 1.);
+    let dull = bsdf("plastic", "\"rgb Ks\" [0 0 0]").eval(&si, up);
+    let shiny = bsdf("plastic", "\"rgb Ks\" [1 1 1]").eval(&si, up);
+    assert!(shiny.luminance() > dull.luminance());
+  }
+  #[test]
+  fn blackbody() {
+    let src = String::from("LightSource \"point\" \"blackbody I\" [6500 2]\n");
+    let scene = parse(src, PathBuf::from("light.pbrt")).unwrap();
+    assert_eq!(scene.lights.len(), 1);
+    assert!(scene.lights[0].strength() > 0.);
+  }
+  #[test]
+  fn spectrum_file() {
+    use crate::spectrum::Spectral;
+    let dir = std::env::temp_dir();
+    let spd = dir.join("mireba_test_light.spd");
+    fs::write(&spd, "400 2\n700 2\n").unwrap();
+    let light = |file: &str| {
+      let src = format!("LightSource \"point\" \"spectrum I\" \"{}\"\n", file);
+      let scene = parse(src, dir.join("light.pbrt")).unwrap();
+      scene.lights[0].strength()
+    };
+    let read = light("mireba_test_light.spd");
+    let missing = light("mireba_test_missing.spd");
+    fs::remove_file(spd).unwrap();
+    // a missing file falls back to the default intensity of 1
+    assert!((missing - from_mono(1.).luminance()).abs() < 1e-5);
+    assert!(read > missing);
+  }
+}
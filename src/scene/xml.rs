@@ -0,0 +1,199 @@
+use super::SceneError;
+use std::path::PathBuf;
+
+/// An XML element with its attributes and child elements. Text content is dropped since scene
+/// formats keep everything in attributes.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Element {
+  pub name: String,
+  pub attrs: Vec<(String, String)>,
+  pub children: Vec<Element>,
+  /// Line this element starts on, for error messages
+  pub line: usize,
+}
+
+impl Element {
+  pub fn attr(&self, name: &str) -> Option<&str> {
+    self
+      .attrs
+      .iter()
+      .find(|(k, _)| k == name)
+      .map(|(_, v)| v.as_str())
+  }
+}
+
+/// Minimal XML parser which handles elements, attributes, comments, declarations and the
+/// predefined entities, which is all scene files use.
+struct Parser<'a> {
+  src: &'a str,
+  pos: usize,
+}
+
+impl<'a> Parser<'a> {
+  fn line(&self) -> usize { self.src[..self.pos].matches('\n').count() + 1 }
+  fn error(&self, msg: &str) -> SceneError {
+    SceneError::parse(PathBuf::new(), Some(self.line()), msg)
+  }
+  fn rest(&self) -> &'a str { &self.src[self.pos..] }
+  fn skip_whitespace(&mut self) {
+    let rest = self.rest();
+    self.pos += rest.len() - rest.trim_start().len();
+  }
+  fn expect(&mut self, s: &str) -> Result<(), SceneError> {
+    if !self.rest().starts_with(s) {
+      return Err(self.error(&format!("Expected {:?}", s)));
+    }
+    self.pos += s.len();
+    Ok(())
+  }
+  /// Skips past the next occurrence of `end`
+  fn skip_past(&mut self, end: &str) -> Result<(), SceneError> {
+    match self.rest().find(end) {
+      None => Err(self.error(&format!("Missing {:?}", end))),
+      Some(i) => {
+        self.pos += i + end.len();
+        Ok(())
+      },
+    }
+  }
+  /// Skips text, comments, declarations and doctypes until the next element tag
+  fn skip_misc(&mut self) -> Result<(), SceneError> {
+    loop {
+      match self.rest().find('<') {
+        None => {
+          self.pos = self.src.len();
+          return Ok(());
+        },
+        Some(i) => self.pos += i,
+      }
+      let rest = self.rest();
+      if rest.starts_with("<!--") {
+        self.skip_past("-->")?;
+      } else if rest.starts_with("<?") {
+        self.skip_past("?>")?;
+      } else if rest.starts_with("<!") {
+        self.skip_past(">")?;
+      } else {
+        return Ok(());
+      }
+    }
+  }
+  fn name(&mut self) -> Result<String, SceneError> {
+    let len = self
+      .rest()
+      .find(|c: char| c.is_whitespace() || c == '=' || c == '>' || c == '/')
+      .unwrap_or_else(|| self.rest().len());
+    if len == 0 {
+      return Err(self.error("Expected a name"));
+    }
+    let name = self.rest()[..len].to_string();
+    self.pos += len;
+    Ok(name)
+  }
+  fn element(&mut self) -> Result<Element, SceneError> {
+    let line = self.line();
+    self.expect("<")?;
+    let name = self.name()?;
+    let mut attrs = vec![];
+    loop {
+      self.skip_whitespace();
+      if self.rest().starts_with("/>") {
+        self.pos += 2;
+        return Ok(Element {
+          name,
+          attrs,
+          children: vec![],
+          line,
+        });
+      }
+      if self.rest().starts_with('>') {
+        self.pos += 1;
+        break;
+      }
+      let key = self.name()?;
+      self.skip_whitespace();
+      self.expect("=")?;
+      self.skip_whitespace();
+      let quote = match self.rest().chars().next() {
+        Some(q @ '"') | Some(q @ '\'') => q,
+        _ => return Err(self.error("Expected a quoted attribute value")),
+      };
+      self.pos += 1;
+      let end = self
+        .rest()
+        .find(quote)
+        .ok_or_else(|| self.error("Unterminated attribute value"))?;
+      let value = unescape(&self.rest()[..end]);
+      self.pos += end + 1;
+      attrs.push((key, value));
+    }
+    let mut children = vec![];
+    loop {
+      self.skip_misc()?;
+      if self.rest().is_empty() {
+        return Err(self.error(&format!("Unclosed element {}", name)));
+      }
+      if self.rest().starts_with("</") {
+        self.pos += 2;
+        let close = self.name()?;
+        if close != name {
+          return Err(self.error(&format!("Expected </{}> but found </{}>", name, close)));
+        }
+        self.skip_whitespace();
+        self.expect(">")?;
+        return Ok(Element {
+          name,
+          attrs,
+          children,
+          line,
+        });
+      }
+      children.push(self.element()?);
+    }
+  }
+}
+
+fn unescape(s: &str) -> String {
+  s.replace("&lt;", "<")
+    .replace("&gt;", ">")
+    .replace("&quot;", "\"")
+    .replace("&apos;", "'")
+    .replace("&amp;", "&")
+}
+
+/// Parses the root element of an XML document. Errors have the line but not the file, which
+/// the caller adds.
+pub fn parse(src: &str) -> Result<Element, SceneError> {
+  let mut p = Parser { src, pos: 0 };
+  p.skip_misc()?;
+  if p.rest().is_empty() {
+    return Err(p.error("Missing root element"));
+  }
+  p.element()
+}
+
+#[cfg(test)]
+mod test_xml {
+  use super::*;
+  #[test]
+  fn nested_elements() {
+    let root = parse(
+      r#"<?xml version="1.0"?>
+      <!-- a comment -->
+      <scene version='2.0.0'>
+        <shape type="obj"><string name="filename" value="a &amp; b.obj"/></shape>
+        <emitter type="point"/>
+      </scene>"#,
+    )
+    .unwrap();
+    assert_eq!(root.name, "scene");
+    assert_eq!(root.attr("version"), Some("2.0.0"));
+    assert_eq!(root.children.len(), 2);
+    assert_eq!(
+      root.children[0].children[0].attr("value"),
+      Some("a & b.obj")
+    );
+    assert_eq!(root.children[1].line, 5);
+    assert!(parse("<scene><shape></scene>").is_err());
+  }
+}
@@ -1,8 +1,15 @@
 use super::{
-  triangle_list::{from_ascii_obj, from_ascii_stl},
+  csg, cuboid, curve, heightfield, patch, ply, positive, quadrics, sdf,
+  triangle_list::{from_ascii_obj, from_ascii_stl, from_binary_stl, from_stl, IndexedTriangles},
   Geometry,
 };
-use quick_maths::Vec3;
+use crate::{
+  camera::Cameras,
+  mesh::{displace, subdivide, DCEL},
+  scene::SceneError,
+  texture::bitmap::Bitmap,
+};
+use quick_maths::{Transform4, Vec2, Vec3};
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct Builder {
@@ -24,24 +31,181 @@ pub enum Variant {
     height: f32,
   },
   Triangle(Vec3<Vec3>),
+  /// Meshes can be smoothed with some levels of subdivision, where edges whose faces meet at
+  /// more than crease_angle degrees are kept sharp, and then displaced by a texture.
   Obj {
     file: String,
     use_mtls: Option<bool>,
-    binary: Option<bool>,
+    subdivision_levels: Option<u32>,
+    subdivision: Option<subdivide::Scheme>,
+    crease_angle: Option<f32>,
+    displacement: Option<displace::Builder>,
+  },
+  /// Triangle mesh given inline, with optional per vertex normals and texture coordinates
+  Mesh {
+    verts: Vec<Vec3>,
+    normals: Option<Vec<Vec3>>,
+    uvs: Option<Vec<Vec2>>,
+    faces: Vec<Vec3<u32>>,
   },
+  /// ASCII or binary PLY, with optional vertex normals, texture coordinates and colors. Colors
+  /// are loaded but not yet used when shading.
+  Ply {
+    file: String,
+  },
+  /// One primitive of a mesh in a glTF file
+  Gltf {
+    file: String,
+    mesh: usize,
+    primitive: usize,
+  },
+  /// STLs are detected as ASCII or binary unless binary is set.
   Stl {
     file: String,
     binary: Option<bool>,
   },
+  /// Quadrics are oriented along the z-axis and swept up to phi_max degrees around it, which
+  /// defaults to a full revolution.
+  Cylinder {
+    radius: f32,
+    z_min: f32,
+    z_max: f32,
+    phi_max: Option<f32>,
+  },
+  /// Disk facing +z, which is an annulus if it has a nonzero inner radius
+  Disk {
+    radius: f32,
+    inner_radius: Option<f32>,
+    height: Option<f32>,
+    phi_max: Option<f32>,
+  },
+  Cone {
+    radius: f32,
+    height: f32,
+    phi_max: Option<f32>,
+  },
+  Paraboloid {
+    radius: f32,
+    z_min: Option<f32>,
+    z_max: f32,
+    phi_max: Option<f32>,
+  },
+  Hyperboloid {
+    radius: f32,
+    slope: f32,
+    z_min: f32,
+    z_max: f32,
+    phi_max: Option<f32>,
+  },
+  /// Box spanning two opposite corners in object space
+  Box {
+    min: Vec3,
+    max: Vec3,
+  },
+  /// Box with edges and corners rounded off by some radius
+  RoundedBox {
+    min: Vec3,
+    max: Vec3,
+    radius: f32,
+  },
+  /// Signed distance function, which must be given bounds as [min, max] if it is unbounded
+  Sdf {
+    sdf: sdf::Sdf,
+    bounds: Option<[Vec3; 2]>,
+  },
+  /// Boolean combination of two closed shapes, each with their own transform
+  Csg {
+    op: csg::Op,
+    left: std::boxed::Box<Builder>,
+    right: std::boxed::Box<Builder>,
+  },
+  /// Terrain from a grayscale image spanning [0,1] on x and z, where white is at a height of
+  /// `height` which defaults to 1.
+  Heightfield {
+    file: String,
+    height: Option<f32>,
+  },
+  /// Strands of hair or fur from a curve file
+  Curves {
+    file: String,
+    kind: Option<curve::CurveKind>,
+  },
+  /// Bicubic bezier patches in the .bpt format, which are intersected directly or converted
+  /// into a grid of `tessellate` x `tessellate` quads per patch.
+  Bpt {
+    file: String,
+    tessellate: Option<u32>,
+  },
 }
 
-impl From<Builder> for Geometry {
-  fn from(b: Builder) -> Self {
-    let Builder { to_world, variant } = b;
+/// Converts an optional sweep in degrees to radians
+fn phi_max(deg: Option<f32>) -> f32 { deg.unwrap_or(360.).to_radians() }
+
+/// Tessellates and displaces a mesh. Edges are measured on the camera's film if there is one.
+fn displaced(
+  mesh: IndexedTriangles,
+  src: String,
+  d: displace::Builder,
+  to_world: &Transform4,
+  camera: Option<&Cameras>,
+) -> Result<IndexedTriangles, SceneError> {
+  let displace::Builder {
+    texture,
+    scale,
+    tessellation,
+  } = d;
+  let texture = Bitmap::from_file(&texture).map_err(|e| SceneError::image(texture, e))?;
+  let (verts, tris) = mesh.unified();
+  let edge_len = |a: Vec3, b: Vec3| match camera {
+    Some(c) => c.projected_length(to_world.apply_point(&a), to_world.apply_point(&b)),
+    None => (a - b).magn(),
+  };
+  let (mut verts, tris) = displace::tessellate(verts, tris, tessellation, edge_len);
+  displace::displace(&mut verts, &tris, &texture, scale.unwrap_or(1.));
+  let (pos, normals, uvs) = verts.into_iter().fold(
+    (vec![], vec![], vec![]),
+    |(mut pos, mut normals, mut uvs), v| {
+      pos.push(v.pos);
+      normals.push(v.normal);
+      uvs.push(v.uv);
+      (pos, normals, uvs)
+    },
+  );
+  Ok(IndexedTriangles::new(src, pos, normals, tris).with_uvs(uvs))
+}
+
+impl Builder {
+  /// Files this shape is loaded from, including textures
+  pub fn files(&self) -> Vec<&str> {
+    use Variant::*;
+    match &self.variant {
+      Obj {
+        file, displacement, ..
+      } => std::iter::once(file)
+        .chain(displacement.iter().map(|d| &d.texture))
+        .map(String::as_str)
+        .collect(),
+      Ply { file }
+      | Gltf { file, .. }
+      | Stl { file, .. }
+      | Heightfield { file, .. }
+      | Curves { file, .. }
+      | Bpt { file, .. } => vec![file.as_str()],
+      Csg { left, right, .. } => left.files().into_iter().chain(right.files()).collect(),
+      _ => vec![],
+    }
+  }
+  /// Builds this shape, using the camera to decide how finely to tessellate if given
+  pub fn build(self, camera: Option<&Cameras>) -> Result<Geometry, SceneError> {
+    let Builder { to_world, variant } = self;
+    let to_world: Transform4 = to_world.into();
     use super::Variant as GeoVariant;
     use Variant::*;
     let variant = match variant {
-      Sphere { center, radius } => GeoVariant::Sphere(super::sphere::Sphere::new(center, radius)),
+      Sphere { center, radius } => {
+        positive("Sphere", "radius", radius)?;
+        GeoVariant::Sphere(super::sphere::Sphere::new(center, radius))
+      },
       Plane {
         normal,
         w,
@@ -53,30 +217,140 @@ impl From<Builder> for Geometry {
       Obj {
         file,
         use_mtls,
-        binary,
+        subdivision_levels,
+        subdivision,
+        crease_angle,
+        displacement,
       } => {
         let use_mtls = use_mtls.unwrap_or(false);
-        let binary = binary.unwrap_or(false);
-        let triangle_list = if binary {
-          todo!("Cannot handle binary objs yet");
+        let levels = subdivision_levels.unwrap_or(0);
+        let triangle_list = if levels > 0 {
+          let (mesh, materials) = DCEL::from_obj(&file).map_err(|e| SceneError::io(&file, e))?;
+          let scheme = subdivision.unwrap_or_default();
+          let mesh = subdivide::subdivide_n(mesh, levels, scheme, crease_angle)
+            .map_err(|e| SceneError::io(&file, e))?;
+          let (verts, faces, groups) = subdivide::to_triangles(&mesh);
+          // creases are kept sharp when shading as well
+          let (verts, normals, faces) = displace::crease_normals(&verts, &faces, crease_angle);
+          let mesh = IndexedTriangles::new(file.clone(), verts, normals, faces);
+          if use_mtls {
+            mesh.with_obj_materials(&materials, &groups)?
+          } else {
+            mesh
+          }
         } else {
-          from_ascii_obj(file, use_mtls).expect("Failed to read input OBJ file")
+          from_ascii_obj(&file, use_mtls)?
         };
-        GeoVariant::TriangleList(triangle_list)
+        GeoVariant::TriangleList(match displacement {
+          None => triangle_list,
+          Some(d) => displaced(triangle_list, file, d, &to_world, camera)?,
+        })
+      },
+      Mesh {
+        verts,
+        normals,
+        uvs,
+        faces,
+      } => {
+        let mesh = IndexedTriangles::new(
+          String::from("inline"),
+          verts,
+          normals.unwrap_or_default(),
+          faces,
+        );
+        GeoVariant::TriangleList(match uvs {
+          None => mesh,
+          Some(uvs) => mesh.with_uvs(uvs),
+        })
       },
+      Gltf {
+        file,
+        mesh,
+        primitive,
+      } => GeoVariant::TriangleList(crate::scene::gltf::load_primitive(file, mesh, primitive)?),
+      Ply { file } => GeoVariant::TriangleList(ply::from_ply(file)?),
       Stl { file, binary } => {
-        let binary = binary.unwrap_or(false);
-        let triangle_list = if binary {
-          todo!("Cannot handle binary stls yet");
-        } else {
-          from_ascii_stl(file).expect("Failed to read input STL file")
+        let triangle_list = match binary {
+          None => from_stl(file),
+          Some(true) => from_binary_stl(file),
+          Some(false) => from_ascii_stl(file),
         };
-        GeoVariant::TriangleList(triangle_list)
+        GeoVariant::TriangleList(triangle_list?)
+      },
+      Cylinder {
+        radius,
+        z_min,
+        z_max,
+        phi_max: deg,
+      } => GeoVariant::Cylinder(quadrics::Cylinder::new(radius, z_min, z_max, phi_max(deg))?),
+      Disk {
+        radius,
+        inner_radius,
+        height,
+        phi_max: deg,
+      } => GeoVariant::Disk(quadrics::Disk::new(
+        height.unwrap_or(0.),
+        radius,
+        inner_radius.unwrap_or(0.),
+        phi_max(deg),
+      )?),
+      Cone {
+        radius,
+        height,
+        phi_max: deg,
+      } => GeoVariant::Cone(quadrics::Cone::new(radius, height, phi_max(deg))?),
+      Paraboloid {
+        radius,
+        z_min,
+        z_max,
+        phi_max: deg,
+      } => GeoVariant::Paraboloid(quadrics::Paraboloid::new(
+        radius,
+        z_min.unwrap_or(0.),
+        z_max,
+        phi_max(deg),
+      )?),
+      Hyperboloid {
+        radius,
+        slope,
+        z_min,
+        z_max,
+        phi_max: deg,
+      } => GeoVariant::Hyperboloid(quadrics::Hyperboloid::new(
+        radius,
+        slope,
+        z_min,
+        z_max,
+        phi_max(deg),
+      )?),
+      Box { min, max } => GeoVariant::Cuboid(cuboid::Cuboid::new(min, max)),
+      RoundedBox { min, max, radius } =>
+        GeoVariant::RoundedBox(cuboid::RoundedBox::new(min, max, radius)?),
+      Sdf { sdf, bounds } => GeoVariant::Sdf(sdf::SdfShape::new(
+        sdf,
+        bounds.map(|[a, b]| crate::bounds::Bounds3::valid(a, b)),
+      )?),
+      Csg { op, left, right } =>
+        GeoVariant::Csg(csg::Csg::new(op, left.build(None)?, right.build(None)?)?),
+      Heightfield { file, height } => GeoVariant::Heightfield(
+        heightfield::Heightfield::from_image(&file, height.unwrap_or(1.))?,
+      ),
+      Curves { file, kind } => GeoVariant::Curves(
+        curve::Curves::from_file(&file, kind.unwrap_or_default())
+          .map_err(|e| SceneError::io(&file, e))?,
+      ),
+      Bpt { file, tessellate } => {
+        let patches = patch::from_bpt(&file).map_err(|e| SceneError::io(&file, e))?;
+        match tessellate {
+          None => GeoVariant::BezierPatches(patches),
+          Some(res) => GeoVariant::TriangleList(patches.tessellate(file, res)),
+        }
       },
     };
-    Self {
-      to_world: to_world.into(),
-      variant,
+    // meshes are indexed and bounded without checks after this
+    if let GeoVariant::TriangleList(t) = &variant {
+      t.validate()?;
     }
+    Ok(Geometry { to_world, variant })
   }
 }
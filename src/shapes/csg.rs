@@ -0,0 +1,300 @@
+//! Constructive solid geometry, which combines closed shapes by intersecting a ray with each and
+//! merging the intervals along the ray which are inside of them.
+use super::{normal_to_world, t_along, Geometry, Shape, Variant};
+use crate::{
+  bounds::{Bounded, Bounds3},
+  interaction::{Interaction, SurfaceInteraction},
+  scene::SceneError,
+};
+use quick_maths::{Ray3, Transform4, Vec2, Vec3};
+
+/// Where a ray crosses the boundary of a solid
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Crossing {
+  pub t: f32,
+  /// Outward facing normal at this crossing
+  pub normal: Vec3,
+  pub uv: Vec2,
+}
+
+impl Crossing {
+  pub fn new(t: f32, normal: Vec3, uv: Vec2) -> Self { Self { t, normal, uv } }
+}
+
+/// Interval along a ray which is inside of a solid
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+  pub enter: Crossing,
+  pub exit: Crossing,
+}
+
+impl Span {
+  /// Creates a span for a ray, orienting the normals outwards of the interval
+  pub fn new(r: &Ray3, mut enter: Crossing, mut exit: Crossing) -> Self {
+    if enter.normal.dot(&r.dir) > 0. {
+      enter.normal = -enter.normal;
+    }
+    if exit.normal.dot(&r.dir) < 0. {
+      exit.normal = -exit.normal;
+    }
+    Self { enter, exit }
+  }
+}
+
+/// Closed shapes which have a well defined inside
+pub trait Solid: Bounded {
+  /// Returns the sorted disjoint intervals along the whole ray, including behind its origin,
+  /// which are inside of this shape.
+  fn spans(&self, r: &Ray3) -> Vec<Span>;
+}
+
+/// Boolean operation on two solids
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Op {
+  Union,
+  Intersection,
+  /// Removes the right solid from the left
+  Difference,
+}
+
+impl Op {
+  fn inside(self, a: bool, b: bool) -> bool {
+    match self {
+      Op::Union => a || b,
+      Op::Intersection => a && b,
+      Op::Difference => a && !b,
+    }
+  }
+}
+
+/// Merges the spans of two solids according to some operation
+pub fn combine(op: Op, a: Vec<Span>, b: Vec<Span>) -> Vec<Span> {
+  let mut events = a
+    .into_iter()
+    .map(|s| (s, true))
+    .chain(b.into_iter().map(|s| (s, false)))
+    .flat_map(|(s, from_a)| vec![(s.enter, from_a, true), (s.exit, from_a, false)])
+    .collect::<Vec<_>>();
+  events.sort_by(|a, b| a.0.t.total_cmp(&b.0.t));
+  let (mut in_a, mut in_b) = (false, false);
+  let mut start = None;
+  let mut out = vec![];
+  for (mut c, from_a, entering) in events {
+    let was_inside = op.inside(in_a, in_b);
+    if from_a {
+      in_a = entering;
+    } else {
+      in_b = entering;
+    }
+    // The surface of the removed solid faces the other way in the result
+    if !from_a && op == Op::Difference {
+      c.normal = -c.normal;
+    }
+    match (was_inside, op.inside(in_a, in_b)) {
+      (false, true) => start = Some(c),
+      (true, false) => out.push(Span {
+        enter: start.take().unwrap(),
+        exit: c,
+      }),
+      _ => (),
+    }
+  }
+  out
+}
+
+/// Returns whether a shape can be used in constructive solid geometry
+pub fn is_solid(v: &Variant) -> bool {
+  matches!(
+    v,
+    Variant::Sphere(_)
+      | Variant::Cuboid(_)
+      | Variant::Cylinder(_)
+      | Variant::TriangleList(_)
+      | Variant::Csg(_)
+  )
+}
+
+fn variant_spans(v: &Variant, r: &Ray3) -> Vec<Span> {
+  match v {
+    Variant::Sphere(s) => s.spans(r),
+    Variant::Cuboid(c) => c.spans(r),
+    Variant::Cylinder(c) => c.spans(r),
+    Variant::TriangleList(t) => t.spans(r),
+    Variant::Csg(c) => c.spans(r),
+    v => unreachable!("{:?} is not a solid", v),
+  }
+}
+
+/// One side of a CSG node, with its own transform
+#[derive(Debug, PartialEq)]
+pub struct Operand {
+  variant: Box<Variant>,
+  to_world: Transform4,
+  from_world: Transform4,
+}
+
+impl Operand {
+  /// Wraps a shape for CSG, which must be a solid. Meshes must also be closed, otherwise
+  /// crossings along a ray would not alternate between entering and exiting.
+  pub fn new(g: Geometry) -> Result<Self, SceneError> {
+    let Geometry { to_world, variant } = g;
+    match &variant {
+      Variant::TriangleList(t) if !t.is_closed() =>
+        return Err(SceneError::invalid(
+          "Meshes in CSG must be closed, with every edge shared by exactly two triangles",
+        )),
+      v if !is_solid(v) =>
+        return Err(SceneError::invalid(
+          "Only spheres, boxes, cylinders, closed meshes and CSG can be combined by CSG",
+        )),
+      _ => (),
+    }
+    Ok(Self {
+      variant: Box::new(variant),
+      from_world: to_world.inv(),
+      to_world,
+    })
+  }
+}
+
+impl Solid for Operand {
+  fn spans(&self, r: &Ray3) -> Vec<Span> {
+    let local = self.from_world.apply_ray(r);
+    let to_world = |c: Crossing| {
+      let t = if c.t.is_finite() {
+        t_along(r, &self.to_world.apply_point(&local.at(c.t)))
+      } else {
+        c.t
+      };
+      Crossing::new(t, normal_to_world(&self.from_world, &c.normal), c.uv)
+    };
+    variant_spans(&self.variant, &local)
+      .into_iter()
+      .map(|s| Span {
+        enter: to_world(s.enter),
+        exit: to_world(s.exit),
+      })
+      .collect()
+  }
+}
+
+impl Bounded for Operand {
+  fn bounds(&self) -> Bounds3 { self.variant.bounds().transform(&self.to_world) }
+}
+
+/// Boolean combination of two solids
+#[derive(Debug, PartialEq)]
+pub struct Csg {
+  op: Op,
+  left: Operand,
+  right: Operand,
+}
+
+impl Csg {
+  pub fn new(op: Op, left: Geometry, right: Geometry) -> Result<Self, SceneError> {
+    Ok(Self {
+      op,
+      left: Operand::new(left)?,
+      right: Operand::new(right)?,
+    })
+  }
+}
+
+impl Solid for Csg {
+  fn spans(&self, r: &Ray3) -> Vec<Span> {
+    let left = self.left.spans(r);
+    if left.is_empty() && self.op != Op::Union {
+      return left;
+    }
+    combine(self.op, left, self.right.spans(r))
+  }
+}
+
+impl Shape for Csg {
+  fn intersect_ray(&self, r: &Ray3) -> Option<SurfaceInteraction> {
+    let c = self
+      .spans(r)
+      .into_iter()
+      .flat_map(|s| vec![s.enter, s.exit])
+      .find(|c| c.t > crate::EPS)?;
+    let p = r.at(c.t);
+    Some(SurfaceInteraction {
+      it: Interaction { t: c.t, p },
+      normal: c.normal,
+      uv: c.uv,
+      wi: r.dir.norm(),
+      material: 0,
+    })
+  }
+}
+
+impl Bounded for Csg {
+  fn bounds(&self) -> Bounds3 {
+    let (l, r) = (self.left.bounds(), self.right.bounds());
+    match self.op {
+      Op::Union => l.union(&r),
+      Op::Intersection => {
+        let (_, min) = l.min.sift(&r.min);
+        let (max, _) = l.max.sift(&r.max);
+        Bounds3::new(min, max)
+      },
+      Op::Difference => l,
+    }
+  }
+}
+
+#[cfg(test)]
+mod test_csg {
+  use super::*;
+  use crate::shapes::{
+    cuboid::Cuboid, plane::Plane, sphere::Sphere, triangle_list::IndexedTriangles,
+  };
+  fn geometry(variant: Variant) -> Geometry {
+    Geometry {
+      to_world: Transform4::identity(),
+      variant,
+    }
+  }
+  #[test]
+  fn box_minus_sphere() {
+    let csg = Csg::new(
+      Op::Difference,
+      geometry(Variant::Cuboid(Cuboid::new(Vec3::of(-1.), Vec3::of(1.)))),
+      geometry(Variant::Sphere(Sphere::new(Vec3::new(0., 0., -1.), 0.5))),
+    )
+    .unwrap();
+    // Through the hole carved by the sphere
+    let r = Ray3::new(Vec3::new(0., 0., -5.), Vec3::new(0., 0., 1.));
+    let si = csg.intersect_ray(&r).unwrap();
+    assert!((si.it.t - 4.5).abs() < 1e-5);
+    assert!((si.normal - Vec3::new(0., 0., -1.)).magn() < 1e-5);
+    // Away from the hole it hits the box
+    let r = Ray3::new(Vec3::new(0.8, 0.8, -5.), Vec3::new(0., 0., 1.));
+    assert!((csg.intersect_ray(&r).unwrap().it.t - 4.).abs() < 1e-5);
+  }
+  #[test]
+  fn open_meshes_are_not_solid() {
+    let verts = vec![
+      Vec3::of(0.),
+      Vec3::new(1., 0., 0.),
+      Vec3::new(0., 1., 0.),
+      Vec3::new(0., 0., 1.),
+    ];
+    let faces = vec![
+      Vec3::new(0, 2, 1),
+      Vec3::new(0, 1, 3),
+      Vec3::new(0, 3, 2),
+      Vec3::new(1, 2, 3),
+    ];
+    let mesh = |faces: Vec<Vec3<u32>>| {
+      let t = IndexedTriangles::new(String::from("tetra"), verts.clone(), vec![], faces);
+      geometry(Variant::TriangleList(t))
+    };
+    let sphere = || geometry(Variant::Sphere(Sphere::new(Vec3::of(0.), 0.1)));
+    assert!(Csg::new(Op::Union, mesh(faces.clone()), sphere()).is_ok());
+    let open = Csg::new(Op::Union, mesh(faces[..3].to_vec()), sphere());
+    assert!(matches!(open, Err(SceneError::Invalid { .. })));
+    let plane = Plane::new(&Vec3::new(0., 1., 0.), 0., &Vec3::new(0., 0., 1.), 1., 1.);
+    assert!(Csg::new(Op::Union, geometry(Variant::Plane(plane)), sphere()).is_err());
+  }
+}
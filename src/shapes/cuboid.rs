@@ -0,0 +1,258 @@
+use super::{
+  csg::{Crossing, Solid, Span},
+  positive,
+  quadrics::Cylinder,
+  sphere::Sphere,
+  Shape,
+};
+use crate::{
+  bounds::{Bounded, Bounds3},
+  interaction::{Interaction, SurfaceInteraction},
+  scene::SceneError,
+};
+use quick_maths::{Ray3, Vec2, Vec3, Vector, Zero};
+use std::f32::consts::PI;
+
+/// Box which is axis aligned in object space
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cuboid {
+  bounds: Bounds3,
+}
+
+impl Cuboid {
+  pub fn new(a: Vec3, b: Vec3) -> Self {
+    Self {
+      bounds: Bounds3::valid(a, b),
+    }
+  }
+}
+
+/// Returns the uv on the face of a box with the given normal, where each face covers all of
+/// [0,1]^2.
+fn face_uv(bounds: &Bounds3, p: &Vec3, normal: &Vec3) -> Vec2 {
+  let Vector([nx, ny, nz]) = normal.abs();
+  let (u, v) = if nx >= ny && nx >= nz {
+    (1, 2)
+  } else if ny >= nz {
+    (2, 0)
+  } else {
+    (0, 1)
+  };
+  let rel = (*p - bounds.min) / bounds.diagonal();
+  Vec2::new(rel[u], rel[v])
+}
+
+impl Shape for Cuboid {
+  fn intersect_ray(&self, r: &Ray3) -> Option<SurfaceInteraction> {
+    let (t, normal) = self.bounds.intersects_ray_params(r)?;
+    let p = r.at(t);
+    Some(SurfaceInteraction {
+      it: Interaction { t, p },
+      uv: face_uv(&self.bounds, &p, &normal),
+      normal,
+      wi: r.dir.norm(),
+      material: 0,
+    })
+  }
+}
+
+impl Bounded for Cuboid {
+  fn bounds(&self) -> Bounds3 { self.bounds }
+}
+
+impl Solid for Cuboid {
+  fn spans(&self, r: &Ray3) -> Vec<Span> {
+    let (mut enter, mut exit) = (
+      (f32::NEG_INFINITY, Vec3::zero()),
+      (f32::INFINITY, Vec3::zero()),
+    );
+    for i in 0..3 {
+      let inv = r.dir[i].recip();
+      if !inv.is_finite() {
+        if r.pos[i] < self.bounds.min[i] || r.pos[i] > self.bounds.max[i] {
+          return vec![];
+        }
+        continue;
+      }
+      let mut n = Vec3::zero();
+      n[i] = 1.;
+      let lo = ((self.bounds.min[i] - r.pos[i]) * inv, -n);
+      let hi = ((self.bounds.max[i] - r.pos[i]) * inv, n);
+      let (near, far) = if lo.0 < hi.0 { (lo, hi) } else { (hi, lo) };
+      if near.0 > enter.0 {
+        enter = near;
+      }
+      if far.0 < exit.0 {
+        exit = far;
+      }
+    }
+    if enter.0 > exit.0 {
+      return vec![];
+    }
+    let crossing = |(t, n): (f32, Vec3)| Crossing::new(t, n, face_uv(&self.bounds, &r.at(t), &n));
+    vec![Span::new(r, crossing(enter), crossing(exit))]
+  }
+}
+
+/// Maps the axis of an edge to z so it can be intersected as a z-aligned cylinder
+fn to_z(v: &Vec3, axis: usize) -> Vec3 {
+  let &Vector([x, y, z]) = v;
+  match axis {
+    0 => Vec3::new(y, z, x),
+    1 => Vec3::new(z, x, y),
+    _ => *v,
+  }
+}
+
+/// Inverse of to_z
+fn from_z(v: &Vec3, axis: usize) -> Vec3 {
+  let &Vector([x, y, z]) = v;
+  match axis {
+    0 => Vec3::new(z, x, y),
+    1 => Vec3::new(y, z, x),
+    _ => *v,
+  }
+}
+
+/// Edge of a rounded box, a cylinder running along some axis
+#[derive(Debug, Clone, PartialEq)]
+struct Edge {
+  axis: usize,
+  center: Vec3,
+  cylinder: Cylinder,
+}
+
+impl Edge {
+  fn intersect_ray(&self, r: &Ray3) -> Option<SurfaceInteraction> {
+    let local = Ray3::new(
+      to_z(&(r.pos - self.center), self.axis),
+      to_z(&r.dir, self.axis),
+    );
+    let si = self.cylinder.intersect_ray(&local)?;
+    Some(SurfaceInteraction {
+      it: Interaction {
+        t: si.it.t,
+        p: from_z(&si.it.p, self.axis) + self.center,
+      },
+      normal: from_z(&si.normal, self.axis),
+      uv: si.uv,
+      wi: r.dir.norm(),
+      material: 0,
+    })
+  }
+}
+
+/// Box with its edges and corners rounded off by some radius, built from the union of three
+/// slabs, twelve cylinders along the edges and eight spheres at the corners.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoundedBox {
+  bounds: Bounds3,
+  slabs: [Cuboid; 3],
+  edges: Vec<Edge>,
+  corners: Vec<Sphere>,
+}
+
+impl RoundedBox {
+  /// Creates a rounded box, where the radius is at most half of the box's smallest side
+  pub fn new(a: Vec3, b: Vec3, radius: f32) -> Result<Self, SceneError> {
+    let bounds = Bounds3::valid(a, b);
+    let half = bounds.diagonal() / 2.;
+    let radius = radius.max(0.).min(half.x()).min(half.y()).min(half.z());
+    positive("Rounded box", "radius", radius)?;
+    let (min, max) = (bounds.min, bounds.max);
+    let inner = Bounds3::new(min + radius, max - radius);
+    let slab = |axis: usize| {
+      let mut lo = inner.min;
+      let mut hi = inner.max;
+      lo[axis] = min[axis];
+      hi[axis] = max[axis];
+      Cuboid::new(lo, hi)
+    };
+    let corner = |i: usize, axis: usize| {
+      if i & (1 << axis) == 0 {
+        inner.min[axis]
+      } else {
+        inner.max[axis]
+      }
+    };
+    let corners = (0..8)
+      .map(|i| Sphere::new(Vec3::new(corner(i, 0), corner(i, 1), corner(i, 2)), radius))
+      .collect();
+    let edges = (0..3)
+      .flat_map(|axis| {
+        (0..8)
+          .filter(move |i| i & (1 << axis) == 0)
+          .map(move |i| (axis, i))
+      })
+      .filter_map(|(axis, i)| {
+        let half_len = (inner.max[axis] - inner.min[axis]) / 2.;
+        // edges vanish when the radius is half of the box, leaving the corners to cover them
+        if half_len <= 0. {
+          return None;
+        }
+        let mut center = Vec3::new(corner(i, 0), corner(i, 1), corner(i, 2));
+        center[axis] = inner.center()[axis];
+        let edge = Cylinder::new(radius, -half_len, half_len, 2. * PI).map(|cylinder| Edge {
+          axis,
+          center,
+          cylinder,
+        });
+        Some(edge)
+      })
+      .collect::<Result<_, SceneError>>()?;
+    Ok(Self {
+      bounds,
+      slabs: [slab(0), slab(1), slab(2)],
+      edges,
+      corners,
+    })
+  }
+}
+
+impl Shape for RoundedBox {
+  fn intersect_ray(&self, r: &Ray3) -> Option<SurfaceInteraction> {
+    let slabs = self.slabs.iter().filter_map(|s| s.intersect_ray(r));
+    let edges = self.edges.iter().filter_map(|e| e.intersect_ray(r));
+    let corners = self.corners.iter().filter_map(|c| c.intersect_ray(r));
+    let mut si = slabs
+      .chain(edges)
+      .chain(corners)
+      .min_by(|a, b| a.it.closer(&b.it))?;
+    si.uv = face_uv(&self.bounds, &si.it.p, &si.normal);
+    Some(si)
+  }
+}
+
+impl Bounded for RoundedBox {
+  fn bounds(&self) -> Bounds3 { self.bounds }
+}
+
+#[cfg(test)]
+mod test_cuboid {
+  use super::*;
+  #[test]
+  fn rounded_box_matches_box_faces() {
+    let (a, b) = (Vec3::of(-1.), Vec3::of(1.));
+    let rounded = RoundedBox::new(a, b, 0.25).unwrap();
+    let cuboid = Cuboid::new(a, b);
+    let r = Ray3::new(Vec3::new(0.1, 0.2, -5.), Vec3::new(0., 0., 1.));
+    let (s0, s1) = (
+      rounded.intersect_ray(&r).unwrap(),
+      cuboid.intersect_ray(&r).unwrap(),
+    );
+    assert!((s0.it.t - s1.it.t).abs() < 1e-5);
+    assert!((s0.normal - s1.normal).magn() < 1e-5);
+    // Near the corner the rounded box is hit later
+    let r = Ray3::new(Vec3::new(0.95, 0.95, -5.), Vec3::new(0., 0., 1.));
+    assert!(rounded.intersect_ray(&r).unwrap().it.t > cuboid.intersect_ray(&r).unwrap().it.t);
+  }
+  #[test]
+  fn rounded_box_needs_a_radius() {
+    let flat = RoundedBox::new(Vec3::of(0.), Vec3::new(1., 1., 0.), 0.25);
+    assert!(flat.is_err());
+    assert!(RoundedBox::new(Vec3::of(0.), Vec3::of(1.), -1.).is_err());
+    // a radius of half the box leaves only the corners, which form a sphere
+    let sphere = RoundedBox::new(Vec3::of(0.), Vec3::of(1.), 0.5).unwrap();
+    assert!(sphere.edges.is_empty());
+  }
+}
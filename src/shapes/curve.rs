@@ -0,0 +1,359 @@
+//! Thin curves for hair and fur, made of cubic bezier segments which are intersected by
+//! recursively subdividing them in the space of the ray.
+use super::Shape;
+use crate::{
+  bounds::{Bounded, Bounds3},
+  interaction::{Interaction, SurfaceInteraction},
+  two_d::spline::{CubicSpline, CUBIC_BEZIER_WEIGHTS},
+};
+use quick_maths::{Ray3, Vec2, Vec3, Vector};
+use std::{
+  fs::File,
+  io::{self, BufRead},
+  path::Path,
+};
+
+/// How the surface of a curve is shaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum CurveKind {
+  /// Flat ribbon which always faces the incoming ray
+  Ribbon,
+  /// Ribbon with normals bent as if it were a cylinder
+  Cylinder,
+}
+
+impl Default for CurveKind {
+  fn default() -> Self { CurveKind::Cylinder }
+}
+
+/// Single cubic bezier segment with a width that varies linearly along it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve {
+  ctrls: [Vec3; 4],
+  widths: [f32; 2],
+  kind: CurveKind,
+}
+
+/// Splits a bezier curve in half using de Casteljau's algorithm
+fn split([p0, p1, p2, p3]: [Vec3; 4]) -> ([Vec3; 4], [Vec3; 4]) {
+  let mid = |a: Vec3, b: Vec3| (a + b) / 2.;
+  let (q0, q1, q2) = (mid(p0, p1), mid(p1, p2), mid(p2, p3));
+  let (r0, r1) = (mid(q0, q1), mid(q1, q2));
+  let s = mid(r0, r1);
+  ([p0, q0, r0, s], [s, r1, q2, p3])
+}
+
+/// Converts the control points of a uniform cubic b-spline into a bezier segment over the
+/// same curve.
+pub fn b_spline_to_bezier([p0, p1, p2, p3]: [Vec3; 4]) -> [Vec3; 4] {
+  [
+    (p0 + p1 * 4. + p2) / 6.,
+    (p1 * 2. + p2) / 3.,
+    (p1 + p2 * 2.) / 3.,
+    (p1 + p2 * 4. + p3) / 6.,
+  ]
+}
+
+/// Intermediate result of intersecting a curve in ray space
+struct Hit {
+  /// Distance along the normalized ray
+  dist: f32,
+  /// Parameter along the curve
+  u: f32,
+  /// Signed distance from the center of the curve in [-1, 1]
+  offset: f32,
+}
+
+impl Curve {
+  pub fn new(ctrls: [Vec3; 4], widths: [f32; 2], kind: CurveKind) -> Self {
+    Self {
+      ctrls,
+      widths,
+      kind,
+    }
+  }
+  pub fn at(&self, u: f32) -> Vec3 {
+    let [p0, p1, p2, p3] = self.ctrls;
+    CubicSpline::new([p0, p1, p2, p3]).at(u, &CUBIC_BEZIER_WEIGHTS)
+  }
+  pub fn tangent(&self, u: f32) -> Vec3 {
+    let [p0, p1, p2, p3] = self.ctrls;
+    let v = 1. - u;
+    ((p1 - p0) * (v * v) + (p2 - p1) * (2. * u * v) + (p3 - p2) * (u * u)) * 3.
+  }
+  fn width(&self, u: f32) -> f32 { self.widths[0] + (self.widths[1] - self.widths[0]) * u }
+  /// Recursively subdivides the curve, given in the space of the ray where the ray starts at the
+  /// origin and points along +z.
+  fn recurse(
+    &self,
+    cps: [Vec3; 4],
+    (u0, u1): (f32, f32),
+    depth: u32,
+    max_dist: f32,
+  ) -> Option<Hit> {
+    let half_width = self.width(u0).max(self.width(u1)) / 2.;
+    let bounds = cps[1..]
+      .iter()
+      .fold(Bounds3::empty(cps[0]), |acc, p| acc.union_vec(p));
+    if bounds.min.x() > half_width
+      || bounds.max.x() < -half_width
+      || bounds.min.y() > half_width
+      || bounds.max.y() < -half_width
+      || bounds.max.z() < 0.
+      || bounds.min.z() > max_dist
+    {
+      return None;
+    }
+    if depth > 0 {
+      let (l, r) = split(cps);
+      let mid = (u0 + u1) / 2.;
+      return match self.recurse(l, (u0, mid), depth - 1, max_dist) {
+        Some(h) => {
+          let max_dist = h.dist;
+          Some(self.recurse(r, (mid, u1), depth - 1, max_dist).unwrap_or(h))
+        },
+        None => self.recurse(r, (mid, u1), depth - 1, max_dist),
+      };
+    }
+    // Approximate the remaining piece with a line segment
+    let (p0, p3) = (cps[0], cps[3]);
+    let seg = Vec2::new(p3.x() - p0.x(), p3.y() - p0.y());
+    let to_origin = Vec2::new(-p0.x(), -p0.y());
+    let w = (to_origin.dot(&seg) / seg.sqr_magn()).max(0.).min(1.);
+    if !w.is_finite() {
+      return None;
+    }
+    let closest = p0 + (p3 - p0) * w;
+    let u = u0 + (u1 - u0) * w;
+    let half_width = self.width(u) / 2.;
+    let dist_sqr = closest.x() * closest.x() + closest.y() * closest.y();
+    if dist_sqr > half_width * half_width || closest.z() < 0. || closest.z() > max_dist {
+      return None;
+    }
+    // sign of which side of the segment the ray passes
+    let side = seg.x() * to_origin.y() - seg.y() * to_origin.x();
+    let offset = dist_sqr.sqrt() / half_width;
+    Some(Hit {
+      dist: closest.z(),
+      u,
+      offset: if side < 0. { -offset } else { offset },
+    })
+  }
+  /// Number of subdivisions needed so that the segments are close to the curve
+  fn max_depth(&self) -> u32 {
+    // https://www.pbr-book.org/3ed-2018/Shapes/Curves
+    let [p0, p1, p2, p3] = self.ctrls;
+    let l0 = (0..3)
+      .map(|i| {
+        (p0[i] - 2. * p1[i] + p2[i])
+          .abs()
+          .max((p1[i] - 2. * p2[i] + p3[i]).abs())
+      })
+      .fold(0., f32::max);
+    let eps = self.widths[0].max(self.widths[1]) * 0.05;
+    let r0 = (std::f32::consts::SQRT_2 * 6. * l0 / (8. * eps)).log2() / 2.;
+    if r0.is_finite() {
+      r0.max(0.).min(10.) as u32
+    } else {
+      0
+    }
+  }
+}
+
+/// Builds an orthonormal basis with the given unit vector as the third axis
+fn basis(n: &Vec3) -> (Vec3, Vec3) {
+  // https://graphics.pixar.com/library/OrthonormalB/paper.pdf
+  let sign = 1f32.copysign(n.z());
+  let a = -1. / (sign + n.z());
+  let b = n.x() * n.y() * a;
+  (
+    Vec3::new(1. + sign * n.x() * n.x() * a, sign * b, -sign * n.x()),
+    Vec3::new(b, sign + n.y() * n.y() * a, -n.y()),
+  )
+}
+
+impl Shape for Curve {
+  fn intersect_ray(&self, r: &Ray3) -> Option<SurfaceInteraction> {
+    let dir_len = r.dir.magn();
+    let d = r.dir / dir_len;
+    let (x, y) = basis(&d);
+    let to_ray = |p: &Vec3| {
+      let rel = *p - r.pos;
+      Vec3::new(rel.dot(&x), rel.dot(&y), rel.dot(&d))
+    };
+    let [p0, p1, p2, p3] = self.ctrls;
+    let cps = [to_ray(&p0), to_ray(&p1), to_ray(&p2), to_ray(&p3)];
+    let hit = self.recurse(cps, (0., 1.), self.max_depth(), f32::INFINITY)?;
+    let t = hit.dist / dir_len;
+    let normal = match self.kind {
+      CurveKind::Ribbon => -d,
+      CurveKind::Cylinder => {
+        let tangent = self.tangent(hit.u).norm();
+        // Direction across the curve as seen by the ray, and towards the ray
+        let across = tangent.cross(&d).norm();
+        let facing = across.cross(&tangent).norm();
+        let facing = if facing.dot(&d) > 0. { -facing } else { facing };
+        let across = if across.dot(&(r.at(t) - self.at(hit.u))) < 0. {
+          -across
+        } else {
+          across
+        };
+        let s = hit.offset.abs().min(1.);
+        (facing * (1. - s * s).sqrt() + across * s).norm()
+      },
+    };
+    Some(SurfaceInteraction {
+      it: Interaction { t, p: r.at(t) },
+      normal,
+      uv: Vec2::new(hit.u, (hit.offset + 1.) / 2.),
+      wi: d,
+      material: 0,
+    })
+  }
+}
+
+impl Bounded for Curve {
+  fn bounds(&self) -> Bounds3 {
+    let [p0, p1, p2, p3] = self.ctrls;
+    let b = [p1, p2, p3]
+      .iter()
+      .fold(Bounds3::empty(p0), |acc, p| acc.union_vec(p));
+    let w = self.widths[0].max(self.widths[1]) / 2.;
+    Bounds3::new(b.min - w, b.max + w)
+  }
+}
+
+/// Collection of curve segments, such as strands of hair
+#[derive(Debug, PartialEq)]
+pub struct Curves {
+  segments: Vec<(Curve, Bounds3)>,
+  bounds: Bounds3,
+}
+
+impl Curves {
+  /// Creates a collection of curves, which must not be empty so that it has bounds
+  pub fn new(segments: Vec<Curve>) -> io::Result<Self> {
+    let segments = segments
+      .into_iter()
+      .map(|c| {
+        let b = c.bounds();
+        (c, b)
+      })
+      .collect::<Vec<_>>();
+    let first = match segments.first() {
+      None =>
+        return Err(io::Error::new(
+          io::ErrorKind::InvalidData,
+          "Curves need at least one strand",
+        )),
+      Some(&(_, b)) => b,
+    };
+    let bounds = segments.iter().fold(first, |acc, (_, b)| acc.union(b));
+    Ok(Self { segments, bounds })
+  }
+  /// Loads strands from a text file, where each line is one strand:
+  /// ```text
+  /// # bezier strands have 3n+1 points, b-spline strands have at least 4
+  /// bezier <root width> <tip width> x y z x y z ...
+  /// bspline <root width> <tip width> x y z x y z ...
+  /// ```
+  pub fn from_file(p: impl AsRef<Path>, kind: CurveKind) -> io::Result<Self> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let buf = io::BufReader::new(File::open(p)?);
+    let mut segments = vec![];
+    for (i, line) in buf.lines().enumerate() {
+      let line = line?;
+      let parts = line
+        .splitn(2, '#')
+        .next()
+        .unwrap()
+        .split_whitespace()
+        .collect::<Vec<_>>();
+      let (basis, rest) = match parts.split_first() {
+        None => continue,
+        Some((basis, rest)) => (*basis, rest),
+      };
+      let nums = rest
+        .iter()
+        .map(|v| v.parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid(format!("line {}: {}", i + 1, e)))?;
+      if nums.len() < 2 || (nums.len() - 2) % 3 != 0 {
+        return Err(invalid(format!(
+          "line {}: expected widths then xyz triples",
+          i + 1
+        )));
+      }
+      let (root, tip) = (nums[0], nums[1]);
+      let pts = nums[2..]
+        .chunks_exact(3)
+        .map(|c| Vec3::new(c[0], c[1], c[2]))
+        .collect::<Vec<_>>();
+      let beziers = match basis {
+        "bezier" if pts.len() >= 4 && (pts.len() - 1) % 3 == 0 => pts
+          .windows(4)
+          .step_by(3)
+          .map(|w| [w[0], w[1], w[2], w[3]])
+          .collect::<Vec<_>>(),
+        "bspline" if pts.len() >= 4 => pts
+          .windows(4)
+          .map(|w| b_spline_to_bezier([w[0], w[1], w[2], w[3]]))
+          .collect(),
+        _ =>
+          return Err(invalid(format!(
+            "line {}: invalid strand {:?}",
+            i + 1,
+            basis
+          ))),
+      };
+      let n = beziers.len() as f32;
+      let width = |u: f32| root + (tip - root) * u;
+      segments.extend(beziers.into_iter().enumerate().map(|(j, ctrls)| {
+        let j = j as f32;
+        Curve::new(ctrls, [width(j / n), width((j + 1.) / n)], kind)
+      }));
+    }
+    Self::new(segments)
+  }
+}
+
+impl Shape for Curves {
+  fn intersect_ray(&self, r: &Ray3) -> Option<SurfaceInteraction> {
+    self
+      .segments
+      .iter()
+      .filter(|(_, b)| b.intersect_ray(r))
+      .filter_map(|(c, _)| c.intersect_ray(r))
+      .min_by(|a, b| a.it.closer(&b.it))
+  }
+}
+
+impl Bounded for Curves {
+  fn bounds(&self) -> Bounds3 { self.bounds }
+}
+
+#[cfg(test)]
+mod test_curve {
+  use super::*;
+  #[test]
+  fn empty_curves() {
+    assert!(Curves::new(vec![]).is_err());
+  }
+  #[test]
+  fn hits_straight_curve() {
+    let ctrls = [
+      Vec3::new(-1., 0., 0.),
+      Vec3::new(-1. / 3., 0., 0.),
+      Vec3::new(1. / 3., 0., 0.),
+      Vec3::new(1., 0., 0.),
+    ];
+    let c = Curve::new(ctrls, [0.1, 0.1], CurveKind::Cylinder);
+    let r = Ray3::new(Vec3::new(0.5, 0., -2.), Vec3::new(0., 0., 1.));
+    let si = c.intersect_ray(&r).unwrap();
+    assert!((si.it.t - 2.).abs() < 1e-4);
+    assert!((si.uv.x() - 0.75).abs() < 1e-3);
+    assert!((si.normal - Vec3::new(0., 0., -1.)).magn() < 1e-3);
+    let miss = Ray3::new(Vec3::new(0.5, 0.2, -2.), Vec3::new(0., 0., 1.));
+    assert!(c.intersect_ray(&miss).is_none());
+  }
+}
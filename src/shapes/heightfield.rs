@@ -0,0 +1,179 @@
+use super::{triangle::Triangle, Shape};
+use crate::{
+  bounds::{Bounded, Bounds3},
+  interaction::SurfaceInteraction,
+  scene::SceneError,
+};
+use quick_maths::{Ray3, Vec2, Vec3, Vector};
+use std::path::Path;
+
+/// Regular grid of elevations which spans [0,1] on the x and z axes, with y as the height.
+/// Each cell of the grid is split into two triangles and shaded with smooth normals.
+#[derive(Debug, PartialEq)]
+pub struct Heightfield {
+  /// Number of samples along x
+  nx: usize,
+  /// Number of samples along z
+  nz: usize,
+  /// Row major heights, with rows along x
+  heights: Vec<f32>,
+  /// Per sample normals
+  normals: Vec<Vec3>,
+  max_height: f32,
+}
+
+impl Heightfield {
+  pub fn new(nx: usize, nz: usize, heights: Vec<f32>) -> Result<Self, SceneError> {
+    if nx < 2 || nz < 2 {
+      let msg = format!("Heightfield needs at least 2x2 samples, not {}x{}", nx, nz);
+      return Err(SceneError::invalid(msg));
+    }
+    if heights.len() != nx * nz {
+      let msg = format!(
+        "Heightfield has {} heights for {}x{}",
+        heights.len(),
+        nx,
+        nz
+      );
+      return Err(SceneError::invalid(msg));
+    }
+    let max_height = heights.iter().copied().fold(0., f32::max);
+    let mut hf = Self {
+      nx,
+      nz,
+      heights,
+      normals: vec![],
+      max_height,
+    };
+    hf.normals = (0..nx * nz)
+      .map(|i| hf.sample_normal(i % nx, i / nx))
+      .collect();
+    Ok(hf)
+  }
+  /// Loads a grayscale image where white is a height of `scale`
+  pub fn from_image(p: impl AsRef<Path>, scale: f32) -> Result<Self, SceneError> {
+    let p = p.as_ref();
+    let img = image::open(p)
+      .map_err(|e| SceneError::image(p, e))?
+      .to_luma();
+    let (w, h) = img.dimensions();
+    let heights = img.pixels().map(|px| px[0] as f32 / 255. * scale).collect();
+    Self::new(w as usize, h as usize, heights)
+      .map_err(|e| SceneError::parse(p, None, e.to_string()))
+  }
+  fn height(&self, x: usize, z: usize) -> f32 { self.heights[z * self.nx + x] }
+  fn vertex(&self, x: usize, z: usize) -> Vec3 {
+    Vec3::new(
+      x as f32 / (self.nx - 1) as f32,
+      self.height(x, z),
+      z as f32 / (self.nz - 1) as f32,
+    )
+  }
+  /// Computes the normal at a sample from the central differences of its neighbors
+  fn sample_normal(&self, x: usize, z: usize) -> Vec3 {
+    let (x0, x1) = (x.saturating_sub(1), (x + 1).min(self.nx - 1));
+    let (z0, z1) = (z.saturating_sub(1), (z + 1).min(self.nz - 1));
+    let dx = self.vertex(x1, z) - self.vertex(x0, z);
+    let dz = self.vertex(x, z1) - self.vertex(x, z0);
+    dz.cross(&dx).norm()
+  }
+  /// Intersects the two triangles in a cell
+  fn intersect_cell(&self, x: usize, z: usize, r: &Ray3) -> Option<SurfaceInteraction> {
+    let corners = [(x, z), (x + 1, z), (x + 1, z + 1), (x, z + 1)];
+    [[0, 1, 2], [0, 2, 3]]
+      .iter()
+      .filter_map(|idxs| {
+        let [a, b, c] = *idxs;
+        let tri = Triangle(Vector([
+          self.vertex(corners[a].0, corners[a].1),
+          self.vertex(corners[b].0, corners[b].1),
+          self.vertex(corners[c].0, corners[c].1),
+        ]));
+        let mut si = tri.intersect_ray(r)?;
+        if !si.it.t.is_sign_positive() {
+          return None;
+        }
+        // interpolate normals with barycentric coordinates
+        let Vector([u, v]) = si.uv;
+        let n = |i: usize| self.normals[corners[i].1 * self.nx + corners[i].0];
+        si.normal = (n(a) * (1. - u - v) + n(b) * u + n(c) * v).norm();
+        si.uv = Vec2::new(si.it.p.x(), si.it.p.z());
+        Some(si)
+      })
+      .min_by(|a, b| a.it.closer(&b.it))
+  }
+}
+
+impl Shape for Heightfield {
+  fn intersect_ray(&self, r: &Ray3) -> Option<SurfaceInteraction> {
+    let (t_min, t_max) = self.bounds().ray_range(r)?;
+    let cells = [(self.nx - 1) as f32, (self.nz - 1) as f32];
+    let start = r.at(t_min);
+    let (o, d) = ([r.pos.x(), r.pos.z()], [r.dir.x(), r.dir.z()]);
+    // Walk the grid cell by cell along the ray in the xz-plane
+    let mut cell = [0; 2];
+    let mut step = [0isize; 2];
+    let mut t_next = [f32::INFINITY; 2];
+    let mut t_delta = [f32::INFINITY; 2];
+    for (i, &p) in [start.x(), start.z()].iter().enumerate() {
+      cell[i] = ((p * cells[i]) as isize).max(0).min(cells[i] as isize - 1);
+      if d[i] > 0. {
+        step[i] = 1;
+        t_next[i] = ((cell[i] + 1) as f32 / cells[i] - o[i]) / d[i];
+        t_delta[i] = 1. / (cells[i] * d[i]);
+      } else if d[i] < 0. {
+        step[i] = -1;
+        t_next[i] = (cell[i] as f32 / cells[i] - o[i]) / d[i];
+        t_delta[i] = -1. / (cells[i] * d[i]);
+      }
+    }
+    loop {
+      if let Some(si) = self.intersect_cell(cell[0] as usize, cell[1] as usize, r) {
+        // Triangles may be hit outside of this cell's part of the ray if they're shared.
+        if si.it.t <= t_next[0].min(t_next[1]) + 1e-5 {
+          return Some(si);
+        }
+      }
+      let axis = if t_next[0] < t_next[1] { 0 } else { 1 };
+      if t_next[axis] > t_max {
+        return None;
+      }
+      cell[axis] += step[axis];
+      if cell[axis] < 0 || cell[axis] >= cells[axis] as isize {
+        return None;
+      }
+      t_next[axis] += t_delta[axis];
+    }
+  }
+}
+
+impl Bounded for Heightfield {
+  fn bounds(&self) -> Bounds3 {
+    Bounds3::new(Vec3::of(0.), Vec3::new(1., self.max_height.max(1e-5), 1.))
+  }
+}
+
+#[cfg(test)]
+mod test_heightfield {
+  use super::*;
+  #[test]
+  fn hits_slope() {
+    // Ramp rising along x from 0 to 1
+    let heights = (0..16).map(|i| (i % 4) as f32 / 3.).collect();
+    let hf = Heightfield::new(4, 4, heights).unwrap();
+    let r = Ray3::new(Vec3::new(0.5, 5., 0.5), Vec3::new(0., -1., 0.));
+    let si = hf.intersect_ray(&r).unwrap();
+    assert!((si.it.p.y() - 0.5).abs() < 1e-4);
+    let expected = Vec3::new(-1., 1., 0.).norm();
+    assert!((si.normal - expected).magn() < 1e-4);
+    // Grazing ray travelling across the grid
+    let r = Ray3::new(Vec3::new(-1., 0.9, 0.3), Vec3::new(1., 0., 0.));
+    let si = hf.intersect_ray(&r).unwrap();
+    assert!((si.it.p.x() - 0.9).abs() < 1e-4);
+  }
+  #[test]
+  fn too_few_samples() {
+    assert!(Heightfield::new(1, 4, vec![0.; 4]).is_err());
+    assert!(Heightfield::new(2, 2, vec![0.; 3]).is_err());
+  }
+}
@@ -1,17 +1,26 @@
 pub mod builder;
+pub mod csg;
 pub use builder::Builder;
+pub mod cuboid;
+pub mod curve;
+pub mod heightfield;
+pub mod patch;
 pub mod plane;
+pub mod ply;
+pub mod quadrics;
+pub mod sdf;
 pub mod sphere;
 pub mod triangle;
 pub mod triangle_list;
 
 use crate::{
   bounds::{Bounded, Bounds3},
-  bsdf::BSDFImpl,
-  interaction::SurfaceInteraction,
+  bsdf::mtl::MTL,
+  interaction::{Interaction, SurfaceInteraction},
+  scene::SceneError,
 };
-use quick_maths::{Ray3, Transform4};
-use std::{fmt::Debug, ptr::NonNull};
+use quick_maths::{Ray3, Transform4, Vec3};
+use std::fmt::Debug;
 
 /// Generic shape trait
 pub trait Shape: Debug + Bounded {
@@ -26,10 +35,93 @@ pub enum Variant {
   Plane(plane::Plane),
   Triangle(triangle::Triangle),
   TriangleList(triangle_list::IndexedTriangles),
-  /*
-  Plane,
-  BBox,
-  */
+  Cylinder(quadrics::Cylinder),
+  Disk(quadrics::Disk),
+  Cone(quadrics::Cone),
+  Paraboloid(quadrics::Paraboloid),
+  Hyperboloid(quadrics::Hyperboloid),
+  Cuboid(cuboid::Cuboid),
+  RoundedBox(cuboid::RoundedBox),
+  Sdf(sdf::SdfShape),
+  Csg(csg::Csg),
+  Heightfield(heightfield::Heightfield),
+  Curves(curve::Curves),
+  BezierPatches(patch::BezierPatches),
+}
+
+impl Variant {
+  /// Intersects a ray in the local space of this shape
+  pub fn intersect_ray(&self, r: &Ray3) -> Option<SurfaceInteraction> {
+    use Variant::*;
+    match self {
+      Sphere(s) => s.intersect_ray(r),
+      Plane(p) => p.intersect_ray(r),
+      Triangle(t) => t.intersect_ray(r),
+      TriangleList(t) => t.intersect_ray(r),
+      Cylinder(c) => c.intersect_ray(r),
+      Disk(d) => d.intersect_ray(r),
+      Cone(c) => c.intersect_ray(r),
+      Paraboloid(p) => p.intersect_ray(r),
+      Hyperboloid(h) => h.intersect_ray(r),
+      Cuboid(c) => c.intersect_ray(r),
+      RoundedBox(b) => b.intersect_ray(r),
+      Sdf(s) => s.intersect_ray(r),
+      Csg(c) => c.intersect_ray(r),
+      Heightfield(h) => h.intersect_ray(r),
+      Curves(c) => c.intersect_ray(r),
+      BezierPatches(p) => p.intersect_ray(r),
+    }
+  }
+  /// Returns the bounds of this shape in its local space
+  pub fn bounds(&self) -> Bounds3 {
+    use Variant::*;
+    match self {
+      Sphere(s) => s.bounds(),
+      Plane(p) => p.bounds(),
+      Triangle(t) => t.bounds(),
+      TriangleList(t) => t.bounds(),
+      Cylinder(c) => c.bounds(),
+      Disk(d) => d.bounds(),
+      Cone(c) => c.bounds(),
+      Paraboloid(p) => p.bounds(),
+      Hyperboloid(h) => h.bounds(),
+      Cuboid(c) => c.bounds(),
+      RoundedBox(b) => b.bounds(),
+      Sdf(s) => s.bounds(),
+      Csg(c) => c.bounds(),
+      Heightfield(h) => h.bounds(),
+      Curves(c) => c.bounds(),
+      BezierPatches(p) => p.bounds(),
+    }
+  }
+}
+
+/// Checks that a parameter of a shape is positive, which also rejects NaNs
+pub(crate) fn positive(shape: &str, param: &str, v: f32) -> Result<(), SceneError> {
+  if v > 0. {
+    Ok(())
+  } else {
+    Err(SceneError::invalid(format!(
+      "{} must have a positive {}, not {}",
+      shape, param, v
+    )))
+  }
+}
+
+/// Returns how far along a ray in world space a point on it is, since transforms may scale the
+/// ray.
+fn t_along(r: &Ray3, p: &Vec3) -> f32 { (*p - r.pos).dot(&r.dir) / r.dir.sqr_magn() }
+
+/// Transforms a local normal to world space. Normals transform by the inverse transpose, whose
+/// rows are the columns of from_world.
+fn normal_to_world(from_world: &Transform4, normal: &Vec3) -> Vec3 {
+  let col = |v: Vec3| from_world.apply_vec(&v).dot(normal);
+  Vec3::new(
+    col(Vec3::new(1., 0., 0.)),
+    col(Vec3::new(0., 1., 0.)),
+    col(Vec3::new(0., 0., 1.)),
+  )
+  .norm()
 }
 
 /// Intermediate shape representation with no bsdf
@@ -39,43 +131,100 @@ pub struct Geometry {
   variant: Variant,
 }
 
+impl Geometry {
+  /// Removes the materials loaded along with this shape, such as those of an OBJ's mtllib.
+  /// Material `i` of the shape's primitives is the `i-1`th of these, and 0 is its own bsdf.
+  pub fn take_materials(&mut self) -> Vec<MTL> {
+    match &mut self.variant {
+      Variant::TriangleList(t) => t.take_materials(),
+      _ => vec![],
+    }
+  }
+  /// Number of triangles in this shape, or None if it is not made of triangles
+  pub fn triangles(&self) -> Option<usize> {
+    match &self.variant {
+      Variant::Triangle(_) => Some(1),
+      Variant::TriangleList(t) => Some(t.len()),
+      _ => None,
+    }
+  }
+  /// Number of triangles in this shape which have no area
+  pub fn degenerate_triangles(&self) -> usize {
+    match &self.variant {
+      Variant::Triangle(t) if t.is_degenerate() => 1,
+      Variant::TriangleList(t) => t.degenerate(),
+      _ => 0,
+    }
+  }
+  /// Approximate number of bytes used by this shape
+  pub fn memory(&self) -> usize {
+    let buffers = match &self.variant {
+      Variant::TriangleList(t) => t.memory(),
+      _ => 0,
+    };
+    std::mem::size_of::<Self>() + buffers
+  }
+  /// Returns the bounds of this shape in world space
+  pub fn bounds(&self) -> Bounds3 { self.variant.bounds().transform(&self.to_world) }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Shapes {
   variant: Variant,
   to_world: Transform4,
-  /// Pointer into the list of non-null bsdfs
-  bsdf: NonNull<BSDFImpl>,
+  from_world: Transform4,
+  /// Whether to_world is not the identity, so that rays must be moved into local space
+  transformed: bool,
+  /// Index into the scene's bsdfs for each material of this shape
+  bsdfs: Vec<usize>,
 }
 
 impl Shapes {
-  pub fn new(si: Geometry, bsdf: &mut BSDFImpl) -> Self {
-    let bsdf = unsafe { NonNull::new_unchecked(bsdf) };
+  /// Creates a shape whose materials use some of the scene's bsdfs, which must not be empty
+  pub fn new(si: Geometry, bsdfs: Vec<usize>) -> Result<Self, SceneError> {
+    if bsdfs.is_empty() {
+      return Err(SceneError::invalid("Shapes require at least one bsdf"));
+    }
     let Geometry { to_world, variant } = si;
-    Self {
+    Ok(Self {
       variant,
+      from_world: to_world.inv(),
+      transformed: to_world != Transform4::identity(),
       to_world,
-      bsdf,
-    }
+      bsdfs,
+    })
   }
-  pub fn bsdf(&self) -> &BSDFImpl { unsafe { self.bsdf.as_ref() } }
 
+  /// Intersects a ray in world space with this shape, returning the interaction in world space
+  /// with its material as an index into the scene's bsdfs. Shapes without a transform are
+  /// intersected directly, as they were before shapes had transforms.
   pub fn intersect_ray(&self, r: &Ray3) -> Option<SurfaceInteraction> {
-    // TODO apply world transforms here to ray and then apply to transformation later
-    use Variant::*;
-    match &self.variant {
-      Sphere(s) => s.intersect_ray(r),
-      Plane(p) => p.intersect_ray(r),
-      Triangle(t) => t.intersect_ray(r),
-      TriangleList(t) => t.intersect_ray(r),
-    }
-  }
-  pub fn bounds(&self) -> Bounds3 {
-    use Variant::*;
-    match &self.variant {
-      Sphere(s) => s.bounds(),
-      Plane(p) => p.bounds(),
-      Triangle(t) => t.bounds(),
-      TriangleList(t) => t.bounds(),
+    let bsdf = |m: usize| self.bsdfs[m.min(self.bsdfs.len() - 1)];
+    if !self.transformed {
+      let mut si = self.variant.intersect_ray(r)?;
+      si.material = bsdf(si.material);
+      return Some(si);
     }
+    let local = self.from_world.apply_ray(r);
+    let SurfaceInteraction {
+      it,
+      normal,
+      uv,
+      material,
+      ..
+    } = self.variant.intersect_ray(&local)?;
+    let p = self.to_world.apply_point(&it.p);
+    Some(SurfaceInteraction {
+      it: Interaction {
+        t: t_along(r, &p),
+        p,
+      },
+      normal: normal_to_world(&self.from_world, &normal),
+      uv,
+      wi: r.dir.norm(),
+      material: bsdf(material),
+    })
   }
+  /// Returns the bounds of this shape in world space
+  pub fn bounds(&self) -> Bounds3 { self.variant.bounds().transform(&self.to_world) }
 }
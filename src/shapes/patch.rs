@@ -0,0 +1,370 @@
+//! Bicubic bezier patches, such as those which make up the Utah teapot.
+use super::{triangle_list::IndexedTriangles, Shape};
+use crate::{
+  bounds::{Bounded, Bounds3},
+  interaction::{Interaction, SurfaceInteraction},
+  two_d::spline::{CubicSurface, CUBIC_BEZIER_WEIGHTS},
+};
+use quick_maths::{Ray3, Vec2, Vec3};
+use std::{
+  fs::File,
+  io::{self, BufRead},
+  path::Path,
+};
+
+/// How many times the patch is split before refining with newton iteration
+const MAX_DEPTH: u32 = 5;
+/// Number of newton iterations to refine a hit
+const NEWTON_STEPS: usize = 8;
+/// Distance at which newton iteration is considered converged
+const NEWTON_EPS: f32 = 1e-5;
+
+/// Cubic bernstein polynomials and their derivatives
+fn bernstein(u: f32) -> ([f32; 4], [f32; 4]) {
+  let v = 1. - u;
+  ([v * v * v, 3. * u * v * v, 3. * u * u * v, u * u * u], [
+    -3. * v * v,
+    3. * v * v - 6. * u * v,
+    6. * u * v - 3. * u * u,
+    3. * u * u,
+  ])
+}
+
+/// Splits the control points of a bezier curve in half
+fn split_curve([p0, p1, p2, p3]: [Vec3; 4]) -> ([Vec3; 4], [Vec3; 4]) {
+  let mid = |a: Vec3, b: Vec3| (a + b) / 2.;
+  let (q0, q1, q2) = (mid(p0, p1), mid(p1, p2), mid(p2, p3));
+  let (r0, r1) = (mid(q0, q1), mid(q1, q2));
+  let s = mid(r0, r1);
+  ([p0, q0, r0, s], [s, r1, q2, p3])
+}
+
+type Net = [[Vec3; 4]; 4];
+
+fn transpose(n: &Net) -> Net {
+  let mut out = *n;
+  for (i, row) in n.iter().enumerate() {
+    for (j, &p) in row.iter().enumerate() {
+      out[j][i] = p;
+    }
+  }
+  out
+}
+
+/// Splits a control net in half along u
+fn split_u(n: &Net) -> (Net, Net) {
+  let (mut l, mut r) = (*n, *n);
+  for i in 0..4 {
+    let (a, b) = split_curve(n[i]);
+    l[i] = a;
+    r[i] = b;
+  }
+  (l, r)
+}
+
+fn net_bounds(n: &Net) -> Bounds3 {
+  n.iter()
+    .flat_map(|row| row.iter())
+    .fold(Bounds3::empty(n[0][0]), |acc, p| acc.union_vec(p))
+}
+
+/// Bicubic bezier patch
+#[derive(Debug, Clone, PartialEq)]
+pub struct BezierPatch {
+  surface: CubicSurface,
+}
+
+impl BezierPatch {
+  pub fn new(ctrls: Net) -> Self {
+    Self {
+      surface: CubicSurface::new(ctrls),
+    }
+  }
+  pub fn at(&self, u: f32, v: f32) -> Vec3 { self.surface.at((u, v), &CUBIC_BEZIER_WEIGHTS) }
+  /// Returns the partial derivatives of the surface along u and v
+  pub fn partials(&self, u: f32, v: f32) -> (Vec3, Vec3) {
+    let ((bu, dbu), (bv, dbv)) = (bernstein(u), bernstein(v));
+    let ctrls = self.surface.ctrls();
+    let mut du = Vec3::of(0.);
+    let mut dv = Vec3::of(0.);
+    for i in 0..4 {
+      for j in 0..4 {
+        du = du + ctrls[i][j] * (dbu[j] * bv[i]);
+        dv = dv + ctrls[i][j] * (bu[j] * dbv[i]);
+      }
+    }
+    (du, dv)
+  }
+  pub fn normal(&self, u: f32, v: f32) -> Vec3 {
+    let (du, dv) = self.partials(u, v);
+    let n = du.cross(&dv);
+    if n.sqr_magn() > 0. {
+      return n.norm();
+    }
+    // Along collapsed edges, such as the tip of the teapot's lid, one of the tangents is zero.
+    // The normal just inside of the patch converges to the one at the edge.
+    let inside = |x: f32| x + (0.5 - x) * 1e-3;
+    let (du, dv) = self.partials(inside(u), inside(v));
+    let n = du.cross(&dv);
+    if n.sqr_magn() > 0. {
+      n.norm()
+    } else {
+      // the whole patch is collapsed, so any normal is as good as another
+      Vec3::new(0., 0., 1.)
+    }
+  }
+  /// Refines an initial guess of (u, v, t) where the ray hits the surface
+  fn newton(&self, r: &Ray3, mut u: f32, mut v: f32, mut t: f32) -> Option<(f32, f32, f32)> {
+    for _ in 0..NEWTON_STEPS {
+      let err = self.at(u, v) - r.at(t);
+      if err.magn() < NEWTON_EPS {
+        return Some((u, v, t));
+      }
+      // Solve [du dv -dir] * delta = -err by Cramer's rule
+      let (a, b) = self.partials(u, v);
+      let c = -r.dir;
+      let det = a.dot(&b.cross(&c));
+      if det.abs() < 1e-12 {
+        return None;
+      }
+      let rhs = -err;
+      u += rhs.dot(&b.cross(&c)) / det;
+      v += a.dot(&rhs.cross(&c)) / det;
+      t += a.dot(&b.cross(&rhs)) / det;
+    }
+    Some((u, v, t)).filter(|&(u, v, t)| (self.at(u, v) - r.at(t)).magn() < NEWTON_EPS * 10.)
+  }
+  /// Recursively subdivides the control net, then refines candidate hits with newton iteration
+  fn recurse(
+    &self,
+    r: &Ray3,
+    net: &Net,
+    (u0, u1): (f32, f32),
+    (v0, v1): (f32, f32),
+    depth: u32,
+    best: &mut Option<(f32, f32, f32)>,
+  ) {
+    let t_max = best.map(|(_, _, t)| t).unwrap_or(f32::INFINITY);
+    let (t_near, t_far) = match net_bounds(net).ray_range(r) {
+      None => return,
+      Some(range) => range,
+    };
+    if t_near > t_max {
+      return;
+    }
+    if depth == 0 {
+      let guess = ((u0 + u1) / 2., (v0 + v1) / 2., (t_near + t_far) / 2.);
+      let hit = match self.newton(r, guess.0, guess.1, guess.2) {
+        None => return,
+        Some(hit) => hit,
+      };
+      let (u, v, t) = hit;
+      let slack = 1e-4;
+      let in_range = u >= u0 - slack && u <= u1 + slack && v >= v0 - slack && v <= v1 + slack;
+      if in_range && t > crate::EPS && t < t_max {
+        *best = Some((u.max(0.).min(1.), v.max(0.).min(1.), t));
+      }
+      return;
+    }
+    // alternate the direction of splitting
+    let (um, vm) = ((u0 + u1) / 2., (v0 + v1) / 2.);
+    if depth % 2 == 0 {
+      let (l, rt) = split_u(net);
+      self.recurse(r, &l, (u0, um), (v0, v1), depth - 1, best);
+      self.recurse(r, &rt, (um, u1), (v0, v1), depth - 1, best);
+    } else {
+      let (l, rt) = split_u(&transpose(net));
+      self.recurse(r, &transpose(&l), (u0, u1), (v0, vm), depth - 1, best);
+      self.recurse(r, &transpose(&rt), (u0, u1), (vm, v1), depth - 1, best);
+    }
+  }
+  /// Tessellates this patch into a grid of res x res quads, appending to vertices and faces
+  fn tessellate_into(
+    &self,
+    res: u32,
+    verts: &mut Vec<Vec3>,
+    norms: &mut Vec<Vec3>,
+    faces: &mut Vec<Vec3<u32>>,
+  ) {
+    let start = verts.len() as u32;
+    let step = (res as f32).recip();
+    for j in 0..=res {
+      for i in 0..=res {
+        let (u, v) = (i as f32 * step, j as f32 * step);
+        verts.push(self.at(u, v));
+        norms.push(self.normal(u, v));
+      }
+    }
+    let idx = |i: u32, j: u32| start + j * (res + 1) + i;
+    for j in 0..res {
+      for i in 0..res {
+        faces.push(Vec3::new(idx(i, j), idx(i + 1, j), idx(i + 1, j + 1)));
+        faces.push(Vec3::new(idx(i, j), idx(i + 1, j + 1), idx(i, j + 1)));
+      }
+    }
+  }
+}
+
+impl Shape for BezierPatch {
+  fn intersect_ray(&self, r: &Ray3) -> Option<SurfaceInteraction> {
+    let mut best = None;
+    self.recurse(
+      r,
+      self.surface.ctrls(),
+      (0., 1.),
+      (0., 1.),
+      MAX_DEPTH * 2,
+      &mut best,
+    );
+    let (u, v, t) = best?;
+    Some(SurfaceInteraction {
+      it: Interaction { t, p: r.at(t) },
+      normal: self.normal(u, v),
+      uv: Vec2::new(u, v),
+      wi: r.dir.norm(),
+      material: 0,
+    })
+  }
+}
+
+impl Bounded for BezierPatch {
+  fn bounds(&self) -> Bounds3 { net_bounds(self.surface.ctrls()) }
+}
+
+/// Collection of bezier patches
+#[derive(Debug, PartialEq)]
+pub struct BezierPatches {
+  patches: Vec<(BezierPatch, Bounds3)>,
+  bounds: Bounds3,
+}
+
+impl BezierPatches {
+  /// Creates a collection of patches, which must not be empty so that it has bounds
+  pub fn new(patches: Vec<BezierPatch>) -> io::Result<Self> {
+    let patches = patches
+      .into_iter()
+      .map(|p| {
+        let b = p.bounds();
+        (p, b)
+      })
+      .collect::<Vec<_>>();
+    let first = match patches.first() {
+      None =>
+        return Err(io::Error::new(
+          io::ErrorKind::InvalidData,
+          "Bezier patches need at least one patch",
+        )),
+      Some(&(_, b)) => b,
+    };
+    let bounds = patches.iter().fold(first, |acc, (_, b)| acc.union(b));
+    Ok(Self { patches, bounds })
+  }
+  /// Tessellates every patch into res x res quads
+  pub fn tessellate(&self, src: String, res: u32) -> IndexedTriangles {
+    let (mut verts, mut norms, mut faces) = (vec![], vec![], vec![]);
+    for (p, _) in &self.patches {
+      p.tessellate_into(res.max(1), &mut verts, &mut norms, &mut faces);
+    }
+    IndexedTriangles::new(src, verts, norms, faces)
+  }
+}
+
+impl Shape for BezierPatches {
+  fn intersect_ray(&self, r: &Ray3) -> Option<SurfaceInteraction> {
+    self
+      .patches
+      .iter()
+      .filter(|(_, b)| b.intersect_ray(r))
+      .filter_map(|(p, _)| p.intersect_ray(r))
+      .min_by(|a, b| a.it.closer(&b.it))
+  }
+}
+
+impl Bounded for BezierPatches {
+  fn bounds(&self) -> Bounds3 { self.bounds }
+}
+
+/// Reads patches in the .bpt format, which is the number of patches followed by each patch as
+/// its degree "3 3" and then 16 lines of control points.
+pub fn from_bpt(p: impl AsRef<Path>) -> io::Result<BezierPatches> {
+  let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+  let buf = io::BufReader::new(File::open(p)?);
+  let mut lines = buf
+    .lines()
+    .filter(|l| l.as_ref().map_or(true, |l| !l.trim().is_empty()));
+  let mut next_nums = || -> io::Result<Vec<f32>> {
+    let line = lines
+      .next()
+      .ok_or_else(|| invalid("Unexpected end of file"))??;
+    line
+      .split_whitespace()
+      .map(|v| v.parse::<f32>().map_err(|_| invalid("Expected number")))
+      .collect()
+  };
+  let count = match next_nums()?.as_slice() {
+    &[n] => n as usize,
+    _ => return Err(invalid("Expected number of patches")),
+  };
+  let mut patches = Vec::with_capacity(count);
+  for _ in 0..count {
+    match next_nums()?.as_slice() {
+      &[du, dv] if du as u32 == 3 && dv as u32 == 3 => (),
+      _ => return Err(invalid("Only bicubic patches are supported")),
+    }
+    let mut net = [[Vec3::of(0.); 4]; 4];
+    for row in net.iter_mut() {
+      for p in row.iter_mut() {
+        *p = match next_nums()?.as_slice() {
+          &[x, y, z] => Vec3::new(x, y, z),
+          _ => return Err(invalid("Expected control point")),
+        };
+      }
+    }
+    patches.push(BezierPatch::new(net));
+  }
+  BezierPatches::new(patches)
+}
+
+#[cfg(test)]
+mod test_patch {
+  use super::*;
+  #[test]
+  fn empty_patches() {
+    assert!(BezierPatches::new(vec![]).is_err());
+  }
+  #[test]
+  fn flat_patch() {
+    let mut net = [[Vec3::of(0.); 4]; 4];
+    for (j, row) in net.iter_mut().enumerate() {
+      for (i, p) in row.iter_mut().enumerate() {
+        *p = Vec3::new(i as f32 / 3., j as f32 / 3., 0.);
+      }
+    }
+    let patch = BezierPatch::new(net);
+    let r = Ray3::new(Vec3::new(0.25, 0.75, -1.), Vec3::new(0., 0., 1.));
+    let si = patch.intersect_ray(&r).unwrap();
+    assert!((si.it.t - 1.).abs() < 1e-4);
+    assert!((si.uv - Vec2::new(0.25, 0.75)).magn() < 1e-3);
+    assert!((si.normal.z().abs() - 1.).abs() < 1e-4);
+    let mesh = BezierPatches::new(vec![patch])
+      .unwrap()
+      .tessellate(String::from("flat"), 4);
+    assert_eq!(mesh.len(), 32);
+  }
+  #[test]
+  fn collapsed_edge_normal() {
+    // a flat fan whose first row of control points is a single point
+    let mut net = [[Vec3::of(0.); 4]; 4];
+    for (j, row) in net.iter_mut().enumerate() {
+      for (i, p) in row.iter_mut().enumerate() {
+        let v = j as f32 / 3.;
+        *p = Vec3::new((i as f32 / 3. - 0.5) * v, v, 0.);
+      }
+    }
+    let patch = BezierPatch::new(net);
+    for &u in &[0., 0.5, 1.] {
+      let n = patch.normal(u, 0.);
+      assert!((n.z().abs() - 1.).abs() < 1e-4, "{:?} at {}", n, u);
+    }
+  }
+}
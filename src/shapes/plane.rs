@@ -63,6 +63,7 @@ impl Shape for Plane {
       normal: self.normal,
       uv: self.uv(&p),
       wi: r.dir,
+      material: 0,
     })
   }
 }
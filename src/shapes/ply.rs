@@ -0,0 +1,340 @@
+use super::triangle_list::IndexedTriangles;
+use crate::{scene::SceneError, utils::triangulate};
+use quick_maths::{Vec2, Vec3};
+use std::{
+  fs::File,
+  io::{self, BufRead, Read},
+  path::Path,
+};
+
+fn invalid(msg: String) -> io::Error { io::Error::new(io::ErrorKind::InvalidData, msg) }
+
+/// How the body of a PLY file is encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+  Ascii,
+  BinaryLittleEndian,
+  BinaryBigEndian,
+}
+
+/// Scalar types which properties can have
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scalar {
+  I8,
+  U8,
+  I16,
+  U16,
+  I32,
+  U32,
+  F32,
+  F64,
+}
+
+impl Scalar {
+  fn from_name(s: &str) -> io::Result<Self> {
+    use Scalar::*;
+    Ok(match s {
+      "char" | "int8" => I8,
+      "uchar" | "uint8" => U8,
+      "short" | "int16" => I16,
+      "ushort" | "uint16" => U16,
+      "int" | "int32" => I32,
+      "uint" | "uint32" => U32,
+      "float" | "float32" => F32,
+      "double" | "float64" => F64,
+      _ => return Err(invalid(format!("Unknown PLY property type {}", s))),
+    })
+  }
+  fn size(self) -> usize {
+    use Scalar::*;
+    match self {
+      I8 | U8 => 1,
+      I16 | U16 => 2,
+      I32 | U32 | F32 => 4,
+      F64 => 8,
+    }
+  }
+  fn is_float(self) -> bool { self == Scalar::F32 || self == Scalar::F64 }
+}
+
+#[derive(Debug)]
+enum Property {
+  Scalar(Scalar, String),
+  List {
+    count: Scalar,
+    item: Scalar,
+    name: String,
+  },
+}
+
+#[derive(Debug)]
+struct Element {
+  name: String,
+  count: usize,
+  props: Vec<Property>,
+}
+
+/// The body of a PLY file after its header
+enum Body {
+  Ascii(std::vec::IntoIter<f64>),
+  Binary {
+    bytes: Vec<u8>,
+    pos: usize,
+    big_endian: bool,
+  },
+}
+
+impl Body {
+  fn read(&mut self, s: Scalar) -> io::Result<f64> {
+    let (bytes, pos, big_endian) = match self {
+      Body::Ascii(vals) => return vals.next().ok_or_else(|| invalid("PLY ended early".into())),
+      Body::Binary {
+        bytes,
+        pos,
+        big_endian,
+      } => (bytes, pos, *big_endian),
+    };
+    let n = s.size();
+    if *pos + n > bytes.len() {
+      return Err(invalid("PLY ended early".into()));
+    }
+    let mut b = [0; 8];
+    b[..n].copy_from_slice(&bytes[*pos..*pos + n]);
+    *pos += n;
+    if big_endian {
+      b[..n].reverse();
+    }
+    use Scalar::*;
+    Ok(match s {
+      I8 => b[0] as i8 as f64,
+      U8 => b[0] as f64,
+      I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+      U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+      I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+      U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+      F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+      F64 => f64::from_le_bytes(b),
+    })
+  }
+}
+
+/// Reads the header of a PLY file, leaving the reader at the start of the body
+fn parse_header(buf: &mut impl BufRead) -> io::Result<(Format, Vec<Element>)> {
+  let mut format = None;
+  let mut elements: Vec<Element> = vec![];
+  let mut line = vec![];
+  let mut first = true;
+  loop {
+    line.clear();
+    if buf.read_until(b'\n', &mut line)? == 0 {
+      return Err(invalid("PLY header is missing end_header".into()));
+    }
+    let line = String::from_utf8_lossy(&line);
+    let parts = line.split_whitespace().collect::<Vec<_>>();
+    if std::mem::replace(&mut first, false) {
+      if parts != ["ply"] {
+        return Err(invalid("PLY files must start with ply".into()));
+      }
+      continue;
+    }
+    match parts.as_slice() {
+      [] | ["comment", ..] | ["obj_info", ..] => (),
+      ["format", f, _version] =>
+        format = Some(match *f {
+          "ascii" => Format::Ascii,
+          "binary_little_endian" => Format::BinaryLittleEndian,
+          "binary_big_endian" => Format::BinaryBigEndian,
+          _ => return Err(invalid(format!("Unknown PLY format {}", f))),
+        }),
+      ["element", name, count] => elements.push(Element {
+        name: name.to_string(),
+        count: count
+          .parse()
+          .map_err(|_| invalid(format!("Invalid element count {}", count)))?,
+        props: vec![],
+      }),
+      ["property", "list", count, item, name] => elements
+        .last_mut()
+        .ok_or_else(|| invalid("PLY property before any element".into()))?
+        .props
+        .push(Property::List {
+          count: Scalar::from_name(count)?,
+          item: Scalar::from_name(item)?,
+          name: name.to_string(),
+        }),
+      ["property", kind, name] => elements
+        .last_mut()
+        .ok_or_else(|| invalid("PLY property before any element".into()))?
+        .props
+        .push(Property::Scalar(Scalar::from_name(kind)?, name.to_string())),
+      ["end_header"] => break,
+      _ => return Err(invalid(format!("Unknown line in PLY header: {:?}", line))),
+    }
+  }
+  let format = format.ok_or_else(|| invalid("PLY header is missing its format".into()))?;
+  Ok((format, elements))
+}
+
+/// Reads an ASCII or binary PLY file with optional vertex normals, texture coordinates and
+/// colors. Polygonal faces are triangulated. Colors are kept on the mesh but are not yet used
+/// when shading it.
+pub fn from_ply(p: impl AsRef<Path>) -> Result<IndexedTriangles, SceneError> {
+  let p = p.as_ref();
+  let f = File::open(p).map_err(|e| SceneError::io(p, e))?;
+  parse(p.to_string_lossy().into_owned(), io::BufReader::new(f)).map_err(|e| SceneError::io(p, e))
+}
+
+fn parse(src: String, mut buf: impl BufRead) -> io::Result<IndexedTriangles> {
+  let (format, elements) = parse_header(&mut buf)?;
+  let mut rest = vec![];
+  buf.read_to_end(&mut rest)?;
+  let mut body = match format {
+    Format::Ascii => Body::Ascii(
+      String::from_utf8_lossy(&rest)
+        .split_whitespace()
+        .map(|v| {
+          v.parse()
+            .map_err(|_| invalid(format!("Invalid PLY value {}", v)))
+        })
+        .collect::<io::Result<Vec<_>>>()?
+        .into_iter(),
+    ),
+    _ => Body::Binary {
+      bytes: rest,
+      pos: 0,
+      big_endian: format == Format::BinaryBigEndian,
+    },
+  };
+  let (mut verts, mut norms, mut uvs, mut colors, mut faces) =
+    (vec![], vec![], vec![], vec![], vec![]);
+  for el in &elements {
+    for _ in 0..el.count {
+      let mut pos = Vec3::of(0.);
+      let (mut n, mut uv, mut color) = (None, None, None);
+      for prop in &el.props {
+        match prop {
+          Property::List { count, item, name } => {
+            let len = body.read(*count)? as usize;
+            let vals = (0..len)
+              .map(|_| body.read(*item))
+              .collect::<io::Result<Vec<_>>>()?;
+            let is_face = name == "vertex_indices" || name == "vertex_index";
+            if el.name == "face" && is_face && len >= 3 {
+              let idxs = vals
+                .into_iter()
+                .map(|i| {
+                  if !(0. ..=f64::from(u32::MAX)).contains(&i) || i.fract() > 0. {
+                    return Err(invalid(format!("Invalid PLY vertex index {}", i)));
+                  }
+                  Ok(i as u32)
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+              faces.extend(triangulate(idxs));
+            }
+          },
+          &Property::Scalar(kind, ref name) => {
+            let v = body.read(kind)? as f32;
+            if el.name != "vertex" {
+              continue;
+            }
+            // integer colors are in [0, 255]
+            let channel = || if kind.is_float() { v } else { v / 255. };
+            match name.as_str() {
+              "x" => pos[0] = v,
+              "y" => pos[1] = v,
+              "z" => pos[2] = v,
+              "nx" => n.get_or_insert(Vec3::of(0.))[0] = v,
+              "ny" => n.get_or_insert(Vec3::of(0.))[1] = v,
+              "nz" => n.get_or_insert(Vec3::of(0.))[2] = v,
+              "u" | "s" | "texture_u" | "texture_s" => uv.get_or_insert(Vec2::of(0.))[0] = v,
+              "v" | "t" | "texture_v" | "texture_t" => uv.get_or_insert(Vec2::of(0.))[1] = v,
+              "red" => color.get_or_insert(Vec3::of(0.))[0] = channel(),
+              "green" => color.get_or_insert(Vec3::of(0.))[1] = channel(),
+              "blue" => color.get_or_insert(Vec3::of(0.))[2] = channel(),
+              _ => (),
+            }
+          },
+        }
+      }
+      if el.name == "vertex" {
+        verts.push(pos);
+        norms.extend(n);
+        uvs.extend(uv);
+        colors.extend(color);
+      }
+    }
+  }
+  if let Some(&max) = faces.iter().flat_map(|f| f.0.iter()).max() {
+    if max as usize >= verts.len() {
+      return Err(invalid(format!(
+        "PLY face refers to missing vertex {}",
+        max
+      )));
+    }
+  }
+  // attributes are only kept if every vertex has them
+  let all = |n: usize| n == verts.len();
+  if !all(norms.len()) {
+    norms.clear();
+  }
+  if !all(uvs.len()) {
+    uvs.clear();
+  }
+  if !all(colors.len()) {
+    colors.clear();
+  }
+  let mut mesh = IndexedTriangles::new(src, verts, norms, faces);
+  if !uvs.is_empty() {
+    mesh = mesh.with_uvs(uvs);
+  }
+  Ok(mesh.with_colors(colors))
+}
+
+#[cfg(test)]
+mod test_ply {
+  use super::*;
+  #[test]
+  fn ascii_and_binary_agree() {
+    let header = |format: &str| {
+      format!(
+        "ply\nformat {} 1.0\ncomment test\nelement vertex 4\nproperty float x\n\
+         property float y\nproperty float z\nproperty uchar red\nproperty uchar green\n\
+         property uchar blue\nelement face 1\nproperty list uchar int vertex_indices\n\
+         end_header\n",
+        format
+      )
+    };
+    let verts = [[0., 0., 0.], [1., 0., 0.], [1., 1., 0.], [0., 1., 0.]];
+    let mut ascii = header("ascii");
+    for v in &verts {
+      ascii += &format!("{} {} {} 255 0 0\n", v[0], v[1], v[2]);
+    }
+    ascii += "4 0 1 2 3\n";
+    let mut binary = header("binary_big_endian").into_bytes();
+    for v in &verts {
+      for &c in v {
+        binary.extend_from_slice(&(c as f32).to_be_bytes());
+      }
+      binary.extend_from_slice(&[255, 0, 0]);
+    }
+    binary.push(4);
+    for i in 0..4i32 {
+      binary.extend_from_slice(&i.to_be_bytes());
+    }
+    let a = parse(String::from("a"), io::Cursor::new(ascii)).expect("Failed to parse ascii");
+    let b = parse(String::from("b"), io::Cursor::new(binary)).expect("Failed to parse binary");
+    assert_eq!(a.len(), 2);
+    assert_eq!(b.len(), 2);
+    assert_eq!(a.vertex_colors(), b.vertex_colors());
+    assert_eq!(a.vertex_colors()[0], Vec3::new(1., 0., 0.));
+    assert_eq!(a.iter().collect::<Vec<_>>(), b.iter().collect::<Vec<_>>());
+  }
+  #[test]
+  fn negative_indices() {
+    let ply = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+               property float z\nelement face 1\nproperty list uchar int vertex_indices\n\
+               end_header\n0 0 0\n1 0 0\n0 1 0\n3 0 -1 2\n";
+    let err = parse(String::from("a"), io::Cursor::new(ply)).unwrap_err();
+    assert_eq!(err.to_string(), "Invalid PLY vertex index -1");
+  }
+}
@@ -0,0 +1,431 @@
+//! Quadric surfaces of revolution around the z-axis in object space. They can be placed in the
+//! world with the transform of their shape.
+use super::{
+  csg::{Crossing, Solid, Span},
+  positive, Shape,
+};
+use crate::{
+  bounds::{Bounded, Bounds3},
+  interaction::{Interaction, SurfaceInteraction},
+  scene::SceneError,
+  utils::quad_solve,
+};
+use quick_maths::{Ray3, Vec2, Vec3};
+use std::f32::consts::PI;
+
+/// Returns the angle of a point around the z-axis in [0, 2 pi)
+fn phi(p: &Vec3) -> f32 {
+  let phi = p.y().atan2(p.x());
+  if phi < 0. {
+    phi + 2. * PI
+  } else {
+    phi
+  }
+}
+
+/// Returns the closest non-negative root of a quadric whose point along the ray is valid
+fn closest_root(
+  r: &Ray3,
+  roots: Option<(f32, f32)>,
+  valid: impl Fn(&Vec3) -> bool,
+) -> Option<(f32, Vec3)> {
+  let (t0, t1) = roots?;
+  let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+  [t0, t1]
+    .iter()
+    .filter(|t| t.is_sign_positive())
+    .map(|&t| (t, r.at(t)))
+    .find(|(_, p)| valid(p))
+}
+
+fn surface_interaction(r: &Ray3, t: f32, p: Vec3, normal: Vec3, uv: Vec2) -> SurfaceInteraction {
+  SurfaceInteraction {
+    it: Interaction { t, p },
+    normal: normal.norm(),
+    uv,
+    wi: r.dir.norm(),
+    material: 0,
+  }
+}
+
+/// Open cylinder of some radius between two heights
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cylinder {
+  radius: f32,
+  z_min: f32,
+  z_max: f32,
+  /// Maximum angle swept around the z-axis in radians
+  phi_max: f32,
+}
+
+/// Checks that a quadric spans some height, since its uvs are spread along it
+fn z_range(shape: &str, z_min: f32, z_max: f32) -> Result<(), SceneError> {
+  if z_max > z_min {
+    Ok(())
+  } else {
+    Err(SceneError::invalid(format!(
+      "{} must have z_min < z_max, not {} and {}",
+      shape, z_min, z_max
+    )))
+  }
+}
+
+impl Cylinder {
+  pub fn new(radius: f32, z_min: f32, z_max: f32, phi_max: f32) -> Result<Self, SceneError> {
+    positive("Cylinder", "radius", radius)?;
+    z_range("Cylinder", z_min, z_max)?;
+    Ok(Self {
+      radius,
+      z_min,
+      z_max,
+      phi_max: phi_max.max(0.).min(2. * PI),
+    })
+  }
+}
+
+impl Shape for Cylinder {
+  fn intersect_ray(&self, r: &Ray3) -> Option<SurfaceInteraction> {
+    let (o, d) = (&r.pos, &r.dir);
+    let a = d.x() * d.x() + d.y() * d.y();
+    let b = 2. * (d.x() * o.x() + d.y() * o.y());
+    let c = o.x() * o.x() + o.y() * o.y() - self.radius * self.radius;
+    let valid = |p: &Vec3| p.z() >= self.z_min && p.z() <= self.z_max && phi(p) <= self.phi_max;
+    let (t, p) = closest_root(r, quad_solve(a, b, c), valid)?;
+    let uv = Vec2::new(
+      phi(&p) / self.phi_max,
+      (p.z() - self.z_min) / (self.z_max - self.z_min),
+    );
+    Some(surface_interaction(
+      r,
+      t,
+      p,
+      Vec3::new(p.x(), p.y(), 0.),
+      uv,
+    ))
+  }
+}
+
+impl Bounded for Cylinder {
+  fn bounds(&self) -> Bounds3 {
+    let r = self.radius;
+    Bounds3::new(Vec3::new(-r, -r, self.z_min), Vec3::new(r, r, self.z_max))
+  }
+}
+
+/// Cylinders used as solids are capped at both ends and swept all the way around
+impl Solid for Cylinder {
+  fn spans(&self, r: &Ray3) -> Vec<Span> {
+    let (o, d) = (&r.pos, &r.dir);
+    let a = d.x() * d.x() + d.y() * d.y();
+    let c = o.x() * o.x() + o.y() * o.y() - self.radius * self.radius;
+    let side = |t: f32| {
+      let p = r.at(t);
+      (t, Vec3::new(p.x(), p.y(), 0.))
+    };
+    let (mut enter, mut exit) = if a == 0. {
+      // parallel to the axis
+      if c > 0. {
+        return vec![];
+      }
+      let n = Vec3::new(0., 0., 1.);
+      ((f32::NEG_INFINITY, n), (f32::INFINITY, n))
+    } else {
+      let b = 2. * (d.x() * o.x() + d.y() * o.y());
+      match quad_solve(a, b, c) {
+        None => return vec![],
+        Some((t0, t1)) => (side(t0.min(t1)), side(t0.max(t1))),
+      }
+    };
+    if d.z() == 0. {
+      if o.z() < self.z_min || o.z() > self.z_max {
+        return vec![];
+      }
+    } else {
+      let cap = |z: f32, n: f32| ((z - o.z()) / d.z(), Vec3::new(0., 0., n));
+      let (lo, hi) = (cap(self.z_min, -1.), cap(self.z_max, 1.));
+      let (near, far) = if lo.0 < hi.0 { (lo, hi) } else { (hi, lo) };
+      if near.0 > enter.0 {
+        enter = near;
+      }
+      if far.0 < exit.0 {
+        exit = far;
+      }
+    }
+    if enter.0 > exit.0 {
+      return vec![];
+    }
+    let crossing = |(t, n): (f32, Vec3)| Crossing::new(t, n.norm(), Vec2::new(0., 0.));
+    vec![Span::new(r, crossing(enter), crossing(exit))]
+  }
+}
+
+/// Flat disk facing +z at some height, which is an annulus if it has an inner radius
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disk {
+  height: f32,
+  radius: f32,
+  inner_radius: f32,
+  /// Maximum angle swept around the z-axis in radians
+  phi_max: f32,
+}
+
+impl Disk {
+  pub fn new(
+    height: f32,
+    radius: f32,
+    inner_radius: f32,
+    phi_max: f32,
+  ) -> Result<Self, SceneError> {
+    positive("Disk", "radius", radius)?;
+    if !(0. ..radius).contains(&inner_radius) {
+      let msg = format!(
+        "Disk must have 0 <= inner radius < radius, not {}",
+        inner_radius
+      );
+      return Err(SceneError::invalid(msg));
+    }
+    Ok(Self {
+      height,
+      radius,
+      inner_radius,
+      phi_max: phi_max.max(0.).min(2. * PI),
+    })
+  }
+}
+
+impl Shape for Disk {
+  fn intersect_ray(&self, r: &Ray3) -> Option<SurfaceInteraction> {
+    if r.dir.z() == 0. {
+      return None;
+    }
+    let t = (self.height - r.pos.z()) / r.dir.z();
+    if t.is_sign_negative() {
+      return None;
+    }
+    let p = r.at(t);
+    let dist_sqr = p.x() * p.x() + p.y() * p.y();
+    if dist_sqr > self.radius * self.radius || dist_sqr < self.inner_radius * self.inner_radius {
+      return None;
+    }
+    let phi = phi(&p);
+    if phi > self.phi_max {
+      return None;
+    }
+    let uv = Vec2::new(
+      phi / self.phi_max,
+      (self.radius - dist_sqr.sqrt()) / (self.radius - self.inner_radius),
+    );
+    Some(surface_interaction(r, t, p, Vec3::new(0., 0., 1.), uv))
+  }
+}
+
+impl Bounded for Disk {
+  fn bounds(&self) -> Bounds3 {
+    let r = self.radius;
+    // Slightly thickened so that the bounds are not degenerate
+    Bounds3::new(
+      Vec3::new(-r, -r, self.height - 1e-5),
+      Vec3::new(r, r, self.height + 1e-5),
+    )
+  }
+}
+
+/// Cone with its base of some radius at z = 0 and its tip at some height
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cone {
+  radius: f32,
+  height: f32,
+  /// Maximum angle swept around the z-axis in radians
+  phi_max: f32,
+}
+
+impl Cone {
+  pub fn new(radius: f32, height: f32, phi_max: f32) -> Result<Self, SceneError> {
+    positive("Cone", "radius", radius)?;
+    positive("Cone", "height", height)?;
+    Ok(Self {
+      radius,
+      height,
+      phi_max: phi_max.max(0.).min(2. * PI),
+    })
+  }
+}
+
+impl Shape for Cone {
+  fn intersect_ray(&self, r: &Ray3) -> Option<SurfaceInteraction> {
+    let (o, d) = (&r.pos, &r.dir);
+    let k = (self.radius / self.height) * (self.radius / self.height);
+    let oz = o.z() - self.height;
+    let a = d.x() * d.x() + d.y() * d.y() - k * d.z() * d.z();
+    let b = 2. * (d.x() * o.x() + d.y() * o.y() - k * d.z() * oz);
+    let c = o.x() * o.x() + o.y() * o.y() - k * oz * oz;
+    let valid = |p: &Vec3| p.z() >= 0. && p.z() <= self.height && phi(p) <= self.phi_max;
+    let (t, p) = closest_root(r, quad_solve(a, b, c), valid)?;
+    let uv = Vec2::new(phi(&p) / self.phi_max, p.z() / self.height);
+    let normal = Vec3::new(p.x(), p.y(), -k * (p.z() - self.height));
+    Some(surface_interaction(r, t, p, normal, uv))
+  }
+}
+
+impl Bounded for Cone {
+  fn bounds(&self) -> Bounds3 {
+    let r = self.radius;
+    Bounds3::new(Vec3::new(-r, -r, 0.), Vec3::new(r, r, self.height))
+  }
+}
+
+/// Paraboloid opening towards +z with its tip at the origin, reaching some radius at z_max.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Paraboloid {
+  radius: f32,
+  z_min: f32,
+  z_max: f32,
+  /// Maximum angle swept around the z-axis in radians
+  phi_max: f32,
+}
+
+impl Paraboloid {
+  pub fn new(radius: f32, z_min: f32, z_max: f32, phi_max: f32) -> Result<Self, SceneError> {
+    positive("Paraboloid", "radius", radius)?;
+    positive("Paraboloid", "z_max", z_max)?;
+    let z_min = z_min.max(0.);
+    z_range("Paraboloid", z_min, z_max)?;
+    Ok(Self {
+      radius,
+      z_min,
+      z_max,
+      phi_max: phi_max.max(0.).min(2. * PI),
+    })
+  }
+}
+
+impl Shape for Paraboloid {
+  fn intersect_ray(&self, r: &Ray3) -> Option<SurfaceInteraction> {
+    let (o, d) = (&r.pos, &r.dir);
+    let k = self.z_max / (self.radius * self.radius);
+    let a = k * (d.x() * d.x() + d.y() * d.y());
+    let b = 2. * k * (d.x() * o.x() + d.y() * o.y()) - d.z();
+    let c = k * (o.x() * o.x() + o.y() * o.y()) - o.z();
+    let valid = |p: &Vec3| p.z() >= self.z_min && p.z() <= self.z_max && phi(p) <= self.phi_max;
+    let (t, p) = closest_root(r, quad_solve(a, b, c), valid)?;
+    let uv = Vec2::new(
+      phi(&p) / self.phi_max,
+      (p.z() - self.z_min) / (self.z_max - self.z_min),
+    );
+    let normal = Vec3::new(2. * k * p.x(), 2. * k * p.y(), -1.);
+    Some(surface_interaction(r, t, p, normal, uv))
+  }
+}
+
+impl Bounded for Paraboloid {
+  fn bounds(&self) -> Bounds3 {
+    let r = self.radius;
+    Bounds3::new(Vec3::new(-r, -r, self.z_min), Vec3::new(r, r, self.z_max))
+  }
+}
+
+/// Hyperboloid of one sheet, x^2 + y^2 = radius^2 + (slope * z)^2, where radius is the radius
+/// of its waist at z = 0 and slope is that of its asymptotes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hyperboloid {
+  radius: f32,
+  slope: f32,
+  z_min: f32,
+  z_max: f32,
+  /// Maximum angle swept around the z-axis in radians
+  phi_max: f32,
+}
+
+impl Hyperboloid {
+  pub fn new(
+    radius: f32,
+    slope: f32,
+    z_min: f32,
+    z_max: f32,
+    phi_max: f32,
+  ) -> Result<Self, SceneError> {
+    positive("Hyperboloid", "radius", radius)?;
+    z_range("Hyperboloid", z_min, z_max)?;
+    Ok(Self {
+      radius,
+      slope,
+      z_min,
+      z_max,
+      phi_max: phi_max.max(0.).min(2. * PI),
+    })
+  }
+}
+
+impl Shape for Hyperboloid {
+  fn intersect_ray(&self, r: &Ray3) -> Option<SurfaceInteraction> {
+    let (o, d) = (&r.pos, &r.dir);
+    let s2 = self.slope * self.slope;
+    let a = d.x() * d.x() + d.y() * d.y() - s2 * d.z() * d.z();
+    let b = 2. * (d.x() * o.x() + d.y() * o.y() - s2 * d.z() * o.z());
+    let c = o.x() * o.x() + o.y() * o.y() - s2 * o.z() * o.z() - self.radius * self.radius;
+    let valid = |p: &Vec3| p.z() >= self.z_min && p.z() <= self.z_max && phi(p) <= self.phi_max;
+    let (t, p) = closest_root(r, quad_solve(a, b, c), valid)?;
+    let uv = Vec2::new(
+      phi(&p) / self.phi_max,
+      (p.z() - self.z_min) / (self.z_max - self.z_min),
+    );
+    let normal = Vec3::new(p.x(), p.y(), -s2 * p.z());
+    Some(surface_interaction(r, t, p, normal, uv))
+  }
+}
+
+impl Bounded for Hyperboloid {
+  fn bounds(&self) -> Bounds3 {
+    let z = self.z_min.abs().max(self.z_max.abs());
+    let r = (self.radius * self.radius + self.slope * self.slope * z * z).sqrt();
+    Bounds3::new(Vec3::new(-r, -r, self.z_min), Vec3::new(r, r, self.z_max))
+  }
+}
+
+#[cfg(test)]
+mod test_quadrics {
+  use super::*;
+  #[test]
+  fn cylinder_partial_sweep() {
+    let c = Cylinder::new(1., -1., 1., PI).unwrap();
+    // Front half of the sweep is hit from outside
+    let r = Ray3::new(Vec3::new(0., 5., 0.), Vec3::new(0., -1., 0.));
+    let si = c.intersect_ray(&r).unwrap();
+    assert!((si.it.t - 4.).abs() < 1e-5);
+    assert!((si.normal - Vec3::new(0., 1., 0.)).magn() < 1e-5);
+    // Back half is cut away, so the ray passes through to the inside of the front half
+    let r = Ray3::new(Vec3::new(0., -5., 0.), Vec3::new(0., 1., 0.));
+    let si = c.intersect_ray(&r).unwrap();
+    assert!((si.it.t - 6.).abs() < 1e-5);
+  }
+  #[test]
+  fn annulus_hole() {
+    let d = Disk::new(0., 2., 1., 2. * PI).unwrap();
+    let down = Vec3::new(0., 0., -1.);
+    assert!(d
+      .intersect_ray(&Ray3::new(Vec3::new(0., 0., 1.), down))
+      .is_none());
+    assert!(d
+      .intersect_ray(&Ray3::new(Vec3::new(1.5, 0., 1.), down))
+      .is_some());
+  }
+  #[test]
+  fn invalid_parameters() {
+    let err = Cylinder::new(-1., 0., 1., PI).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "Cylinder must have a positive radius, not -1"
+    );
+    assert!(Cylinder::new(f32::NAN, 0., 1., PI).is_err());
+    assert!(Disk::new(0., 1., 2., PI).is_err());
+    assert!(Cone::new(1., 0., PI).is_err());
+    assert!(Paraboloid::new(1., 0., -1., PI).is_err());
+    assert!(Hyperboloid::new(0., 1., -1., 1., PI).is_err());
+    // uvs are spread over the height, which must not be empty
+    assert!(Cylinder::new(1., 1., 1., PI).is_err());
+    assert!(Cylinder::new(1., 1., 0., PI).is_err());
+    assert!(Paraboloid::new(1., 2., 1., PI).is_err());
+    assert!(Hyperboloid::new(1., 1., 0., 0., PI).is_err());
+    assert!(Hyperboloid::new(1., 1., 0., f32::NAN, PI).is_err());
+  }
+}
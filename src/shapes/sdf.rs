@@ -0,0 +1,280 @@
+//! Shapes defined by signed distance functions, which are intersected by sphere tracing.
+use super::Shape;
+use crate::{
+  bounds::{Bounded, Bounds3},
+  interaction::{Interaction, SurfaceInteraction},
+  scene::SceneError,
+};
+use quick_maths::{Ray3, Vec2, Vec3, Vector};
+
+/// Maximum number of steps taken along a ray before giving up
+const MAX_STEPS: usize = 512;
+/// Distance from the surface at which a ray is considered to have hit it
+const HIT_EPS: f32 = 1e-4;
+/// Fraction of the distance bound stepped each iteration, since smooth blends and twists are
+/// not exact distances.
+const STEP_SCALE: f32 = 0.8;
+/// Offset used to compute normals by finite differences
+const NORMAL_EPS: f32 = 1e-4;
+
+/// Tree of signed distance functions, where primitives are centered at the origin
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum Sdf {
+  Sphere {
+    radius: f32,
+  },
+  Box {
+    half_extents: Vec3,
+  },
+  /// Torus lying in the xz-plane
+  Torus {
+    major_radius: f32,
+    minor_radius: f32,
+  },
+  /// Line segment between two points thickened by some radius
+  Capsule {
+    a: Vec3,
+    b: Vec3,
+    radius: f32,
+  },
+  /// Infinite plane where normal . p = offset
+  Plane {
+    normal: Vec3,
+    offset: f32,
+  },
+
+  Translate(Vec3, Box<Sdf>),
+
+  Union(Vec<Sdf>),
+  Intersection(Vec<Sdf>),
+  /// Removes the second from the first
+  Difference(Box<Sdf>, Box<Sdf>),
+  /// Blended versions of the above, where k is roughly the size of the blend
+  SmoothUnion(f32, Box<Sdf>, Box<Sdf>),
+  SmoothIntersection(f32, Box<Sdf>, Box<Sdf>),
+  SmoothDifference(f32, Box<Sdf>, Box<Sdf>),
+
+  /// Infinitely repeats a shape along each axis with a nonzero period
+  Repeat(Vec3, Box<Sdf>),
+  /// Twists a shape around the y-axis by some radians per unit
+  Twist(f32, Box<Sdf>),
+}
+
+fn mix(a: f32, b: f32, t: f32) -> f32 { a + (b - a) * t }
+
+fn clamp01(v: f32) -> f32 { v.max(0.).min(1.) }
+
+impl Sdf {
+  /// Returns the signed distance from a point to this shape, negative if inside
+  pub fn distance(&self, p: &Vec3) -> f32 {
+    use Sdf::*;
+    // https://iquilezles.org/www/articles/distfunctions/distfunctions.htm
+    match self {
+      Sphere { radius } => p.magn() - radius,
+      Box { half_extents } => {
+        let q = p.abs() - *half_extents;
+        q.max(0.).magn() + q.x().max(q.y()).max(q.z()).min(0.)
+      },
+      Torus {
+        major_radius,
+        minor_radius,
+      } => {
+        let xz = Vec2::new(p.x(), p.z()).magn() - major_radius;
+        Vec2::new(xz, p.y()).magn() - minor_radius
+      },
+      Capsule { a, b, radius } => {
+        let (pa, ba) = (*p - *a, *b - *a);
+        let h = clamp01(pa.dot(&ba) / ba.sqr_magn());
+        (pa - ba * h).magn() - radius
+      },
+      Plane { normal, offset } => p.dot(&normal.norm()) - offset,
+      Translate(by, s) => s.distance(&(*p - *by)),
+      Union(ss) => ss
+        .iter()
+        .map(|s| s.distance(p))
+        .fold(f32::INFINITY, f32::min),
+      Intersection(ss) => ss
+        .iter()
+        .map(|s| s.distance(p))
+        .fold(f32::NEG_INFINITY, f32::max),
+      Difference(a, b) => a.distance(p).max(-b.distance(p)),
+      &SmoothUnion(k, ref a, ref b) => {
+        let (a, b) = (a.distance(p), b.distance(p));
+        let h = clamp01(0.5 + 0.5 * (b - a) / k);
+        mix(b, a, h) - k * h * (1. - h)
+      },
+      &SmoothIntersection(k, ref a, ref b) => {
+        let (a, b) = (a.distance(p), b.distance(p));
+        let h = clamp01(0.5 - 0.5 * (b - a) / k);
+        mix(b, a, h) + k * h * (1. - h)
+      },
+      &SmoothDifference(k, ref a, ref b) => {
+        let (a, b) = (a.distance(p), b.distance(p));
+        let h = clamp01(0.5 - 0.5 * (a + b) / k);
+        mix(a, -b, h) + k * h * (1. - h)
+      },
+      Repeat(period, s) => {
+        let q = Vector::with(|i| {
+          if period[i] == 0. {
+            p[i]
+          } else {
+            p[i] - period[i] * (p[i] / period[i]).round()
+          }
+        });
+        s.distance(&q)
+      },
+      &Twist(rate, ref s) => {
+        let (sin, cos) = (rate * p.y()).sin_cos();
+        s.distance(&Vec3::new(
+          cos * p.x() - sin * p.z(),
+          p.y(),
+          sin * p.x() + cos * p.z(),
+        ))
+      },
+    }
+  }
+  /// Returns the bounds of this shape, or none if it is unbounded
+  pub fn bounds(&self) -> Option<Bounds3> {
+    use Sdf::*;
+    let cube = |r: f32| Bounds3::new(Vec3::of(-r), Vec3::of(r));
+    let grow = |b: Bounds3, k: f32| Bounds3::new(b.min - k, b.max + k);
+    Some(match self {
+      Sphere { radius } => cube(*radius),
+      Box { half_extents } => Bounds3::new(-*half_extents, *half_extents),
+      Torus {
+        major_radius,
+        minor_radius,
+      } => {
+        let r = major_radius + minor_radius;
+        Bounds3::new(
+          Vec3::new(-r, -minor_radius, -r),
+          Vec3::new(r, *minor_radius, r),
+        )
+      },
+      Capsule { a, b, radius } => grow(Bounds3::valid(*a, *b), *radius),
+      Plane { .. } | Repeat(..) => return None,
+      Translate(by, s) => {
+        let b = s.bounds()?;
+        Bounds3::new(b.min + *by, b.max + *by)
+      },
+      Union(ss) => {
+        let mut bounds = ss.iter().map(|s| s.bounds());
+        let first = bounds.next()??;
+        bounds.try_fold(first, |acc, b| Some(acc.union(&b?)))?
+      },
+      Intersection(ss) => {
+        let mut bounds = ss.iter().filter_map(|s| s.bounds());
+        let first = bounds.next()?;
+        bounds.fold(first, |acc, b| {
+          let (_, min) = acc.min.sift(&b.min);
+          let (max, _) = acc.max.sift(&b.max);
+          Bounds3::new(min, max)
+        })
+      },
+      Difference(a, _) => a.bounds()?,
+      SmoothUnion(k, a, b) => grow(a.bounds()?.union(&b.bounds()?), *k),
+      SmoothIntersection(_, a, b) => match (a.bounds(), b.bounds()) {
+        (Some(a), _) | (None, Some(a)) => a,
+        (None, None) => return None,
+      },
+      SmoothDifference(_, a, _) => a.bounds()?,
+      &Twist(_, ref s) => {
+        // twisting about y keeps points within the same radius of the axis
+        let b = s.bounds()?;
+        let r = (b.min.x().abs().max(b.max.x().abs())).hypot(b.min.z().abs().max(b.max.z().abs()));
+        Bounds3::new(Vec3::new(-r, b.min.y(), -r), Vec3::new(r, b.max.y(), r))
+      },
+    })
+  }
+  /// Returns the normal of the field at a point by central differences
+  pub fn normal(&self, p: &Vec3) -> Vec3 {
+    let d = |i: usize| {
+      let mut off = Vec3::of(0.);
+      off[i] = NORMAL_EPS;
+      self.distance(&(*p + off)) - self.distance(&(*p - off))
+    };
+    Vec3::new(d(0), d(1), d(2)).norm()
+  }
+}
+
+/// A signed distance function clipped to some bounds
+#[derive(Debug, Clone, PartialEq)]
+pub struct SdfShape {
+  sdf: Sdf,
+  bounds: Bounds3,
+}
+
+impl SdfShape {
+  /// Creates a new SDF shape, which must be given bounds if the SDF is unbounded
+  pub fn new(sdf: Sdf, bounds: Option<Bounds3>) -> Result<Self, SceneError> {
+    let bounds = bounds
+      .or_else(|| sdf.bounds())
+      .ok_or_else(|| SceneError::invalid("Unbounded SDFs must be given explicit bounds"))?;
+    Ok(Self { sdf, bounds })
+  }
+}
+
+impl Shape for SdfShape {
+  fn intersect_ray(&self, r: &Ray3) -> Option<SurfaceInteraction> {
+    let (t_min, t_max) = self.bounds.ray_range(r)?;
+    let dir_len = r.dir.magn();
+    let dir = r.dir / dir_len;
+    // march in units of distance along the normalized direction
+    let (mut dist, end) = (t_min * dir_len, t_max * dir_len);
+    for _ in 0..MAX_STEPS {
+      if dist > end {
+        return None;
+      }
+      let p = r.pos + dir * dist;
+      let d = self.sdf.distance(&p);
+      if d.abs() < HIT_EPS {
+        return Some(SurfaceInteraction {
+          it: Interaction {
+            t: dist / dir_len,
+            p,
+          },
+          normal: self.sdf.normal(&p),
+          wi: dir,
+          material: 0,
+
+          // just a placeholder
+          uv: Vec2::new(0.0, 0.0),
+        });
+      }
+      dist += d.abs().max(HIT_EPS) * STEP_SCALE;
+    }
+    None
+  }
+}
+
+impl Bounded for SdfShape {
+  fn bounds(&self) -> Bounds3 { self.bounds }
+}
+
+#[cfg(test)]
+mod test_sdf {
+  use super::*;
+  use crate::shapes::sphere::Sphere;
+  #[test]
+  fn traced_sphere_matches_analytic() {
+    let traced = SdfShape::new(Sdf::Sphere { radius: 1. }, None).unwrap();
+    let analytic = Sphere::new(Vec3::of(0.), 1.);
+    let r = Ray3::new(Vec3::new(0.3, -0.2, -4.), Vec3::new(0., 0., 2.));
+    let (a, b) = (
+      traced.intersect_ray(&r).unwrap(),
+      analytic.intersect_ray(&r).unwrap(),
+    );
+    assert!((a.it.t - b.it.t).abs() < 1e-3);
+    assert!((a.normal - b.normal).magn() < 1e-2);
+  }
+  #[test]
+  fn unbounded_needs_bounds() {
+    let plane = || Sdf::Plane {
+      normal: Vec3::new(0., 1., 0.),
+      offset: 0.,
+    };
+    assert!(SdfShape::new(plane(), None).is_err());
+    let bounds = Bounds3::new(Vec3::of(-1.), Vec3::of(1.));
+    assert!(SdfShape::new(plane(), Some(bounds)).is_ok());
+  }
+}
@@ -1,4 +1,7 @@
-use super::Shape;
+use super::{
+  csg::{Crossing, Solid, Span},
+  Shape,
+};
 use crate::{
   bounds::{Bounded, Bounds3},
   interaction::{Interaction, SurfaceInteraction},
@@ -47,6 +50,7 @@ impl Shape for Sphere {
           it: Interaction { t, p },
           normal: self.normal(p),
           wi: r.dir.norm(),
+          material: 0,
 
           // just a placeholder
           uv: Vec2::new(0.0, 0.0),
@@ -55,6 +59,19 @@ impl Shape for Sphere {
   }
 }
 
+impl Solid for Sphere {
+  fn spans(&self, r: &Ray3) -> Vec<Span> {
+    let from_sphere = r.pos - self.center;
+    let b = 2.0 * r.dir.dot(&from_sphere);
+    let c = from_sphere.sqr_magn() - self.radius * self.radius;
+    let crossing = |t: f32| Crossing::new(t, self.normal(r.at(t)), Vec2::new(0.0, 0.0));
+    quad_solve(r.dir.sqr_magn(), b, c)
+      .map(|(t0, t1)| Span::new(r, crossing(t0.min(t1)), crossing(t0.max(t1))))
+      .into_iter()
+      .collect()
+  }
+}
+
 impl Bounded for Sphere {
   fn bounds(&self) -> Bounds3 {
     let min = self.center - self.radius;
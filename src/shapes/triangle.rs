@@ -52,6 +52,7 @@ impl Shape for Triangle {
       uv: Vec2::new(u, v),
       normal: self.normal().norm(),
       wi: r.dir,
+      material: 0,
     })
   }
 }
@@ -124,6 +125,11 @@ impl Triangle {
     }
   */
   pub fn area(&self) -> f32 { self.edge0().cross(&self.edge1()).magn() / 2.0 }
+  /// Whether this triangle has no area, including when its vertices are not finite
+  pub fn is_degenerate(&self) -> bool {
+    let area = self.area();
+    area.is_nan() || area <= 0.
+  }
   /// Returns two barycentric coordinates for a point, but performs no checks whether it is in
   /// bounds or not
   pub fn barycentric(&self, p: &Vec3) -> Vec2 {
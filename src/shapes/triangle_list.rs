@@ -1,11 +1,20 @@
-use super::{triangle::Triangle, Shape};
+use super::{
+  csg::{Crossing, Solid, Span},
+  triangle::Triangle,
+  Shape,
+};
 use crate::{
   bounds::{Bounded, Bounds3},
+  bsdf::mtl::{read_mtl, MTL},
   interaction::SurfaceInteraction,
+  mesh::{dcel::ObjMaterials, displace},
+  scene::SceneError,
   utils::triangulate,
 };
-use quick_maths::{Ray3, Vec3, Vector};
-use std::{fs::File, io, io::BufRead, path::Path, str::FromStr};
+use quick_maths::{Ray3, Vec2, Vec3, Vector};
+use std::{
+  collections::HashMap, fs::File, io, io::BufRead, mem::size_of, path::Path, str::FromStr,
+};
 
 /// A group of faces
 #[derive(Debug, Default)]
@@ -15,6 +24,8 @@ pub struct FaceGroup {
   // currently unused?
   normals: Vec<Vec3<u32>>,
   textures: Vec<Vec3<u32>>,
+  /// Index of this group's material in the mesh's materials, if it has one
+  material: Option<usize>,
 }
 
 impl FaceGroup {
@@ -31,6 +42,10 @@ pub struct IndexedTriangles {
   norms: Vec<Vec3>,
   /// list of textures
   textures: Vec<Vec3>,
+  /// per vertex colors, either empty or the same length as verts
+  colors: Vec<Vec3>,
+  /// materials loaded from the mtllibs of an OBJ
+  materials: Vec<MTL>,
 
   groups: Vec<FaceGroup>,
 }
@@ -40,6 +55,207 @@ impl PartialEq for IndexedTriangles {
 }
 
 impl IndexedTriangles {
+  /// Creates a single group of triangles with per vertex normals from indexed faces
+  pub fn new(src: String, verts: Vec<Vec3>, norms: Vec<Vec3>, faces: Vec<Vec3<u32>>) -> Self {
+    let group = FaceGroup {
+      name: src.clone(),
+      normals: if norms.is_empty() {
+        vec![]
+      } else {
+        faces.clone()
+      },
+      verts: faces,
+      textures: vec![],
+      material: None,
+    };
+    Self {
+      src,
+      verts,
+      norms,
+      textures: vec![],
+      colors: vec![],
+      materials: vec![],
+      groups: vec![group],
+    }
+  }
+  /// Sets the texture coordinates of each vertex of this mesh
+  pub fn with_uvs(mut self, uvs: Vec<Vec2>) -> Self {
+    self.textures = uvs
+      .into_iter()
+      .map(|uv| Vec3::new(uv.x(), uv.y(), 0.))
+      .collect();
+    for group in self.groups.iter_mut() {
+      group.textures = group.verts.clone();
+    }
+    self
+  }
+  /// Sets the color of each vertex of this mesh
+  pub fn with_colors(mut self, colors: Vec<Vec3>) -> Self {
+    assert!(colors.is_empty() || colors.len() == self.verts.len());
+    self.colors = colors;
+    self
+  }
+  /// Returns the color of each vertex, which is empty if the mesh has no colors
+  pub fn vertex_colors(&self) -> &[Vec3] { &self.colors }
+  /// Loads the materials of the OBJ this mesh was made from, and splits it into groups of
+  /// faces using the same material. Each face's group is as given by `DCEL::from_obj`.
+  pub fn with_obj_materials(
+    mut self,
+    obj_materials: &ObjMaterials,
+    face_groups: &[usize],
+  ) -> Result<Self, SceneError> {
+    let p = Path::new(&self.src).to_path_buf();
+    for lib in &obj_materials.libs {
+      read_mtllib(&p, lib, &mut self.materials)?;
+    }
+    let of_group = obj_materials
+      .names
+      .iter()
+      .map(|name| {
+        let material = self.materials.iter().position(|m| m.name == *name);
+        if material.is_none() {
+          eprintln!("Unknown material {} in {}", name, self.src);
+        }
+        material
+      })
+      .collect::<Vec<_>>();
+    // faces from the half-edge mesh have no uvs, so textures would all sample one texel
+    let mut used = of_group.iter().flatten().map(|&i| &self.materials[i]);
+    if let Some(m) = used.find(|m| m.map_d.is_some()) {
+      return Err(SceneError::invalid(format!(
+        "{} has no uvs after subdivision, which material {} needs for its textures",
+        self.src, m.name
+      )));
+    }
+    let has_normals = !self.norms.is_empty();
+    let faces = std::mem::take(&mut self.groups)
+      .into_iter()
+      .flat_map(|g| g.verts);
+    for (face, &group) in faces.zip(face_groups) {
+      let material = group.checked_sub(1).and_then(|g| of_group[g]);
+      if self.groups.last().map_or(true, |g| g.material != material) {
+        self.groups.push(FaceGroup {
+          name: self.src.clone(),
+          material,
+          ..FaceGroup::new()
+        });
+      }
+      let curr = self.groups.last_mut().unwrap();
+      curr.verts.push(face);
+      if has_normals {
+        curr.normals.push(face);
+      }
+    }
+    Ok(self)
+  }
+  /// Removes the materials of this mesh, which primitives refer to by their index plus 1
+  pub fn take_materials(&mut self) -> Vec<MTL> { std::mem::take(&mut self.materials) }
+  /// Returns this mesh with a single index per vertex which shares its position, normal and
+  /// texture coordinate. Missing normals are smoothed from the faces, and missing texture
+  /// coordinates are zero.
+  pub fn unified(&self) -> (Vec<displace::Vertex>, Vec<Vec3<u32>>) {
+    let faces = self
+      .groups
+      .iter()
+      .flat_map(|g| g.verts.iter().copied())
+      .collect::<Vec<_>>();
+    let smooth = displace::vertex_normals(&self.verts, &faces);
+    let mut idxs = HashMap::new();
+    let mut verts = vec![];
+    let mut tris = vec![];
+    for group in &self.groups {
+      for (i, face) in group.verts.iter().enumerate() {
+        let mut tri = [0; 3];
+        for (j, slot) in tri.iter_mut().enumerate() {
+          let vt = group.textures.get(i).map(|t| t[j]);
+          let vn = group
+            .normals
+            .get(i)
+            .filter(|_| !self.norms.is_empty())
+            .map(|n| n[j]);
+          let key = (face[j], vt, vn);
+          *slot = *idxs.entry(key).or_insert_with(|| {
+            verts.push(displace::Vertex {
+              pos: self.verts[face[j] as usize],
+              normal: vn.map_or(smooth[face[j] as usize], |n| self.norms[n as usize]),
+              uv: vt.map_or(Vec2::of(0.), |t| {
+                let t = self.textures[t as usize];
+                Vec2::new(t.x(), t.y())
+              }),
+            });
+            verts.len() as u32 - 1
+          });
+        }
+        tris.push(Vector(tri));
+      }
+    }
+    (verts, tris)
+  }
+  /// Checks that this mesh has triangles and that each of their indices refers to an existing
+  /// vertex, normal and texture coordinate.
+  pub fn validate(&self) -> Result<(), SceneError> {
+    if self.groups.iter().all(FaceGroup::is_empty) {
+      return Err(SceneError::invalid(format!(
+        "Mesh {} has no triangles",
+        self.src
+      )));
+    }
+    for g in &self.groups {
+      for (kind, faces, len) in &[
+        ("vertex", &g.verts, self.verts.len()),
+        ("normal", &g.normals, self.norms.len()),
+        ("texture", &g.textures, self.textures.len()),
+      ] {
+        let out_of_range = faces
+          .iter()
+          .flat_map(|f| f.0.iter())
+          .find(|&&i| i as usize >= *len);
+        if let Some(i) = out_of_range {
+          let msg = format!("Mesh {} has {} index {} out of range", self.src, kind, i);
+          return Err(SceneError::invalid(msg));
+        }
+      }
+      // faces are matched to their normals and uvs by position
+      for (kind, faces) in &[("normals", &g.normals), ("uvs", &g.textures)] {
+        if !faces.is_empty() && faces.len() != g.verts.len() {
+          let msg = format!(
+            "Mesh {} has {} for only {} of {} faces in group {}",
+            self.src,
+            kind,
+            faces.len(),
+            g.verts.len(),
+            g.name
+          );
+          return Err(SceneError::invalid(msg));
+        }
+      }
+    }
+    Ok(())
+  }
+  /// Number of triangles with no area, which can never be hit
+  pub fn degenerate(&self) -> usize { self.iter().filter(Triangle::is_degenerate).count() }
+  /// Approximate number of bytes used by the vertices and indices of this mesh
+  pub fn memory(&self) -> usize {
+    let attrs = self.verts.len() + self.norms.len() + self.textures.len() + self.colors.len();
+    let idxs = self
+      .groups
+      .iter()
+      .map(|g| g.verts.len() + g.normals.len() + g.textures.len())
+      .sum::<usize>();
+    attrs * size_of::<Vec3>() + idxs * size_of::<Vec3<u32>>()
+  }
+  /// Whether every edge of this mesh is shared by exactly two triangles, so that it encloses a
+  /// volume
+  pub fn is_closed(&self) -> bool {
+    let mut edges = HashMap::new();
+    for face in self.groups.iter().flat_map(|g| g.verts.iter()) {
+      for j in 0..3 {
+        let (a, b) = (face[j], face[(j + 1) % 3]);
+        *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+      }
+    }
+    !edges.is_empty() && edges.values().all(|&n| n == 2)
+  }
   pub fn len(&self) -> usize { self.groups.iter().map(|fg| fg.verts.len()).sum() }
   pub fn is_empty(&self) -> bool { self.groups.is_empty() }
   pub fn iter(&self) -> impl Iterator<Item = Triangle> + '_ {
@@ -56,11 +272,52 @@ impl IndexedTriangles {
 impl Shape for IndexedTriangles {
   fn intersect_ray(&self, r: &Ray3) -> Option<SurfaceInteraction> {
     self
+      .groups
       .iter()
-      .filter_map(|t| t.intersect_ray(r))
+      .flat_map(|g| g.verts.iter().enumerate().map(move |(i, f)| (g, i, f)))
+      .filter_map(|(g, i, f)| {
+        let mut si = Triangle(f.apply_fn(|v| self.verts[v as usize])).intersect_ray(r)?;
+        // barycentric weights of each vertex
+        let Vector([u, v]) = si.uv;
+        let w = [1. - u - v, u, v];
+        if let Some(t) = g.textures.get(i).filter(|_| !self.textures.is_empty()) {
+          let uvw = (0..3).fold(Vec3::of(0.), |acc, j| {
+            acc + self.textures[t[j] as usize] * w[j]
+          });
+          si.uv = Vec2::new(uvw.x(), uvw.y());
+        }
+        if let Some(n) = g.normals.get(i).filter(|_| !self.norms.is_empty()) {
+          let n = (0..3).fold(Vec3::of(0.), |acc, j| {
+            acc + self.norms[n[j] as usize] * w[j]
+          });
+          if n.sqr_magn() > 0. {
+            si.normal = n.norm();
+          }
+        }
+        si.material = g.material.map_or(0, |m| m + 1);
+        Some(si)
+      })
       .min_by(|a, b| a.it.closer(&b.it))
   }
 }
+/// Treats this mesh as closed, so crossings along a ray alternate between entering and exiting.
+/// CSG checks that meshes are closed before using them.
+impl Solid for IndexedTriangles {
+  fn spans(&self, r: &Ray3) -> Vec<Span> {
+    let mut crossings = self
+      .iter()
+      .filter_map(|t| t.intersect_ray(r))
+      .filter(|si| !si.it.t.is_nan())
+      .map(|si| Crossing::new(si.it.t, si.normal, si.uv))
+      .collect::<Vec<_>>();
+    crossings.sort_by(|a, b| a.t.total_cmp(&b.t));
+    crossings
+      .chunks_exact(2)
+      .map(|c| Span::new(r, c[0], c[1]))
+      .collect()
+  }
+}
+
 impl Bounded for IndexedTriangles {
   fn bounds(&self) -> Bounds3 {
     let mut verts = self.verts.iter();
@@ -76,58 +333,193 @@ where
   <D as FromStr>::Err: std::fmt::Debug, {
   let mut items = s.split('/');
   let v = items.next().unwrap().parse::<D>()?;
-  let vt = items.next().map(|vt| vt.parse()).transpose()?;
+  // texture coordinates may be left out as in 1//2
+  let vt = items
+    .next()
+    .filter(|vt| !vt.is_empty())
+    .map(|vt| vt.parse())
+    .transpose()?;
   let vn = items.next().map(|vn| vn.parse()).transpose()?;
   Ok((v, vt, vn))
 }
 
-pub fn from_ascii_stl(p: impl AsRef<Path>) -> io::Result<IndexedTriangles> {
-  let f = File::open(p.as_ref())?;
-  let buf = io::BufReader::new(f);
-  let mut triangle_list = IndexedTriangles::default();
-  let mut count = 0;
-  let mut starting_index = 0;
-  let mut curr_group = FaceGroup::new();
-  for line in buf.lines() {
-    let line = line?;
-    let parts = line.split_whitespace().collect::<Vec<_>>();
+/// Merges vertices of triangles which are at exactly the same position
+fn weld(src: String, tris: impl IntoIterator<Item = [Vec3; 3]>) -> IndexedTriangles {
+  let mut idxs = HashMap::new();
+  let mut verts = vec![];
+  let faces = tris
+    .into_iter()
+    .map(|tri| {
+      let mut face = [0; 3];
+      for (slot, v) in face.iter_mut().zip(tri.iter()) {
+        // adding zero makes -0 and 0 the same position
+        let key = (
+          (v[0] + 0.).to_bits(),
+          (v[1] + 0.).to_bits(),
+          (v[2] + 0.).to_bits(),
+        );
+        *slot = *idxs.entry(key).or_insert_with(|| {
+          verts.push(*v);
+          verts.len() as u32 - 1
+        });
+      }
+      Vector(face)
+    })
+    .collect();
+  IndexedTriangles::new(src, verts, vec![], faces)
+}
+
+/// Size of the header of binary STL files, before the number of triangles
+const STL_HEADER: usize = 80;
+/// Byte order mark some tools write at the start of UTF-8 text
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+/// Size of each triangle in a binary STL, 12 floats followed by 2 attribute bytes
+const STL_RECORD: usize = 50;
+
+/// Reads a little endian u32 starting at some byte
+fn u32_le(bytes: &[u8], i: usize) -> u32 {
+  let mut b = [0; 4];
+  b.copy_from_slice(&bytes[i..i + 4]);
+  u32::from_le_bytes(b)
+}
+
+/// Binary STLs may also start with "solid", so they are detected by whether the size of the file
+/// matches the number of triangles in the header. Otherwise they are ASCII if they start with
+/// "solid" after any byte order mark and whitespace.
+fn is_binary_stl(bytes: &[u8]) -> bool {
+  if bytes.len() >= STL_HEADER + 4 {
+    let n = u32_le(bytes, STL_HEADER) as usize;
+    let size = n
+      .checked_mul(STL_RECORD)
+      .and_then(|records| records.checked_add(STL_HEADER + 4));
+    if size == Some(bytes.len()) {
+      return true;
+    }
+  }
+  let text = bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes);
+  let space = text.iter().take_while(|b| b.is_ascii_whitespace()).count();
+  !text[space..].starts_with(b"solid")
+}
+
+/// Reads an STL file, detecting whether it is ASCII or binary
+pub fn from_stl(p: impl AsRef<Path>) -> Result<IndexedTriangles, SceneError> {
+  let p = p.as_ref();
+  let bytes = std::fs::read(p).map_err(|e| SceneError::io(p, e))?;
+  let src = p.to_string_lossy().into_owned();
+  if is_binary_stl(&bytes) {
+    parse_binary_stl(src, &bytes)
+  } else {
+    parse_ascii_stl(src, io::Cursor::new(bytes))
+  }
+}
+
+pub fn from_binary_stl(p: impl AsRef<Path>) -> Result<IndexedTriangles, SceneError> {
+  let p = p.as_ref();
+  let bytes = std::fs::read(p).map_err(|e| SceneError::io(p, e))?;
+  parse_binary_stl(p.to_string_lossy().into_owned(), &bytes)
+}
+
+fn parse_binary_stl(src: String, bytes: &[u8]) -> Result<IndexedTriangles, SceneError> {
+  let invalid = |msg: &str| SceneError::parse(&src, None, msg);
+  if bytes.len() < STL_HEADER + 4 {
+    return Err(invalid("Binary STL is missing its header"));
+  }
+  let n = u32_le(bytes, STL_HEADER) as usize;
+  if bytes.len() < STL_HEADER + 4 + n * STL_RECORD {
+    return Err(invalid(
+      "Binary STL has fewer triangles than its header states",
+    ));
+  }
+  let tris = (0..n).map(|t| {
+    // skip the facet normal, which is recomputed from the winding anyways
+    let start = STL_HEADER + 4 + t * STL_RECORD + 12;
+    let vert = |v: usize| Vec3::with(|i| f32::from_bits(u32_le(bytes, start + 12 * v + 4 * i)));
+    [vert(0), vert(1), vert(2)]
+  });
+  Ok(weld(src, tris))
+}
+
+pub fn from_ascii_stl(p: impl AsRef<Path>) -> Result<IndexedTriangles, SceneError> {
+  let p = p.as_ref();
+  let f = File::open(p).map_err(|e| SceneError::io(p, e))?;
+  parse_ascii_stl(p.to_string_lossy().into_owned(), io::BufReader::new(f))
+}
+
+fn parse_ascii_stl(src: String, buf: impl BufRead) -> Result<IndexedTriangles, SceneError> {
+  let mut tris = vec![];
+  let mut curr = vec![];
+  let mut name = String::new();
+  for (i, line) in buf.lines().enumerate() {
+    let line = line.map_err(|e| SceneError::io(&src, e))?;
+    let invalid = |msg: &str| SceneError::parse(&src, Some(i + 1), msg);
+    let parts = line
+      .trim_start_matches('\u{feff}')
+      .split_whitespace()
+      .collect::<Vec<_>>();
     match parts.as_slice() {
       [] | [""] => (),
-      ["solid", name] => curr_group.name = name.to_string(),
+      ["solid"] => (),
+      ["solid", n, ..] => name = n.to_string(),
       ["outer", "loop"] => (),
       ["endloop"] => {
-        assert_eq!(count, 3, "Expect only 3 vertices in STL file");
-        let indeces = Vec3::new(starting_index, starting_index + 1, starting_index + 2);
-        curr_group.verts.push(indeces);
-        starting_index = triangle_list.verts.len() as u32;
-        count = 0;
+        if curr.len() != 3 {
+          return Err(invalid(&format!(
+            "Expected 3 vertices per facet but got {}",
+            curr.len()
+          )));
+        }
+        tris.push([curr[0], curr[1], curr[2]]);
+        curr.clear();
       },
-      ["endfacet"] => assert_eq!(count, 0),
-      ["endsolid", name] => assert_eq!(name, &curr_group.name),
-      ["facet", "normal", n_i, n_j, n_k] => triangle_list
-        .norms
-        .push(Vec3::from_str_radix([n_i, n_j, n_k], 10).unwrap()),
+      ["endfacet"] if curr.is_empty() => (),
+      ["endfacet"] => return Err(invalid("Facet ended inside of its loop")),
+      ["endsolid"] => (),
+      ["endsolid", n, ..] if *n == name => (),
+      ["endsolid", n, ..] =>
+        return Err(invalid(&format!("Expected endsolid {}, not {}", name, n))),
+      ["facet", "normal", _, _, _] => (),
       ["vertex", v_i, v_j, v_k] => {
-        count += 1;
-        triangle_list
-          .verts
-          .push(Vec3::from_str_radix([v_i, v_j, v_k], 10).unwrap());
+        let v = Vec3::from_str_radix([v_i, v_j, v_k], 10).map_err(|_| invalid("Invalid vertex"))?;
+        curr.push(v)
       },
-      _ => panic!("Unknown line while parsing ASCII STL: {:?}", line),
+      _ => return Err(invalid(&format!("Unknown line {:?}", line))),
     };
   }
-  triangle_list.groups.push(curr_group);
-  Ok(triangle_list)
+  Ok(weld(src, tris))
 }
 
-pub fn from_ascii_obj(p: impl AsRef<Path>, load_mtls: bool) -> io::Result<IndexedTriangles> {
-  let f = File::open(p.as_ref())?;
+/// Reads a material library named by an OBJ, which is relative to it, and loads its textures
+fn read_mtllib(obj: &Path, mtl_file: &str, materials: &mut Vec<MTL>) -> Result<(), SceneError> {
+  let dir = obj.parent().unwrap_or_else(|| Path::new(""));
+  let mtl_file = dir.join(mtl_file);
+  let start = materials.len();
+  let f = File::open(&mtl_file).map_err(|e| SceneError::io(&mtl_file, e))?;
+  read_mtl(f, &mtl_file, materials)?;
+  let mtl_dir = mtl_file.parent().unwrap_or(dir);
+  for mtl in &mut materials[start..] {
+    if let Err(e) = mtl.load_textures(mtl_dir) {
+      eprintln!("Failed to load textures of material {}: {}", mtl.name, e);
+    }
+  }
+  Ok(())
+}
+
+pub fn from_ascii_obj(
+  p: impl AsRef<Path>,
+  load_mtls: bool,
+) -> Result<IndexedTriangles, SceneError> {
+  let p = p.as_ref();
+  let f = File::open(p).map_err(|e| SceneError::io(p, e))?;
   let buf = io::BufReader::new(f);
   let mut triangle_list = IndexedTriangles::default();
-  triangle_list.src = p.as_ref().to_string_lossy().into_owned();
+  triangle_list.src = p.to_string_lossy().into_owned();
   let mut curr_group = FaceGroup::new();
-  for line in buf.lines() {
-    let line = line?;
+  // OBJs from some tools have names and comments which are not UTF-8, so they are read lossily
+  for (i, line) in buf.split(b'\n').enumerate() {
+    let line = line.map_err(|e| SceneError::io(p, e))?;
+    let line = String::from_utf8_lossy(&line);
+    let invalid = |msg: &str| SceneError::parse(p, Some(i + 1), msg);
+    let vec3 = |x, y, z| Vec3::from_str_radix([x, y, z], 10).map_err(|_| invalid("Invalid vector"));
     // TODO convert this into not using collect as it allocates
     let parts = line
       .splitn(2, '#')
@@ -137,46 +529,82 @@ pub fn from_ascii_obj(p: impl AsRef<Path>, load_mtls: bool) -> io::Result<Indexe
       .collect::<Vec<_>>();
     match parts.as_slice() {
       [] | ["#", ..] => (),
-      ["g", name] =>
-        if curr_group.is_empty() && curr_group.name.is_empty() {
-          curr_group.name = name.to_string();
-        } else {
+      ["g", name] => {
+        if !curr_group.is_empty() || !curr_group.name.is_empty() {
+          // the material in use carries over into the new group
+          let material = curr_group.material;
           let done_group = std::mem::replace(&mut curr_group, FaceGroup::new());
           triangle_list.groups.push(done_group);
-          curr_group.name = name.to_string();
-        },
-      ["usemtl", _mat_name] =>
+          curr_group.material = material;
+        }
+        curr_group.name = name.to_string();
+      },
+      ["usemtl", name] =>
         if load_mtls {
-          todo!()
+          let material = triangle_list.materials.iter().position(|m| m.name == *name);
+          if material.is_none() {
+            eprintln!("Unknown material {} in {}", name, triangle_list.src);
+          }
+          if !curr_group.is_empty() {
+            let name = curr_group.name.clone();
+            let done_group = std::mem::replace(&mut curr_group, FaceGroup::new());
+            triangle_list.groups.push(done_group);
+            curr_group.name = name;
+          }
+          curr_group.material = material;
         },
-      ["mtllib", _mtl_files @ ..] =>
+      ["mtllib", mtl_files @ ..] =>
         if load_mtls {
-          todo!()
+          for mtl_file in mtl_files {
+            read_mtllib(p, mtl_file, &mut triangle_list.materials)?;
+          }
         },
-      ["v", x, y, z] => triangle_list
-        .verts
-        .push(Vec3::from_str_radix([x, y, z], 10).unwrap()),
-      ["v", x, y, z, _w] => triangle_list
-        .verts
-        .push(Vec3::from_str_radix([x, y, z], 10).unwrap()),
+      ["v", x, y, z] => triangle_list.verts.push(vec3(x, y, z)?),
+      ["v", x, y, z, _w] => triangle_list.verts.push(vec3(x, y, z)?),
       // Vertex normal
-      ["vn", i, j, k] => triangle_list
-        .norms
-        .push(Vec3::from_str_radix([i, j, k], 10).unwrap()),
+      ["vn", i, j, k] => triangle_list.norms.push(vec3(i, j, k)?),
       // Vertex Textures
-      ["vt", u, v, w] => triangle_list
-        .textures
-        .push(Vec3::from_str_radix([u, v, w], 10).unwrap()),
+      ["vt", u, v, w] => triangle_list.textures.push(vec3(u, v, w)?),
+      ["vt", u, v] => triangle_list.textures.push(vec3(u, v, &"0")?),
       // Points
-      ["p", _vs @ ..] => todo!(),
+      ["p", ..] => return Err(SceneError::unsupported(p, Some(i + 1), "OBJ points")),
       // Faces
       ["f", fs @ ..] => {
-        assert!(
-          fs.len() >= 3,
-          "OBJ faces require at least 3 elements: {:?}",
-          line
-        );
-        let vert_indeces = fs.iter().map(|f| parse_slashed::<u32>(f).unwrap());
+        if fs.len() < 3 {
+          return Err(invalid("OBJ faces require at least 3 vertices"));
+        }
+        let vert_indeces = fs
+          .iter()
+          .map(|f| parse_slashed::<u32>(f).map_err(|_| invalid("Invalid face index")))
+          .collect::<Result<Vec<_>, _>>()?;
+        // indices start at 1 and refer to what was defined before the face
+        let check = |kind: &str, idx: Option<u32>, len: usize| match idx {
+          Some(idx) if idx == 0 || idx as usize > len => Err(invalid(&format!(
+            "OBJ {} index {} is out of range",
+            kind, idx
+          ))),
+          _ => Ok(()),
+        };
+        for &(v, vt, vn) in &vert_indeces {
+          check("vertex", Some(v), triangle_list.verts.len())?;
+          check("texture", vt, triangle_list.textures.len())?;
+          check("normal", vn, triangle_list.norms.len())?;
+        }
+        // uvs and normals are looked up by face, so a group's faces either all have them or
+        // none do
+        let (has_vt, has_vn) = (vert_indeces[0].1.is_some(), vert_indeces[0].2.is_some());
+        let differs =
+          curr_group.textures.is_empty() == has_vt || curr_group.normals.is_empty() == has_vn;
+        if !curr_group.is_empty() && differs {
+          let next = FaceGroup {
+            name: curr_group.name.clone(),
+            material: curr_group.material,
+            ..FaceGroup::new()
+          };
+          triangle_list
+            .groups
+            .push(std::mem::replace(&mut curr_group, next));
+        }
         for v in triangulate(vert_indeces) {
           let Vector([vs0, vs1, vs2]) = v;
           let (v0, vt0, vn0) = vs0;
@@ -186,27 +614,31 @@ pub fn from_ascii_obj(p: impl AsRef<Path>, load_mtls: bool) -> io::Result<Indexe
           match (vt0, vt1, vt2) {
             (None, None, None) => (),
             (Some(vt0), Some(vt1), Some(vt2)) => {
-              curr_group.verts.push(Vec3::new(vt0, vt1, vt2) - 1);
+              curr_group.textures.push(Vec3::new(vt0, vt1, vt2) - 1);
             },
-            _ => panic!(
-              "Partially specified some texture indeces but not all in OBJ file {}",
-              line
-            ),
+            _ =>
+              return Err(invalid(
+                "Only some vertices of the face have texture indices",
+              )),
           };
           match (vn0, vn1, vn2) {
             (None, None, None) => (),
             (Some(vn0), Some(vn1), Some(vn2)) => {
-              curr_group.verts.push(Vec3::new(vn0, vn1, vn2) - 1);
+              curr_group.normals.push(Vec3::new(vn0, vn1, vn2) - 1);
             },
-            _ => panic!(
-              "Partially specified some texture indeces but not all in OBJ file {}",
-              line
-            ),
+            _ =>
+              return Err(invalid(
+                "Only some vertices of the face have normal indices",
+              )),
           };
         }
       },
-      ["s", "off"] => (),
-      l => panic!("Unexpected {:?}", l),
+      // smoothing groups and object names are not used
+      ["s", ..] | ["o", ..] => (),
+      [cmd, ..] => {
+        let context = format!("OBJ command {}", cmd);
+        return Err(SceneError::unsupported(p, Some(i + 1), context));
+      },
     };
   }
   if !curr_group.is_empty() {
@@ -215,6 +647,35 @@ pub fn from_ascii_obj(p: impl AsRef<Path>, load_mtls: bool) -> io::Result<Indexe
   Ok(triangle_list)
 }
 
+#[test]
+fn test_obj_mixed_uvs() {
+  let p = std::env::temp_dir().join("mireba_test_mixed_uvs.obj");
+  std::fs::write(
+    &p,
+    "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 2 0 0\nv 2 1 0\nvt 0 0\nvt 1 0\nvt 1 1\n\
+     f 1/1 2/2 3/3\nf 1 3 4\nf 2/1 5/2 6/3\n",
+  )
+  .unwrap();
+  let mesh = from_ascii_obj(&p, false).expect("Failed to load obj");
+  std::fs::remove_file(&p).unwrap();
+  // the face without uvs is split into its own group
+  assert_eq!(mesh.groups.len(), 3);
+  assert!(mesh.validate().is_ok());
+  let r = Ray3::new(Vec3::new(1.9, 0.5, 1.), Vec3::new(0., 0., -1.));
+  let si = mesh.intersect_ray(&r).expect("Missed the last face");
+  assert!((si.uv - Vec2::new(0.9, 0.5)).magn() < 1e-4, "{:?}", si.uv);
+
+  let mut mixed = IndexedTriangles::new(
+    String::from("mixed"),
+    vec![Vec3::of(0.); 3],
+    vec![],
+    vec![Vec3::new(0, 1, 2); 2],
+  )
+  .with_uvs(vec![Vec2::of(0.); 3]);
+  mixed.groups[0].textures.pop();
+  assert!(mixed.validate().is_err());
+}
+
 #[test]
 fn test_from_ascii_stl() {
   let p = Path::new(file!())
@@ -228,6 +689,30 @@ fn test_from_ascii_stl() {
   from_ascii_stl(p).expect("Failed to parse stl file");
 }
 
+#[test]
+fn test_binary_stl_welds() {
+  let tris = [[0., 0., 0., 1., 0., 0., 0., 1., 0.], [
+    1., 0., 0., 1., 1., 0., 0., 1., 0.,
+  ]];
+  let mut bytes = vec![0; STL_HEADER];
+  bytes.extend_from_slice(&(tris.len() as u32).to_le_bytes());
+  for t in &tris {
+    bytes.extend_from_slice(&[0; 12]);
+    for c in t {
+      bytes.extend_from_slice(&(*c as f32).to_bits().to_le_bytes());
+    }
+    bytes.extend_from_slice(&[0; 2]);
+  }
+  assert!(is_binary_stl(&bytes));
+  // an ASCII STL with a byte order mark and leading whitespace
+  assert!(!is_binary_stl(b"\xEF\xBB\xBF  \nsolid a\nendsolid a\n"));
+  let bom = "\u{feff}solid a\nendsolid a\n";
+  assert!(parse_ascii_stl(String::from("a.stl"), io::Cursor::new(bom)).is_ok());
+  let mesh = parse_binary_stl(String::from("test"), &bytes).expect("Failed to parse binary stl");
+  assert_eq!(mesh.len(), 2);
+  assert_eq!(mesh.verts.len(), 4);
+}
+
 #[test]
 fn test_from_ascii_obj() {
   let p = Path::new(file!())
@@ -252,3 +737,138 @@ fn test_from_ascii_obj_complex() {
     .join("sponza.obj");
   assert!(from_ascii_obj(p, true).is_ok());
 }
+
+#[test]
+fn test_obj_materials() {
+  let dir = std::env::temp_dir();
+  std::fs::write(
+    dir.join("mireba_test_materials.mtl"),
+    "newmtl red\nKd 1 0 0\nmap_Kd red.png\nnewmtl blue\nKd 0 0 1\n",
+  )
+  .unwrap();
+  let p = dir.join("mireba_test_materials.obj");
+  std::fs::write(
+    &p,
+    "mtllib mireba_test_materials.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\nf 1 2 3\n\
+     usemtl blue\nf 2 4 3\ng other\nf 1 2 3\n",
+  )
+  .unwrap();
+  let mut mesh = from_ascii_obj(&p, true).expect("Failed to parse obj file");
+  let materials = mesh.take_materials();
+  assert_eq!(materials.len(), 2);
+  assert_eq!(materials[1].name, "blue");
+  assert_eq!(mesh.groups.len(), 3);
+  assert_eq!(mesh.groups[0].material, None);
+  assert_eq!(mesh.groups[1].material, Some(1));
+  // a new group keeps using the last material
+  assert_eq!(mesh.groups[2].material, Some(1));
+  let r = Ray3::new(Vec3::new(0.75, 0.75, -1.), Vec3::new(0., 0., 1.));
+  assert_eq!(mesh.intersect_ray(&r).unwrap().material, 2);
+}
+
+#[test]
+fn test_obj_face_indices() {
+  let p = std::env::temp_dir().join("mireba_test_face_indices.obj");
+  let obj = |faces: &str| {
+    let src = format!(
+      "o tri\ns 1\nv 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\n{}\n",
+      faces
+    );
+    std::fs::write(&p, src).unwrap();
+    from_ascii_obj(&p, false).map(|m| m.len())
+  };
+  assert_eq!(obj("f 1 2 3").unwrap(), 1);
+  assert_eq!(obj("f 1//1 2//1 3//1").unwrap(), 1);
+  for (faces, expected) in &[
+    ("f 0 1 2", "vertex index 0"),
+    ("f 1 2 4", "vertex index 4"),
+    ("f 1//2 2//1 3//1", "normal index 2"),
+    ("f 1/1 2/1 3/1", "texture index 1"),
+  ] {
+    let err = obj(faces).unwrap_err().to_string();
+    assert!(
+      err.ends_with(&format!(":7: OBJ {} is out of range", expected)),
+      "{}",
+      err
+    );
+  }
+  std::fs::remove_file(&p).unwrap();
+}
+
+#[test]
+fn test_ascii_stl_errors() {
+  let stl = "solid a\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nendloop\n";
+  let err = parse_ascii_stl(String::from("a.stl"), io::Cursor::new(stl)).unwrap_err();
+  assert_eq!(
+    err.to_string(),
+    "a.stl:6: Expected 3 vertices per facet but got 2"
+  );
+}
+
+#[test]
+fn test_subdivided_obj_materials() {
+  use super::builder::{Builder, Variant};
+  let dir = std::env::temp_dir();
+  std::fs::write(
+    dir.join("mireba_test_subdivided.mtl"),
+    "newmtl blue\nKd 0 0 1\n",
+  )
+  .unwrap();
+  let p = dir.join("mireba_test_subdivided.obj");
+  std::fs::write(
+    &p,
+    "mtllib mireba_test_subdivided.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 2 0 0\nv 2 1 0\n\
+     f 1 2 3 4\nusemtl blue\nf 2 5 6 3\n",
+  )
+  .unwrap();
+  let builder = Builder {
+    to_world: crate::transform::Builder::Identity,
+    variant: Variant::Obj {
+      file: p.to_string_lossy().into_owned(),
+      use_mtls: Some(true),
+      subdivision_levels: Some(1),
+      subdivision: None,
+      crease_angle: None,
+      displacement: None,
+    },
+  };
+  let mesh = match builder
+    .build(None)
+    .expect("Failed to subdivide obj")
+    .variant
+  {
+    super::Variant::TriangleList(mesh) => mesh,
+    v => panic!("Expected a mesh but got {:?}", v),
+  };
+  assert_eq!(mesh.materials.len(), 1);
+  assert_eq!(mesh.groups.len(), 2);
+  assert_eq!(mesh.groups[0].material, None);
+  assert_eq!(mesh.groups[1].material, Some(0));
+  assert!(!mesh.norms.is_empty());
+
+  // textures can't be sampled without uvs
+  std::fs::write(
+    dir.join("mireba_test_subdivided.mtl"),
+    "newmtl blue\nKd 0 0 1\nmap_d mireba_test_missing.png\n",
+  )
+  .unwrap();
+  let builder = Builder {
+    to_world: crate::transform::Builder::Identity,
+    variant: Variant::Obj {
+      file: p.to_string_lossy().into_owned(),
+      use_mtls: Some(true),
+      subdivision_levels: Some(1),
+      subdivision: None,
+      crease_angle: None,
+      displacement: None,
+    },
+  };
+  match builder.build(None) {
+    Err(SceneError::Invalid { context }) =>
+      assert!(context.contains("material blue"), "{}", context),
+    r => panic!(
+      "Expected textures to be rejected but got {:?}",
+      r.map(|_| ())
+    ),
+  }
+}
@@ -1,31 +1,205 @@
-#[allow(unused_imports)]
-use quick_maths::{Vec3, Vector};
+use crate::polarized::{
+  nanometers_to_cie,
+  spd::{Spd, MAX_WAVELENGTH, MIN_WAVELENGTH},
+  stokes::Stokes,
+  CIE, CIE_TO_SRGB,
+};
+use quick_maths::{One, Vec3, Vector, Zero};
+use std::{
+  fmt::Debug,
+  ops::{Add, AddAssign, Div, Mul, Sub},
+};
 
 /// RGB is just an alias for vec3
 pub type RGB = Vec3;
 /// Luminance is just a simple float (maybe this will change later?)
 pub type Luminance = f32;
 
+/// Number of wavelength bins used in spectral mode
+pub const SPECTRAL_SAMPLES: usize = 16;
+/// Spectrum sampled in regular bins between MIN_WAVELENGTH and MAX_WAVELENGTH
+pub type Sampled = Vector<SPECTRAL_SAMPLES, f32>;
+
+/// Operations shared by every representation of light used while rendering
+pub trait Spectral:
+  Copy
+  + Debug
+  + PartialEq
+  + Zero
+  + One
+  + Add<Output = Self>
+  + AddAssign
+  + Add<f32, Output = Self>
+  + Sub<f32, Output = Self>
+  + Mul<Output = Self>
+  + Mul<f32, Output = Self>
+  + Div<f32, Output = Self> {
+  /// Converts from linear RGB
+  fn from_rgb(rgb: RGB) -> Self;
+  /// Converts to linear RGB
+  fn to_rgb(self) -> RGB;
+  /// Light with the same amount of energy in each channel
+  fn from_mono(l: Luminance) -> Self;
+  /// Converts a spectral power distribution, by default through RGB
+  fn from_spd(spd: &Spd) -> Self { Self::from_rgb(spd.to_rgb()) }
+  /// Returns the perceived brightness of this spectrum
+  fn luminance(self) -> Luminance;
+  /// Removes any negative energy from this spectrum
+  fn clamp_negative(self) -> Self;
+}
+
+/// Luminance of linear RGB
+fn rgb_luminance(rgb: RGB) -> Luminance {
+  let Vector([r, g, b]) = rgb;
+  // https://en.wikipedia.org/wiki/Relative_luminance
+  0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+impl Spectral for Luminance {
+  fn from_rgb(rgb: RGB) -> Self { rgb_luminance(rgb) }
+  fn to_rgb(self) -> RGB { Vector([self, self, self]) }
+  fn from_mono(l: Luminance) -> Self { l }
+  fn luminance(self) -> Luminance { self }
+  fn clamp_negative(self) -> Self { self.max(0.) }
+}
+
+impl Spectral for RGB {
+  fn from_rgb(rgb: RGB) -> Self { rgb }
+  fn to_rgb(self) -> RGB { self }
+  fn from_mono(l: Luminance) -> Self { Vector([l, l, l]) }
+  fn luminance(self) -> Luminance { rgb_luminance(self) }
+  fn clamp_negative(self) -> Self { self.max(0.) }
+}
+
+/// Stokes vectors only carry luminance, s0 is the intensity
+impl Spectral for Stokes {
+  fn from_rgb(rgb: RGB) -> Self { Self::from_mono(rgb_luminance(rgb)) }
+  fn to_rgb(self) -> RGB { Vector([self[0], self[0], self[0]]) }
+  fn from_mono(l: Luminance) -> Self { Vector([l, 0., 0., 0.]) }
+  fn luminance(self) -> Luminance { self[0] }
+  // The polarized components are signed so only the intensity is clamped
+  fn clamp_negative(self) -> Self {
+    if self[0] > 0. {
+      self
+    } else {
+      Self::zero()
+    }
+  }
+}
+
+/// Width of each bin in nanometers in spectral mode
+const BIN_WIDTH: f32 = (MAX_WAVELENGTH - MIN_WAVELENGTH) / SPECTRAL_SAMPLES as f32;
+/// How many times each bin is sampled when integrating
+const BIN_STEPS: usize = 5;
+
+/// Returns the center wavelength in nanometers of a spectral bin
+pub fn bin_center(i: usize) -> f32 { MIN_WAVELENGTH + BIN_WIDTH * (i as f32 + 0.5) }
+
+/// Returns the color matching functions integrated over a bin, normalized so that a constant
+/// spectrum of 1 has a luminance of 1.
+fn bin_cie(i: usize) -> CIE {
+  let step = BIN_WIDTH / BIN_STEPS as f32;
+  let integrate = |i: usize| {
+    let start = MIN_WAVELENGTH + BIN_WIDTH * i as f32;
+    (0..BIN_STEPS)
+      .map(|j| nanometers_to_cie(start + step * (j as f32 + 0.5)))
+      .fold(CIE::zero(), |acc, n| acc + n)
+  };
+  let y_sum: f32 = (0..SPECTRAL_SAMPLES).map(|i| integrate(i).y()).sum();
+  integrate(i) / y_sum
+}
+
+/// Box spectra for red, green and blue, chosen by the center of each bin
+fn rgb_basis(channel: usize) -> Sampled {
+  Vector::with(|i| {
+    let l = bin_center(i);
+    let c = if l < 490. {
+      2
+    } else if l < 580. {
+      1
+    } else {
+      0
+    };
+    if c == channel {
+      1.
+    } else {
+      0.
+    }
+  })
+}
+
+/// Inverts a 3x3 matrix given as columns
+fn inv3([a, b, c]: [Vec3; 3]) -> [Vec3; 3] {
+  let det = a.dot(&b.cross(&c));
+  // rows of the inverse are the cross products of the columns
+  let rows = [b.cross(&c) / det, c.cross(&a) / det, a.cross(&b) / det];
+  [
+    Vec3::new(rows[0][0], rows[1][0], rows[2][0]),
+    Vec3::new(rows[0][1], rows[1][1], rows[2][1]),
+    Vec3::new(rows[0][2], rows[1][2], rows[2][2]),
+  ]
+}
+
+impl Spectral for Sampled {
+  fn from_rgb(rgb: RGB) -> Self {
+    // Mix box spectra with weights picked so that converting back gives the same color
+    let basis = [rgb_basis(0), rgb_basis(1), rgb_basis(2)];
+    let [c0, c1, c2] = inv3([basis[0].to_rgb(), basis[1].to_rgb(), basis[2].to_rgb()]);
+    let Vector([r, g, b]) = c0 * rgb[0] + c1 * rgb[1] + c2 * rgb[2];
+    basis[0] * r + basis[1] * g + basis[2] * b
+  }
+  fn to_rgb(self) -> RGB {
+    let xyz = (0..SPECTRAL_SAMPLES).fold(CIE::zero(), |acc, i| acc + bin_cie(i) * self[i]);
+    CIE_TO_SRGB.dot(&xyz)
+  }
+  fn from_mono(l: Luminance) -> Self { Vector::of(l) }
+  fn from_spd(spd: &Spd) -> Self { Vector::with(|i| spd.eval(bin_center(i))) }
+  fn luminance(self) -> Luminance {
+    (0..SPECTRAL_SAMPLES)
+      .map(|i| bin_cie(i).y() * self[i])
+      .sum()
+  }
+  fn clamp_negative(self) -> Self { self.max(0.) }
+}
+
 cfg_if::cfg_if! {
   if #[cfg(feature="mono")] {
     /// Spectrum type is one channel luminance in mono
     pub type Spectrum = Luminance;
-    pub const fn to_rgb(s: Spectrum) -> RGB { Vector([s, s, s]) }
-    pub fn from_rgb(rgb: RGB) -> Spectrum {
-      let Vector([r, g, b]) = rgb;
-      // TODO not correct but... close enough
-      (r + g + b)/3.0
-      // wonder how geometic mean would look
-    }
-    pub const fn from_mono(l: Luminance) -> Spectrum { l }
   } else if #[cfg(feature="polarized")] {
-    todo!();
+    /// Spectrum type is a stokes vector of luminance when rendering polarization
+    pub type Spectrum = Stokes;
+  } else if #[cfg(feature="spectral")] {
+    /// Spectrum type is sampled wavelength bins in spectral mode
+    pub type Spectrum = Sampled;
   } else {
     /// Spectrum type is three channel RGB by default
     pub type Spectrum = RGB;
-    pub const fn to_rgb(s: Spectrum) -> RGB { s }
-    pub const fn from_rgb(rgb: RGB) -> Spectrum { rgb }
-    pub const fn from_mono(l: Luminance) -> Spectrum { Vector([l, l, l]) }
   }
-  // TODO add other spectrum types here
+}
+
+pub fn to_rgb(s: Spectrum) -> RGB { s.to_rgb() }
+pub fn from_rgb(rgb: RGB) -> Spectrum { Spectrum::from_rgb(rgb) }
+pub fn from_mono(l: Luminance) -> Spectrum { Spectrum::from_mono(l) }
+
+#[cfg(test)]
+mod test_spectrum {
+  use super::*;
+  fn round_trips<S: Spectral>(keeps_color: bool) {
+    let close = |a: RGB, b: RGB| assert!((a - b).magn() < 1e-3, "{:?} != {:?}", a, b);
+    let gray = Vec3::of(0.4);
+    close(S::from_rgb(gray).to_rgb(), gray);
+    let rgb = Vec3::new(0.2, 0.5, 0.8);
+    if keeps_color {
+      close(S::from_rgb(rgb).to_rgb(), rgb);
+    }
+    assert!((S::from_rgb(rgb).luminance() - rgb_luminance(rgb)).abs() < 1e-3);
+  }
+  #[test]
+  fn all_representations_round_trip() {
+    round_trips::<Luminance>(false);
+    round_trips::<RGB>(true);
+    round_trips::<Stokes>(false);
+    round_trips::<Sampled>(true);
+  }
 }
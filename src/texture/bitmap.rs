@@ -1,18 +1,53 @@
 use super::Texture;
 use crate::spectrum::{from_rgb, Spectrum};
-use image::{Rgb, RgbImage};
+use image::{DynamicImage, GrayImage, Luma, Rgb, RgbImage};
 use quick_maths::{Vec2, Vec3, Vector};
+use std::path::Path;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Bitmap {
   img: RgbImage,
+  /// Alpha channel of the image, if it had one
+  alpha: Option<GrayImage>,
+}
+
+impl Bitmap {
+  pub fn new(img: RgbImage) -> Self { Self { img, alpha: None } }
+  /// Keeps the alpha channel of an image if it has one
+  pub fn from_image(img: DynamicImage) -> Self {
+    let alpha = if img.color().has_alpha() {
+      let rgba = img.to_rgba();
+      Some(GrayImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        Luma([rgba.get_pixel(x, y)[3]])
+      }))
+    } else {
+      None
+    };
+    Self {
+      img: img.to_rgb(),
+      alpha,
+    }
+  }
+  pub fn from_file(p: impl AsRef<Path>) -> image::ImageResult<Self> {
+    Ok(Self::from_image(image::open(p)?))
+  }
+  fn pixel(&self, uv: Vec2) -> (u32, u32) {
+    let uv = uv * Vec2::new(self.img.width() as f32, self.img.height() as f32);
+    let Vector([u, v]) = uv.apply_fn(|v| v.max(0.) as u32);
+    (u.min(self.img.width() - 1), v.min(self.img.height() - 1))
+  }
+  /// Returns the alpha at some uv, or None if the image has no alpha channel
+  pub fn alpha(&self, uv: Vec2) -> Option<f32> {
+    let (u, v) = self.pixel(uv);
+    let alpha = self.alpha.as_ref()?;
+    Some(alpha.get_pixel(u, v)[0] as f32 / 255.0)
+  }
 }
 
 impl Texture for Bitmap {
   fn sample(&self, uv: Vec2) -> Spectrum {
-    let uv = uv * Vec2::new(self.img.width() as f32, self.img.height() as f32);
-    let Vector([u, v]) = uv.apply_fn(|v| v as u32);
-    let Rgb([r, g, b]) = self.img.get_pixel(u, v);
-    from_rgb(Vec3::new(r, g, b).apply_fn(|&v| v as f32) / 255.0)
+    let (u, v) = self.pixel(uv);
+    let &Rgb([r, g, b]) = self.img.get_pixel(u, v);
+    from_rgb(Vec3::new(r as f32, g as f32, b as f32) / 255.0)
   }
 }
@@ -0,0 +1,29 @@
+use super::bitmap::Bitmap;
+use crate::scene::SceneError;
+
+/// Where to load an image texture from
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub enum Builder {
+  File(String),
+  /// Image stored in a glTF file, which may be embedded in its buffers
+  Gltf {
+    file: String,
+    image: usize,
+  },
+}
+
+impl Builder {
+  /// File the image is read from
+  pub fn file(&self) -> &str {
+    match self {
+      Builder::File(file) | Builder::Gltf { file, .. } => file,
+    }
+  }
+  pub fn build(self) -> Result<Bitmap, SceneError> {
+    let img = match self {
+      Builder::File(src) => image::open(&src).map_err(|e| SceneError::image(src, e))?,
+      Builder::Gltf { file, image } => crate::scene::gltf::load_image(file, image)?,
+    };
+    Ok(Bitmap::from_image(img))
+  }
+}
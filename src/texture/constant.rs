@@ -7,6 +7,10 @@ pub struct Constant {
   s: Spectrum,
 }
 
+impl Constant {
+  pub fn new(s: Spectrum) -> Self { Self { s } }
+}
+
 impl Texture for Constant {
   fn sample(&self, _uv: Vec2) -> Spectrum { self.s }
 }
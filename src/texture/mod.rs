@@ -1,4 +1,5 @@
 pub mod bitmap;
+pub mod builder;
 pub mod constant;
 
 use crate::spectrum::Spectrum;
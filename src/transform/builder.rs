@@ -1,6 +1,6 @@
-use quick_maths::{Transform4, Vec3};
+use quick_maths::{Transform4, Vec3, Vector};
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub enum Builder {
   LookAt {
     origin: Vec3,
@@ -8,8 +8,15 @@ pub enum Builder {
     up: Vec3,
   },
   Scale(Vec3),
+  /// Rotation about an axis by some radians
   Rotate(Vec3, f32),
+  Translate(Vec3),
+  /// Product of transforms, so the last one is applied first
+  Compose(Vec<Builder>),
+  Inverse(Box<Builder>),
   Identity,
+  /// Column major matrix, which is used as is so it may also reflect or shear
+  Matrix([f32; 16]),
 }
 
 impl From<Builder> for Transform4 {
@@ -23,7 +30,27 @@ impl From<Builder> for Transform4 {
       } => Transform4::look_at(origin, towards, up),
       Scale(by) => Transform4::scale(by),
       Rotate(axis, theta) => Transform4::rot(axis, theta),
+      Translate(by) => Transform4::translate(by),
+      Compose(tfs) => tfs
+        .into_iter()
+        .fold(Transform4::identity(), |acc, tf| acc * Transform4::from(tf)),
+      Inverse(tf) => Transform4::from(*tf).inv(),
       Identity => Transform4::identity(),
+      Matrix(m) => Transform4::new(quick_maths::Matrix(Vector::with(|c| {
+        Vector::with(|r| m[4 * c + r])
+      }))),
     }
   }
 }
+
+impl Builder {
+  /// Rotation from a unit quaternion given as x, y, z, w
+  pub fn from_quat([x, y, z, w]: [f32; 4]) -> Self {
+    let w = w.max(-1.).min(1.);
+    let s = (1. - w * w).sqrt();
+    if s < 1e-6 {
+      return Builder::Identity;
+    }
+    Builder::Rotate(Vec3::new(x, y, z) / s, 2. * w.acos())
+  }
+}
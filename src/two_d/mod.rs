@@ -1,5 +1,6 @@
 pub mod lgram;
 pub mod scene;
+pub mod spline;
 pub mod turtle;
 
 use crate::{film::Film, spectrum::from_rgb};
@@ -41,6 +41,11 @@ pub fn cubic_spline<T: Float>(
 */
 
 impl<const M: usize> CubicSpline<M> {
+  pub fn new(ctrls: [Vector<M, f32>; 4]) -> Self {
+    Self {
+      ctrls: Matrix(Vector(ctrls)),
+    }
+  }
   pub fn components(&self) -> [Vec4; M] {
     let Matrix(Vector(v)) = self.ctrls.t();
     v
@@ -100,43 +105,24 @@ impl CubicSpline<3> {
   }
 }
 
-/*
-
-/// A cubic spline
+/// A cubic surface, as a tensor product of cubic splines
 #[derive(PartialEq, Clone, Copy, Debug)]
-pub struct CubicSurface<'a, D = f32, V = Vec3<D>> {
-  /// Control points for use in interpolation
-  ctrls: Matrix4<&'a V>,
-  _phantom: PhantomData<D>,
+pub struct CubicSurface {
+  /// Control points, where each row is a spline along u and rows go along v
+  ctrls: [[Vec3; 4]; 4],
 }
 
-impl<'a, D: Float> CubicSurface<'a, D, Vec3<D>> {
-  pub fn components(&self) -> [Matrix4<D>; 3] {
-    // Lol this is incredibly lazy and redundant but works
-    [
-      self.ctrls.apply_fn(|v| v.0),
-      self.ctrls.apply_fn(|v| v.1),
-      self.ctrls.apply_fn(|v| v.2),
-    ]
-  }
-  pub fn at<M>(&self, uv: (D, D), weights: &M) -> Vec3<D>
-  where
-    M: Matrix<Field = D, Vector = Vec4<D>>, {
-    let (u, v) = uv;
-    let us = cubic_comp(u.max(D::zero()).min(D::one()));
-    let vs = cubic_comp(v.max(D::zero()).min(D::one()));
-    let [xs, ys, zs] = self.components();
-    let partial = weights.t().vecmul(&vs);
-    Vec3(
-      weights.vecmul(&xs.vecmul(&partial)).dot(&us),
-      weights.vecmul(&ys.vecmul(&partial)).dot(&us),
-      weights.vecmul(&zs.vecmul(&partial)).dot(&us),
-    )
+impl CubicSurface {
+  pub fn new(ctrls: [[Vec3; 4]; 4]) -> Self { Self { ctrls } }
+  pub fn ctrls(&self) -> &[[Vec3; 4]; 4] { &self.ctrls }
+  pub fn at(&self, (u, v): (f32, f32), weights: &Mat4) -> Vec3 {
+    // Evaluate each row along u, then the spline through those points along v
+    let [r0, r1, r2, r3] = self.ctrls;
+    let row = |r: [Vec3; 4]| CubicSpline::new(r).at(u, weights);
+    CubicSpline::new([row(r0), row(r1), row(r2), row(r3)]).at(v, weights)
   }
 }
 
-*/
-
 pub const CUBIC_BEZIER_WEIGHTS: Mat4 = Matrix(Vector([
   Vector([-1., 3., -3., 1.]),
   Vector([3., -6., 3., 0.]),
@@ -0,0 +1,8 @@
+#!/bin/sh
+# Runs the tests under the default RGB spectrum and each of the spectrum features
+set -e
+
+cargo test "$@"
+for feature in mono polarized spectral; do
+  cargo test --features "$feature" "$@"
+done